@@ -0,0 +1,261 @@
+//! 不依赖 Tauri 运行时的解密接口
+//!
+//! 命令行工具和测试可以直接调用这些函数；对应的 Tauri 命令只负责读取应用状态、
+//! 解析密钥和转换错误。
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::decrypt::aes::AesHandler;
+use crate::decrypt::{DatDecryptor, V3Decryptor, DEFAULT_CHUNK_SIZE};
+use crate::throttle::{RateLimiter, ThrottledWriter};
+
+pub use crate::decrypt::{DatVersion, XorKey};
+pub use crate::error::AppError;
+
+/// 解密 DAT 文件，返回未经规范化的明文 (WXGF 不转换)
+pub fn decrypt_raw(
+    path: &Path,
+    xor_key: impl Into<XorKey>,
+    aes_key: Option<&[u8]>,
+) -> Result<Vec<u8>, AppError> {
+    Ok(DatDecryptor::decrypt(path, xor_key, aes_key)?)
+}
+
+/// 解密 DAT 文件并规范化，返回图片数据和 MIME 类型
+///
+/// WXGF 数据会尽量通过 DLL 转换为标准图片，无法转换时原样返回。
+pub fn decrypt_image(
+    path: &Path,
+    xor_key: impl Into<XorKey>,
+    aes_key: Option<&[u8]>,
+) -> Result<(Vec<u8>, String), AppError> {
+    let raw = decrypt_raw(path, xor_key, aes_key)?;
+    Ok(crate::normalize_decrypted_image(raw))
+}
+
+/// XOR 解密内存中的 v3 数据，大块数据会并行处理
+pub fn xor_decrypt(data: &[u8], xor_key: u8) -> Vec<u8> {
+    V3Decryptor::xor_decrypt(data, xor_key)
+}
+
+/// 检测 DAT 文件的加密版本
+pub fn detect_version(path: &Path) -> Result<DatVersion, AppError> {
+    Ok(DatDecryptor::detect_version(path)?)
+}
+
+/// 解析配置格式的 AES 密钥（文本或 32/64 位十六进制），长度不是 16 或 32 字节时返回 None
+pub fn parse_aes_key(aes: &str) -> Option<Vec<u8>> {
+    let key = crate::aes_key_from_str(aes);
+    AesHandler::is_valid_key_len(key.len()).then_some(key)
+}
+
+/// 读取 config.json 中的全局密钥 (XOR 密钥, AES 密钥字节)，配置文件不存在时返回 (0, 空)
+pub fn keys_from_config() -> Result<(u8, Vec<u8>), AppError> {
+    crate::read_key_from_config()
+}
+
+/// 列出目录中可识别的 DAT / Sns 文件，`recursive` 为 true 时遍历子目录
+///
+/// 使用与界面相同的遍历限制，不跟随符号链接；返回的路径按文件名排序。
+pub fn find_images(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = crate::collect_images(dir, dir, false, recursive)
+        .into_iter()
+        .map(|info| dir.join(info.path))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// 判断格式时解密的文件开头字节数
+const STREAM_HEAD_LEN: usize = 64;
+
+/// 可以边解密边输出的 DAT 文件，由 [`stream_source`] 创建
+pub struct StreamSource {
+    xor_key: XorKey,
+    /// 解密后的文件开头，用于判断格式
+    head: Vec<u8>,
+}
+
+impl StreamSource {
+    /// 按文件开头识别的 MIME 类型
+    pub fn mime_type(&self) -> String {
+        crate::detect_mime_type(&self.head)
+    }
+
+    /// 与格式对应的文件扩展名
+    pub fn extension(&self) -> &'static str {
+        crate::extension_for_mime(&self.head, &self.mime_type())
+    }
+}
+
+/// 只解密文件开头，判断能否边解密边输出
+///
+/// 内容为 WXGF (需要整体解密后转换) 或版本未知时返回 None。`xor_key` 的含义与
+/// [`DatDecryptor::decrypt`] 相同。
+pub fn stream_source(
+    path: &Path,
+    xor_key: impl Into<XorKey>,
+    aes_key: Option<&[u8]>,
+) -> Result<Option<StreamSource>, AppError> {
+    let xor_key = xor_key.into();
+    if detect_version(path)? == DatVersion::Unknown {
+        return Ok(None);
+    }
+    let head = DatDecryptor::decrypt_prefix(path, xor_key, aes_key, STREAM_HEAD_LEN)?;
+
+    // WXGF 需要完整数据交给 DLL 转换
+    if crate::media_kind(&head) == "wxgf" {
+        return Ok(None);
+    }
+
+    Ok(Some(StreamSource { xor_key, head }))
+}
+
+/// 按块解密 `source` 对应的文件并写入 `writer`，返回写入的字节数
+///
+/// 按 [`DEFAULT_CHUNK_SIZE`] 分块处理，不在内存中保留完整明文。
+pub fn stream_to_writer(
+    path: &Path,
+    source: &StreamSource,
+    aes_key: Option<&[u8]>,
+    writer: &mut dyn Write,
+) -> Result<u64, AppError> {
+    Ok(DatDecryptor::decrypt_to_writer(
+        path,
+        source.xor_key,
+        aes_key,
+        writer,
+        DEFAULT_CHUNK_SIZE,
+    )?)
+}
+
+/// 解密并规范化后写入 `output_path`，扩展名按检测到的格式修正，返回实际写入的路径
+///
+/// 内容不是 WXGF 时边解密边写入，不在内存中保留完整明文；WXGF 需要整体解密后转换。
+/// 先写入同目录下的临时文件，成功后再重命名，失败时不影响 `output_path` 处已有的文件。
+pub fn decrypt_to_file(
+    path: &Path,
+    xor_key: impl Into<XorKey>,
+    aes_key: Option<&[u8]>,
+    output_path: &Path,
+) -> Result<PathBuf, AppError> {
+    decrypt_to_file_limited(
+        path,
+        xor_key,
+        aes_key,
+        |ext| output_path.with_extension(ext),
+        None,
+    )
+}
+
+/// 与 [`decrypt_to_file`] 相同，输出路径由 `output_for` 根据检测到的扩展名生成；
+/// 提供 `limiter` 时按其速率限制写入
+pub(crate) fn decrypt_to_file_limited(
+    path: &Path,
+    xor_key: impl Into<XorKey>,
+    aes_key: Option<&[u8]>,
+    output_for: impl FnOnce(&'static str) -> PathBuf,
+    limiter: Option<&RateLimiter>,
+) -> Result<PathBuf, AppError> {
+    let xor_key = xor_key.into();
+    if let Some(source) = stream_source(path, xor_key, aes_key)? {
+        let output = output_for(source.extension());
+        crate::write_file_atomically(&output, |file| {
+            write_limited(file, limiter, |writer| {
+                stream_to_writer(path, &source, aes_key, writer).map(|_| ())
+            })
+        })?;
+        return Ok(output);
+    }
+
+    let (data, mime_type) = decrypt_image(path, xor_key, aes_key)?;
+
+    let output = output_for(crate::extension_for_mime(&data, &mime_type));
+    crate::write_file_atomically(&output, |file| {
+        write_limited(file, limiter, |writer| {
+            writer
+                .write_all(&data)
+                .map_err(|e| AppError::FileWriteError(format!("{}: {}", output.display(), e)))
+        })
+    })?;
+
+    Ok(output)
+}
+
+/// 经缓冲写入 `file`，提供 `limiter` 时限速
+fn write_limited(
+    file: &mut fs::File,
+    limiter: Option<&RateLimiter>,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    let mut out = BufWriter::new(file);
+    match limiter {
+        Some(limiter) => write(&mut ThrottledWriter::new(&mut out, limiter))?,
+        None => write(&mut out)?,
+    }
+    out.flush()
+        .map_err(|e| AppError::FileWriteError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_to_file_streams_v3() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut mp4 = b"\x00\x00\x00\x18ftypmp42".to_vec();
+        mp4.extend((0..200_000u32).map(|i| (i % 241) as u8));
+        let path = dir.path().join("video.dat");
+        fs::write(&path, V3Decryptor::xor_decrypt(&mp4, 0x21)).unwrap();
+
+        let written = decrypt_to_file(&path, 0x21, None, &dir.path().join("out")).unwrap();
+        assert_eq!(written, dir.path().join("out.mp4"));
+        assert_eq!(fs::read(&written).unwrap(), mp4);
+
+        // 要求推算密钥时与内存解密一样从文件头推算
+        let gif = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;".to_vec();
+        let path = dir.path().join("a.dat");
+        fs::write(&path, V3Decryptor::xor_decrypt(&gif, 0x33)).unwrap();
+        let written =
+            decrypt_to_file(&path, XorKey::recovering(0x21), None, &dir.path().join("a")).unwrap();
+        assert_eq!(written, dir.path().join("a.gif"));
+        assert_eq!(fs::read(&written).unwrap(), gif);
+    }
+
+    #[test]
+    fn test_decrypt_image_end_to_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let gif = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;".to_vec();
+        let path = dir.path().join("a.dat");
+        fs::write(&path, V3Decryptor::xor_decrypt(&gif, 0x21)).unwrap();
+
+        assert_eq!(detect_version(&path).unwrap(), DatVersion::V3);
+        let (data, mime_type) = decrypt_image(&path, 0x21, None).unwrap();
+        assert_eq!(data, gif);
+        assert_eq!(mime_type, "image/gif");
+
+        assert_eq!(
+            parse_aes_key("0123456789abcdef").unwrap(),
+            b"0123456789abcdef"
+        );
+        assert_eq!(parse_aes_key("short"), None);
+
+        assert!(decrypt_image(&dir.path().join("missing.dat"), 0x21, None).is_err());
+        assert_eq!(decrypt_raw(&path, 0x21, None).unwrap(), gif);
+        let message =
+            String::from(decrypt_raw(&dir.path().join("missing.dat"), 0x21, None).unwrap_err());
+        assert!(message.contains("missing.dat"));
+
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("b.dat"), b"x").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"x").unwrap();
+        assert_eq!(find_images(dir.path(), false), vec![path.clone()]);
+        assert_eq!(
+            find_images(dir.path(), true),
+            vec![path, dir.path().join("sub").join("b.dat")]
+        );
+    }
+}
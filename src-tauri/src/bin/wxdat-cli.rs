@@ -0,0 +1,195 @@
+//! 命令行批量解密工具
+//!
+//! 不启动界面，直接调用库的 `api` 模块解密单个文件或整个目录：
+//!
+//! ```text
+//! wxdat-cli --input <文件或目录> --output <目录> [--xor <密钥>] [--aes <密钥>] [--recursive]
+//! ```
+//!
+//! 未指定 `--xor` / `--aes` 时从当前目录的 config.json 读取；任一文件失败时以非零状态退出。
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use wxdatviewer_rusted_lib::api;
+
+const USAGE: &str = "用法: wxdat-cli --input <文件或目录> --output <目录> [--xor <密钥>] [--aes <密钥>] [--recursive]";
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    input: PathBuf,
+    output: PathBuf,
+    xor: Option<u8>,
+    aes: Option<String>,
+    recursive: bool,
+}
+
+/// 解析 XOR 密钥，支持十进制和 0x 开头的十六进制
+fn parse_xor(value: &str) -> Result<u8, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("无效的 XOR 密钥: {}", value))
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} 缺少参数值", arg));
+        match arg.as_str() {
+            "--input" => input = Some(PathBuf::from(value()?)),
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--xor" => options.xor = Some(parse_xor(&value()?)?),
+            "--aes" => options.aes = Some(value()?),
+            "--recursive" => options.recursive = true,
+            other => return Err(format!("未知参数: {}", other)),
+        }
+    }
+
+    options.input = input.ok_or("缺少 --input")?;
+    options.output = output.ok_or("缺少 --output")?;
+    Ok(options)
+}
+
+/// 命令行未指定的密钥从 config.json 补全
+fn resolve_keys(options: &Options) -> Result<(u8, Option<Vec<u8>>), String> {
+    let (config_xor, config_aes) = if options.xor.is_none() || options.aes.is_none() {
+        api::keys_from_config().map_err(String::from)?
+    } else {
+        (0, Vec::new())
+    };
+
+    let xor = options.xor.unwrap_or(config_xor);
+    let aes =
+        match &options.aes {
+            Some(aes) => Some(api::parse_aes_key(aes).ok_or_else(|| {
+                format!("AES 密钥必须为 16/32 字节文本或 32/64 位十六进制: {}", aes)
+            })?),
+            None => (!config_aes.is_empty()).then_some(config_aes),
+        };
+    Ok((xor, aes))
+}
+
+/// 输入文件对应的输出路径（不含扩展名），递归时保留相对输入目录的子目录结构
+fn output_stem(input_root: &Path, file: &Path, output_dir: &Path) -> PathBuf {
+    let relative = file
+        .strip_prefix(input_root)
+        .ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new(file.file_name().unwrap_or_default()));
+    output_dir.join(relative.with_extension(""))
+}
+
+fn run(options: &Options) -> Result<bool, String> {
+    let (xor, aes) = resolve_keys(options)?;
+
+    let (input_root, files) = if options.input.is_dir() {
+        (
+            options.input.clone(),
+            api::find_images(&options.input, options.recursive),
+        )
+    } else if options.input.is_file() {
+        let parent = options
+            .input
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        (parent, vec![options.input.clone()])
+    } else {
+        return Err(format!("输入不存在: {}", options.input.display()));
+    };
+
+    let mut failed = 0;
+    for file in &files {
+        let stem = output_stem(&input_root, file, &options.output);
+        let result = stem
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| api::AppError::FileWriteError(e.to_string()))
+            .and_then(|_| api::decrypt_to_file(file, xor, aes.as_deref(), &stem));
+
+        match result {
+            Ok(written) => println!("OK    {}  ->  {}", file.display(), written.display()),
+            Err(err) => {
+                failed += 1;
+                println!("FAIL  {}  {}", file.display(), err);
+            }
+        }
+    }
+
+    println!(
+        "共 {} 个文件，成功 {}，失败 {}",
+        files.len(),
+        files.len() - failed,
+        failed
+    );
+    Ok(failed == 0)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(&[
+            "--input",
+            "in",
+            "--output",
+            "out",
+            "--xor",
+            "0x56",
+            "--recursive",
+        ]))
+        .unwrap();
+        assert_eq!(options.input, PathBuf::from("in"));
+        assert_eq!(options.output, PathBuf::from("out"));
+        assert_eq!(options.xor, Some(0x56));
+        assert_eq!(options.aes, None);
+        assert!(options.recursive);
+
+        assert!(parse_args(args(&["--input", "in"])).is_err());
+        assert!(parse_args(args(&["--input", "in", "--output", "out", "--xor", "300"])).is_err());
+        assert!(parse_args(args(&["--bogus"])).is_err());
+
+        assert_eq!(
+            output_stem(
+                Path::new("in"),
+                Path::new("in/2024-01/a.dat"),
+                Path::new("out")
+            ),
+            PathBuf::from("out/2024-01/a")
+        );
+    }
+}
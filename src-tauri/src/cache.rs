@@ -0,0 +1,457 @@
+//! 图片缓存模块
+//!
+//! 缓存解密后的图片数据。默认保存在内存中；切换到磁盘后端后数据写入临时目录，
+//! 内存中只保留文件路径和 MIME 类型，适合内存较小的机器。
+//!
+//! 缓存本身放在全局锁中，锁内只查找或登记条目；磁盘文件的读写由调用方在释放锁之后
+//! 通过 [`CacheHit::load`] 和 [`PendingInsert::write`] 完成。
+//!
+//! 由图片生成的派生数据 (缩略图、胶片条、视频帧等) 以 `<图片 ID>#<用途>` 为键，
+//! 删除图片时一并删除；派生条目总数有上限，超出时先删除最早写入的条目。
+
+use crate::CachedImage;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+/// 缓存数据的存放位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheBackend {
+    #[default]
+    Memory,
+    Disk,
+}
+
+impl CacheBackend {
+    /// 从配置字符串解析，支持 "memory" 和 "disk"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "memory" => Some(Self::Memory),
+            "disk" => Some(Self::Disk),
+            _ => None,
+        }
+    }
+}
+
+/// 派生条目键中图片 ID 与用途之间的分隔符
+pub const DERIVED_SEPARATOR: char = '#';
+
+/// 默认最多保留的派生条目数量
+pub const DEFAULT_DERIVED_LIMIT: usize = 256;
+
+/// 单个缓存条目的数据
+enum Stored {
+    Memory(Vec<u8>),
+    Disk(PathBuf),
+}
+
+struct Entry {
+    stored: Stored,
+    mime_type: String,
+}
+
+/// 在锁内查到的缓存条目，磁盘后端的数据需要调用 [`CacheHit::load`] 读取
+pub enum CacheHit {
+    Memory(CachedImage),
+    Disk { path: PathBuf, mime_type: String },
+}
+
+impl CacheHit {
+    /// 取出图片数据；磁盘文件丢失时视为未缓存
+    pub fn load(self) -> Option<CachedImage> {
+        match self {
+            Self::Memory(image) => Some(image),
+            Self::Disk { path, mime_type } => Some(CachedImage {
+                data: fs::read(path).ok()?,
+                mime_type,
+            }),
+        }
+    }
+
+    /// 是否需要读取磁盘文件
+    pub fn needs_io(&self) -> bool {
+        matches!(self, Self::Disk { .. })
+    }
+}
+
+/// 已登记、尚未写入的缓存条目，由 [`ImageCache::prepare_insert`] 创建
+pub struct PendingInsert {
+    key: String,
+    image: CachedImage,
+    /// 磁盘后端分配的文件路径，内存后端为 None
+    path: Option<PathBuf>,
+}
+
+impl PendingInsert {
+    /// 是否需要写入磁盘文件
+    pub fn needs_io(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// 写入磁盘文件；失败时退回内存存储
+    pub fn write(mut self) -> Self {
+        if let Some(path) = &self.path {
+            let written = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, &self.image.data));
+            match written {
+                Ok(()) => self.image.data = Vec::new(),
+                Err(err) => {
+                    log::warn!("写入磁盘缓存失败，改为保存在内存中: {}", err);
+                    self.path = None;
+                }
+            }
+        }
+        self
+    }
+}
+
+/// 删除缓存文件，在释放缓存锁之后调用
+pub fn remove_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// 图片缓存，接口与 HashMap 类似，读取时返回数据的副本
+pub struct ImageCache {
+    backend: CacheBackend,
+    entries: HashMap<String, Entry>,
+    /// 磁盘后端使用的临时目录
+    dir: PathBuf,
+    /// 下一个缓存文件的编号
+    next_file: u64,
+    /// 派生条目的键，按写入顺序排列
+    derived: VecDeque<String>,
+    /// 最多保留的派生条目数量
+    derived_limit: usize,
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join(format!("wxdatviewer-cache-{}", std::process::id())))
+    }
+}
+
+impl ImageCache {
+    /// 创建内存缓存，`dir` 为切换到磁盘后端时使用的临时目录
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            backend: CacheBackend::Memory,
+            entries: HashMap::new(),
+            dir,
+            next_file: 0,
+            derived: VecDeque::new(),
+            derived_limit: DEFAULT_DERIVED_LIMIT,
+        }
+    }
+
+    /// 设置派生条目的数量上限，超出部分立即删除
+    #[cfg(test)]
+    pub fn set_derived_limit(&mut self, limit: usize) {
+        self.derived_limit = limit;
+        remove_files(self.evict_derived());
+    }
+
+    /// 切换后端，切换时清空已有缓存
+    pub fn set_backend(&mut self, backend: CacheBackend) {
+        if backend != self.backend {
+            self.clear();
+            self.backend = backend;
+        }
+    }
+
+    /// 查找缓存条目，不读取磁盘文件
+    pub fn lookup(&self, key: &str) -> Option<CacheHit> {
+        let entry = self.entries.get(key)?;
+        Some(match &entry.stored {
+            Stored::Memory(data) => CacheHit::Memory(CachedImage {
+                data: data.clone(),
+                mime_type: entry.mime_type.clone(),
+            }),
+            Stored::Disk(path) => CacheHit::Disk {
+                path: path.clone(),
+                mime_type: entry.mime_type.clone(),
+            },
+        })
+    }
+
+    /// 读取缓存的图片，会在当前线程读取磁盘文件
+    #[cfg(test)]
+    pub fn get(&self, key: &str) -> Option<CachedImage> {
+        self.lookup(key)?.load()
+    }
+
+    /// 只读取缓存图片的 MIME 类型，不读取数据
+    pub fn mime_type(&self, key: &str) -> Option<String> {
+        self.entries.get(key).map(|entry| entry.mime_type.clone())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// 写入缓存，会在当前线程读写磁盘文件
+    #[cfg(test)]
+    pub fn insert(&mut self, key: String, image: CachedImage) {
+        let pending = self.prepare_insert(key, image).write();
+        remove_files(self.finish_insert(pending));
+    }
+
+    /// 登记要写入的条目，磁盘后端在这里分配文件路径
+    pub fn prepare_insert(&mut self, key: String, image: CachedImage) -> PendingInsert {
+        let path = match self.backend {
+            CacheBackend::Memory => None,
+            CacheBackend::Disk => {
+                let path = self.dir.join(format!("{}.bin", self.next_file));
+                self.next_file += 1;
+                Some(path)
+            }
+        };
+        PendingInsert { key, image, path }
+    }
+
+    /// 保存已写入的条目，返回被替换或淘汰的缓存文件，由调用方在释放锁之后删除
+    pub fn finish_insert(&mut self, pending: PendingInsert) -> Vec<PathBuf> {
+        let PendingInsert { key, image, path } = pending;
+        let stored = match path {
+            // 写入期间切换了后端或清空了缓存，丢弃这个条目
+            Some(path) if self.backend != CacheBackend::Disk || !self.dir.exists() => {
+                return vec![path];
+            }
+            Some(path) => Stored::Disk(path),
+            None => Stored::Memory(image.data),
+        };
+
+        let mut stale = Vec::new();
+        let is_derived = key.contains(DERIVED_SEPARATOR);
+        let old = self.entries.insert(
+            key.clone(),
+            Entry {
+                stored,
+                mime_type: image.mime_type,
+            },
+        );
+        match old {
+            Some(old) => stale.extend(Self::file_of(old)),
+            None if is_derived => {
+                self.derived.push_back(key);
+                stale.extend(self.evict_derived());
+            }
+            None => {}
+        }
+        stale
+    }
+
+    /// 删除条目，同时删除以 `key#` 开头的派生条目；返回对应的缓存文件，由调用方在释放锁之后删除
+    pub fn remove(&mut self, key: &str) -> Vec<PathBuf> {
+        let prefix = format!("{}{}", key, DERIVED_SEPARATOR);
+        let derived: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|k| k.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        let stale = derived
+            .iter()
+            .map(String::as_str)
+            .chain([key])
+            .filter_map(|key| self.entries.remove(key).and_then(Self::file_of))
+            .collect();
+
+        let entries = &self.entries;
+        self.derived.retain(|key| entries.contains_key(key));
+        stale
+    }
+
+    /// 清空缓存并删除磁盘后端的临时文件
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.derived.clear();
+        if self.dir.exists() {
+            if let Err(err) = fs::remove_dir_all(&self.dir) {
+                log::warn!("删除缓存目录失败 {}: {}", self.dir.display(), err);
+            }
+        }
+    }
+
+    /// 已缓存的图片数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 派生条目超过上限时删除最早写入的条目，返回对应的缓存文件
+    fn evict_derived(&mut self) -> Vec<PathBuf> {
+        let mut stale = Vec::new();
+        while self.derived.len() > self.derived_limit {
+            let Some(key) = self.derived.pop_front() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                stale.extend(Self::file_of(entry));
+            }
+        }
+        stale
+    }
+
+    fn file_of(entry: Entry) -> Option<PathBuf> {
+        match entry.stored {
+            Stored::Disk(path) => Some(path),
+            Stored::Memory(_) => None,
+        }
+    }
+}
+
+impl Drop for ImageCache {
+    fn drop(&mut self) {
+        if self.backend == CacheBackend::Disk {
+            self.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(data: &[u8]) -> CachedImage {
+        CachedImage {
+            data: data.to_vec(),
+            mime_type: "image/png".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_disk_backend_round_trip_and_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let mut cache = ImageCache::new(cache_dir.clone());
+        cache.set_backend(CacheBackend::Disk);
+
+        cache.insert("a".to_string(), image(b"first"));
+        cache.insert("b".to_string(), image(b"second"));
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+        let cached = cache.get("a").unwrap();
+        assert_eq!(cached.data, b"first");
+        assert_eq!(cache.mime_type("b").as_deref(), Some("image/png"));
+
+        // 覆盖和删除条目时同时删除对应文件
+        cache.insert("a".to_string(), image(b"replaced"));
+        assert_eq!(cache.get("a").unwrap().data, b"replaced");
+        remove_files(cache.remove("b"));
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(!cache_dir.exists());
+    }
+
+    #[test]
+    fn test_remove_drops_derived_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let mut cache = ImageCache::new(cache_dir.clone());
+        cache.set_backend(CacheBackend::Disk);
+
+        cache.insert("a.dat".to_string(), image(b"full"));
+        cache.insert("a.dat#thumb256".to_string(), image(b"thumb"));
+        cache.insert("a.dat#frame-1000".to_string(), image(b"frame"));
+        cache.insert("a.dat.bak".to_string(), image(b"other"));
+        cache.insert("b.dat#thumb256".to_string(), image(b"other thumb"));
+
+        remove_files(cache.remove("a.dat"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key("a.dat.bak"));
+        assert!(cache.contains_key("b.dat#thumb256"));
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+        // 只删除派生条目时不影响原图
+        cache.insert("a.dat".to_string(), image(b"full"));
+        cache.insert("a.dat#thumb256".to_string(), image(b"thumb"));
+        remove_files(cache.remove("a.dat#thumb256"));
+        assert!(cache.contains_key("a.dat"));
+    }
+
+    #[test]
+    fn test_derived_entries_are_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = ImageCache::new(dir.path().join("cache"));
+        cache.set_derived_limit(2);
+
+        cache.insert("a.dat".to_string(), image(b"full"));
+        cache.insert("a.dat#thumb128".to_string(), image(b"1"));
+        cache.insert("a.dat#thumb256".to_string(), image(b"2"));
+        // 覆盖已有条目不改变写入顺序，也不触发淘汰
+        cache.insert("a.dat#thumb128".to_string(), image(b"1'"));
+        assert_eq!(cache.len(), 3);
+
+        cache.insert("b.dat#thumb256".to_string(), image(b"3"));
+        assert!(!cache.contains_key("a.dat#thumb128"));
+        assert!(cache.contains_key("a.dat#thumb256"));
+        assert!(cache.contains_key("b.dat#thumb256"));
+        // 原图不计入派生条目上限
+        assert!(cache.contains_key("a.dat"));
+
+        // 随原图删除的派生条目不再占用名额
+        remove_files(cache.remove("a.dat"));
+        cache.insert("c.dat#thumb256".to_string(), image(b"4"));
+        assert!(cache.contains_key("b.dat#thumb256"));
+        assert!(cache.contains_key("c.dat#thumb256"));
+    }
+
+    #[test]
+    fn test_disk_io_happens_outside_insert() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let mut cache = ImageCache::new(cache_dir.clone());
+        cache.set_backend(CacheBackend::Disk);
+
+        // 登记和保存条目都不读写文件，文件只在 write 和 load 中访问
+        let pending = cache.prepare_insert("a".to_string(), image(b"data"));
+        assert!(pending.needs_io() && !cache_dir.exists());
+        let pending = pending.write();
+        assert!(cache.finish_insert(pending).is_empty());
+
+        let hit = cache.lookup("a").unwrap();
+        assert!(hit.needs_io());
+        assert_eq!(hit.load().unwrap().data, b"data");
+
+        // 写入期间清空了缓存时丢弃条目，文件交给调用方删除
+        let pending = cache
+            .prepare_insert("b".to_string(), image(b"late"))
+            .write();
+        cache.clear();
+        let stale = cache.finish_insert(pending);
+        assert_eq!(stale.len(), 1);
+        assert!(!cache.contains_key("b"));
+        remove_files(stale);
+
+        // 内存后端不需要文件读写
+        cache.set_backend(CacheBackend::Memory);
+        let pending = cache.prepare_insert("c".to_string(), image(b"mem"));
+        assert!(!pending.needs_io());
+        cache.finish_insert(pending);
+        assert!(!cache.lookup("c").unwrap().needs_io());
+    }
+
+    #[test]
+    fn test_switching_backend_clears_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = ImageCache::new(dir.path().join("cache"));
+        cache.insert("a".to_string(), image(b"x"));
+        assert!(cache.contains_key("a"));
+
+        cache.set_backend(CacheBackend::Disk);
+        assert!(!cache.contains_key("a"));
+        assert_eq!(CacheBackend::parse("DISK"), Some(CacheBackend::Disk));
+        assert_eq!(CacheBackend::parse("ram"), None);
+    }
+}
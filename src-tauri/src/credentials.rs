@@ -0,0 +1,73 @@
+//! 系统凭据存储模块
+//!
+//! 在 Windows 上把 AES 密钥保存到凭据管理器，避免以明文形式写入 config.json。
+//! 其他平台没有对应实现，由调用方回退到配置文件。
+
+use crate::error::AppError;
+
+/// 凭据管理器中使用的服务名
+#[cfg(windows)]
+const SERVICE_NAME: &str = "WxDatViewer";
+
+/// 按目标名称保存和读取密钥的存储
+pub trait SecretStore {
+    /// 保存密钥，已存在时覆盖
+    fn save(&self, target: &str, secret: &str) -> Result<(), AppError>;
+
+    /// 读取密钥，不存在时返回 None
+    fn load(&self, target: &str) -> Result<Option<String>, AppError>;
+}
+
+/// Windows 凭据管理器
+#[cfg(windows)]
+pub struct CredentialManager;
+
+#[cfg(windows)]
+impl CredentialManager {
+    fn entry(target: &str) -> Result<keyring::Entry, AppError> {
+        keyring::Entry::new(SERVICE_NAME, target)
+            .map_err(|e| AppError::Internal(format!("无法访问凭据管理器: {}", e)))
+    }
+}
+
+#[cfg(windows)]
+impl SecretStore for CredentialManager {
+    fn save(&self, target: &str, secret: &str) -> Result<(), AppError> {
+        Self::entry(target)?
+            .set_password(secret)
+            .map_err(|e| AppError::Internal(format!("保存凭据失败: {}", e)))
+    }
+
+    fn load(&self, target: &str) -> Result<Option<String>, AppError> {
+        match Self::entry(target)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(AppError::Internal(format!("读取凭据失败: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// 内存中的存储，供测试使用
+    #[derive(Default)]
+    pub(crate) struct MemoryStore(Mutex<HashMap<String, String>>);
+
+    impl SecretStore for MemoryStore {
+        fn save(&self, target: &str, secret: &str) -> Result<(), AppError> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(target.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn load(&self, target: &str) -> Result<Option<String>, AppError> {
+            Ok(self.0.lock().unwrap().get(target).cloned())
+        }
+    }
+}
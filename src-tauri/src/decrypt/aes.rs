@@ -4,7 +4,7 @@ use super::error::DecryptError;
 
 #[allow(deprecated)]
 use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
-use aes::Aes128;
+use aes::{Aes128, Aes256};
 
 /// AES 加密处理器
 pub struct AesHandler;
@@ -12,16 +12,39 @@ pub struct AesHandler;
 impl AesHandler {
     pub const BLOCK_SIZE: usize = 16;
 
-    #[allow(deprecated)]
+    /// 是否为支持的密钥长度 (AES-128 为 16 字节，AES-256 为 32 字节)
+    pub fn is_valid_key_len(len: usize) -> bool {
+        matches!(len, 16 | 32)
+    }
+
     pub fn decrypt_ecb(data: &[u8], key: &[u8]) -> Result<Vec<u8>, DecryptError> {
-        if key.len() != 16 {
-            return Err(DecryptError::AesDecryptError(
-                "AES 密钥必须为 16 字节".to_string(),
-            ));
+        let mut result = Self::decrypt_ecb_blocks(data, key)?;
+
+        // 移除 PKCS7 填充
+        Self::pkcs7_unpad(&mut result)?;
+
+        Ok(result)
+    }
+
+    /// 按块解密，不处理填充；末尾不足一块的字节原样保留
+    pub fn decrypt_ecb_blocks(data: &[u8], key: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        log::debug!("AES-{} 解密 {} 字节", key.len() * 8, data.len());
+        match key.len() {
+            16 => Self::decrypt_blocks_with::<Aes128>(data, key),
+            32 => Self::decrypt_blocks_with::<Aes256>(data, key),
+            _ => Err(DecryptError::AesDecryptError(
+                "AES 密钥必须为 16 或 32 字节".to_string(),
+            )),
         }
+    }
 
+    #[allow(deprecated)]
+    fn decrypt_blocks_with<C: KeyInit + BlockDecrypt>(
+        data: &[u8],
+        key: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
         // 使用 new_from_slice 避免直接构造 GenericArray 以提升兼容性
-        let cipher = Aes128::new_from_slice(key)
+        let cipher = C::new_from_slice(key)
             .map_err(|_| DecryptError::AesDecryptError("AES 密钥长度无效".to_string()))?;
 
         let mut result = data.to_vec();
@@ -33,9 +56,6 @@ impl AesHandler {
             cipher.decrypt_block(block);
         }
 
-        // 移除 PKCS7 填充
-        Self::pkcs7_unpad(&mut result)?;
-
         Ok(result)
     }
 
@@ -47,18 +67,21 @@ impl AesHandler {
         let padding_len = *data.last().unwrap() as usize;
 
         if padding_len == 0 || padding_len > Self::BLOCK_SIZE || padding_len > data.len() {
-            return Err(DecryptError::AesDecryptError("无效的填充".to_string()));
+            return Err(DecryptError::InvalidPadding);
         }
 
         let start = data.len() - padding_len;
         if !data[start..].iter().all(|&b| b == padding_len as u8) {
-            return Err(DecryptError::AesDecryptError("填充验证失败".to_string()));
+            return Err(DecryptError::InvalidPadding);
         }
 
         data.truncate(start);
         Ok(())
     }
 
+    /// 明文长度为 `size` 时 PKCS7 填充后的密文长度
+    ///
+    /// PKCS7 总会追加填充，明文恰好是块大小整数倍时也会多出一整块，所以 16 对应 32。
     pub fn align_size(size: usize) -> usize {
         size + (Self::BLOCK_SIZE - size % Self::BLOCK_SIZE)
     }
@@ -77,12 +100,42 @@ mod tests {
         let mut data = vec![1, 2, 3, 1]; // 1 byte padding
         AesHandler::pkcs7_unpad(&mut data).unwrap();
         assert_eq!(data, vec![1, 2, 3]);
+
+        // 密钥错误时解出的填充通常无效
+        for bad in [vec![1, 2, 3, 0], vec![1, 2, 3, 17], vec![1, 2, 3, 9, 2]] {
+            let mut data = bad;
+            assert!(matches!(
+                AesHandler::pkcs7_unpad(&mut data),
+                Err(DecryptError::InvalidPadding)
+            ));
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_decrypt_ecb_aes256() {
+        use aes::cipher::BlockEncrypt;
+
+        let key = b"0123456789abcdef0123456789abcdef";
+        let mut data: Vec<u8> = (0..20u8).collect();
+        data.extend(std::iter::repeat_n(12u8, 12));
+        let cipher = Aes256::new_from_slice(key).unwrap();
+        for chunk in data.chunks_exact_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(chunk));
+        }
+
+        let plain = AesHandler::decrypt_ecb(&data, key).unwrap();
+        assert_eq!(plain, (0..20u8).collect::<Vec<_>>());
+
+        assert!(AesHandler::decrypt_ecb(&data, &key[..24]).is_err());
     }
 
     #[test]
     fn test_align_size() {
+        assert_eq!(AesHandler::align_size(0), 16);
         assert_eq!(AesHandler::align_size(15), 16);
         assert_eq!(AesHandler::align_size(16), 32);
         assert_eq!(AesHandler::align_size(17), 32);
+        assert_eq!(AesHandler::align_size(32), 48);
     }
 }
@@ -1,6 +1,7 @@
 //! DAT 解密错误类型
 
 use crate::error::AppError;
+use std::path::Path;
 
 /// DAT 解密错误类型
 #[derive(Debug, Clone)]
@@ -10,16 +11,70 @@ pub enum DecryptError {
     AesDecryptError(String),
     UnsupportedVersion,
     HeaderParseError,
+    /// PKCS7 填充校验失败，通常说明 AES 密钥不正确
+    InvalidPadding,
+    UnsupportedHashAlgorithm(String),
+    /// 流式解密的块大小超出允许范围
+    InvalidChunkSize(usize),
+    /// 附带出错文件路径的错误
+    WithPath {
+        path: String,
+        source: Box<DecryptError>,
+    },
+}
+
+impl DecryptError {
+    /// 为错误附加出错文件的路径，已带路径的错误保持不变
+    pub fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+        match self {
+            DecryptError::WithPath { .. } => self,
+            other => DecryptError::WithPath {
+                path: path.as_ref().display().to_string(),
+                source: Box::new(other),
+            },
+        }
+    }
+
+    /// 出错文件的路径
+    #[allow(dead_code)] // 供调用方按文件汇总错误
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            DecryptError::WithPath { path, .. } => Some(path),
+            _ => None,
+        }
+    }
 }
 
 impl From<DecryptError> for AppError {
     fn from(err: DecryptError) -> Self {
         match err {
+            // 带消息的错误在消息前加上路径，其余错误统一归为解密失败
+            DecryptError::WithPath { path, source } => match AppError::from(*source) {
+                AppError::Internal(msg) => AppError::Internal(format!("{}: {}", path, msg)),
+                AppError::AesDecryptError(msg) => {
+                    AppError::AesDecryptError(format!("{}: {}", path, msg))
+                }
+                AppError::InvalidAesKey(msg) => {
+                    AppError::InvalidAesKey(format!("{}: {}", path, msg))
+                }
+                other => AppError::DecryptFailed(format!("{}: {}", path, other)),
+            },
             DecryptError::IoError(msg) => AppError::Internal(format!("文件读取失败: {}", msg)),
             DecryptError::InvalidFormat => AppError::InvalidDatFormat,
             DecryptError::AesDecryptError(msg) => AppError::AesDecryptError(msg),
             DecryptError::UnsupportedVersion => AppError::UnsupportedDatVersion,
             DecryptError::HeaderParseError => AppError::DatHeaderParseError,
+            DecryptError::InvalidPadding => {
+                AppError::InvalidAesKey("PKCS7 填充校验失败".to_string())
+            }
+            DecryptError::UnsupportedHashAlgorithm(name) => {
+                AppError::InvalidArgument(format!("不支持的哈希算法: {}", name))
+            }
+            DecryptError::InvalidChunkSize(size) => AppError::InvalidArgument(format!(
+                "块大小必须在 1 ~ {} 字节之间: {}",
+                super::MAX_CHUNK_SIZE,
+                size
+            )),
         }
     }
 }
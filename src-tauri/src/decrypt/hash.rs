@@ -0,0 +1,83 @@
+//! 解密数据哈希模块
+
+use super::error::DecryptError;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// 支持的哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// MD5 (微信文件名使用的哈希)
+    Md5,
+    /// SHA-256
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// 从算法名称解析 (不区分大小写)，支持 "md5" 和 "sha256"
+    pub fn parse(name: &str) -> Result<Self, DecryptError> {
+        match name.to_lowercase().as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha256" | "sha-256" => Ok(Self::Sha256),
+            _ => Err(DecryptError::UnsupportedHashAlgorithm(name.to_string())),
+        }
+    }
+}
+
+/// 增量哈希计算器
+pub enum ContentHasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl ContentHasher {
+    /// 创建指定算法的哈希计算器
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+        }
+    }
+
+    /// 追加数据
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// 结束计算并返回小写十六进制摘要
+    pub fn finalize_hex(self) -> String {
+        let digest = match self {
+            Self::Md5(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 一次性计算数据的十六进制摘要
+    pub(crate) fn hash_hex(data: &[u8], algorithm: HashAlgorithm) -> String {
+        let mut hasher = ContentHasher::new(algorithm);
+        hasher.update(data);
+        hasher.finalize_hex()
+    }
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(
+            hash_hex(b"abc", HashAlgorithm::Md5),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            hash_hex(b"abc", HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(HashAlgorithm::parse("crc32").is_err());
+    }
+}
@@ -4,18 +4,28 @@
 
 pub mod aes;
 pub mod error;
+pub mod hash;
 pub mod v3;
 pub mod v4;
 pub mod version;
 
 // 重新导出公共类型
 pub use error::DecryptError;
-pub use v3::V3Decryptor;
+pub use hash::{ContentHasher, HashAlgorithm};
+pub use v3::{V3Decryptor, XorKey};
 pub use v4::V4Decryptor;
 pub use version::{DatVersion, VersionDetector};
 
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
 
+/// 流式解密默认的块大小
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 流式解密允许的最大块大小
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
 /// DAT 文件解密器
 pub struct DatDecryptor;
 
@@ -26,6 +36,7 @@ impl DatDecryptor {
     }
 
     /// 解密 v3 版本的 DAT 文件
+    #[allow(dead_code)] // 保留按版本直接解密的入口
     pub fn decrypt_dat_v3<P: AsRef<Path>>(
         input_path: P,
         xor_key: u8,
@@ -34,31 +45,557 @@ impl DatDecryptor {
     }
 
     /// 解密 v4 版本的 DAT 文件
+    #[allow(dead_code)] // 保留按版本直接解密的入口
     pub fn decrypt_dat_v4<P: AsRef<Path>>(
         input_path: P,
         xor_key: u8,
         aes_key: &[u8],
     ) -> Result<Vec<u8>, DecryptError> {
-        V4Decryptor::decrypt(input_path, xor_key, aes_key)
+        let version = VersionDetector::detect(&input_path)?;
+        V4Decryptor::decrypt(input_path, version, xor_key, aes_key)
     }
 
     /// 自动检测版本并解密 DAT 文件
+    ///
+    /// `xor_key` 为 [`XorKey::recovering`] 时，v3 文件用给定密钥解不出可识别的文件头就按图片魔数
+    /// 推算密钥；直接传入 `u8` 时严格使用该密钥。
     pub fn decrypt<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        Self::decrypt_with_version(input_path, xor_key, aes_key).map(|(data, _)| data)
+    }
+
+    /// 与 [`DatDecryptor::decrypt`] 相同，同时返回检测到的文件版本
+    pub fn decrypt_with_version<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+    ) -> Result<(Vec<u8>, DatVersion), DecryptError> {
+        let input_path = input_path.as_ref();
+        let xor_key = xor_key.into();
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| Self::decrypt_with_sink(&mut file, xor_key, aes_key, &mut |_| {}))
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    /// 尽力解密可能被截断的文件，用于从损坏的缓存中恢复数据
+    ///
+    /// v4 文件被截断时保留能解密的部分 (见 [`V4Decryptor::decrypt_file_best_effort`])，
+    /// 不会因为缺少 PKCS7 填充块而报错；其余情况与 [`DatDecryptor::decrypt`] 相同。
+    pub fn decrypt_best_effort<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let input_path = input_path.as_ref();
+        let xor_key = xor_key.into();
+        let version = VersionDetector::detect(input_path).map_err(|e| e.with_path(input_path))?;
+        let (DatVersion::V4V1 | DatVersion::V4V2) = version else {
+            return Self::decrypt(input_path, xor_key, aes_key);
+        };
+
+        let key = aes_key.ok_or(DecryptError::AesDecryptError(
+            "v4 版本需要提供 AES 密钥".to_string(),
+        ))?;
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| {
+                V4Decryptor::decrypt_file_best_effort(&mut file, version, xor_key.key, key)
+            })
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    /// 严格使用给定密钥解密，不尝试推算 XOR 密钥
+    pub fn decrypt_exact<P: AsRef<Path>>(
         input_path: P,
         xor_key: u8,
         aes_key: Option<&[u8]>,
     ) -> Result<Vec<u8>, DecryptError> {
-        let version = Self::detect_version(&input_path)?;
+        let input_path = input_path.as_ref();
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| {
+                Self::decrypt_with_handle(&mut file, XorKey::exact(xor_key), aes_key)
+            })
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    /// 只解密文件开头部分 (v3 为前 16 字节，v4 为 AES 段)
+    ///
+    /// 用于在不读取整个文件的情况下判断密钥是否正确、内容是什么格式。
+    pub fn decrypt_head<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let input_path = input_path.as_ref();
+        Self::decrypt_head_inner(input_path, xor_key.into(), aes_key)
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    fn decrypt_head_inner(
+        input_path: &Path,
+        xor_key: XorKey,
+        aes_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let mut file = File::open(input_path)?;
+        let version = VersionDetector::detect_from(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        match version {
+            DatVersion::V3 => {
+                let key = V3Decryptor::resolve_key(&mut file, xor_key)?;
+                V3Decryptor::decrypt_head_file(&mut file, key, 16)
+            }
+            DatVersion::V4V1 | DatVersion::V4V2 => {
+                let key = aes_key.ok_or(DecryptError::AesDecryptError(
+                    "v4 版本需要提供 AES 密钥".to_string(),
+                ))?;
+                V4Decryptor::decrypt_head_file(&mut file, version, key)
+            }
+            DatVersion::Unknown => Err(DecryptError::UnsupportedVersion),
+        }
+    }
+
+    /// 只解密明文的前 `len` 字节，文件较短时返回全部明文
+    ///
+    /// 用于读取图片尺寸等只在文件开头的信息，不读取整个文件。
+    pub fn decrypt_prefix<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+        len: usize,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let input_path = input_path.as_ref();
+        Self::decrypt_prefix_inner(input_path, xor_key.into(), aes_key, len)
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    fn decrypt_prefix_inner(
+        input_path: &Path,
+        xor_key: XorKey,
+        aes_key: Option<&[u8]>,
+        len: usize,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let mut file = File::open(input_path)?;
+        let version = VersionDetector::detect_from(&mut file)?;
+        Self::decrypt_prefix_from(&mut file, version, xor_key, aes_key, len)
+    }
+
+    /// 使用已打开的文件句柄和已检测出的版本，只解密明文的前 `len` 字节
+    ///
+    /// 调用方需要同时展示版本时可以先用 [`VersionDetector::detect_from`] 检测，
+    /// 再用同一个句柄解密，解密失败时版本仍然可用。
+    pub fn decrypt_prefix_from(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+        len: usize,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let xor_key = xor_key.into();
+        file.seek(SeekFrom::Start(0))?;
 
         match version {
-            DatVersion::V3 => Self::decrypt_dat_v3(input_path, xor_key),
+            DatVersion::V3 => {
+                let key = V3Decryptor::resolve_key(file, xor_key)?;
+                V3Decryptor::decrypt_head_file(file, key, len)
+            }
             DatVersion::V4V1 | DatVersion::V4V2 => {
                 let key = aes_key.ok_or(DecryptError::AesDecryptError(
                     "v4 版本需要提供 AES 密钥".to_string(),
                 ))?;
-                Self::decrypt_dat_v4(input_path, xor_key, key)
+                V4Decryptor::decrypt_prefix_file(file, version, xor_key.key, key, len)
             }
             DatVersion::Unknown => Err(DecryptError::UnsupportedVersion),
         }
     }
+
+    /// 解密的同时计算明文的哈希，返回明文和十六进制摘要
+    ///
+    /// 哈希随解密出的每一段数据增量更新，不需要在解密后再遍历一次明文。
+    /// `algorithm` 支持 "md5" 和 "sha256"。
+    pub fn decrypt_and_hash<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+        algorithm: &str,
+    ) -> Result<(Vec<u8>, String), DecryptError> {
+        let mut hasher = ContentHasher::new(HashAlgorithm::parse(algorithm)?);
+
+        let input_path = input_path.as_ref();
+        let xor_key = xor_key.into();
+        let (data, _) = File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| {
+                Self::decrypt_with_sink(&mut file, xor_key, aes_key, &mut |chunk| {
+                    hasher.update(chunk)
+                })
+            })
+            .map_err(|e| e.with_path(input_path))?;
+
+        Ok((data, hasher.finalize_hex()))
+    }
+
+    /// 自动检测版本并把明文流式写入 `writer`，返回写入的字节数
+    ///
+    /// 按 `chunk_size` 分块读写，不在内存中保留完整明文，适合解密大视频。
+    /// `chunk_size` 必须在 1 ~ [`MAX_CHUNK_SIZE`] 之间。
+    pub fn decrypt_to_writer<P: AsRef<Path>>(
+        input_path: P,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+        writer: &mut dyn Write,
+        chunk_size: usize,
+    ) -> Result<u64, DecryptError> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(DecryptError::InvalidChunkSize(chunk_size));
+        }
+
+        let input_path = input_path.as_ref();
+        Self::decrypt_to_writer_inner(input_path, xor_key.into(), aes_key, writer, chunk_size)
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    fn decrypt_to_writer_inner(
+        input_path: &Path,
+        xor_key: XorKey,
+        aes_key: Option<&[u8]>,
+        writer: &mut dyn Write,
+        chunk_size: usize,
+    ) -> Result<u64, DecryptError> {
+        let mut file = File::open(input_path)?;
+        let version = VersionDetector::detect_from(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let written = match version {
+            DatVersion::V3 => {
+                let key = V3Decryptor::resolve_key(&mut file, xor_key)?;
+                V3Decryptor::xor_copy(&mut file, writer, key, chunk_size)?
+            }
+            DatVersion::V4V1 | DatVersion::V4V2 => {
+                let key = aes_key.ok_or(DecryptError::AesDecryptError(
+                    "v4 版本需要提供 AES 密钥".to_string(),
+                ))?;
+                V4Decryptor::decrypt_file_to_writer(
+                    &mut file,
+                    version,
+                    xor_key.key,
+                    key,
+                    writer,
+                    chunk_size,
+                )?
+            }
+            DatVersion::Unknown => return Err(DecryptError::UnsupportedVersion),
+        };
+
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// 使用已打开的文件句柄检测版本并解密
+    ///
+    /// 版本检测与解密共用同一个句柄，避免批量处理时重复打开文件。
+    pub fn decrypt_with_handle(
+        file: &mut File,
+        xor_key: impl Into<XorKey>,
+        aes_key: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DecryptError> {
+        Self::decrypt_with_sink(file, xor_key.into(), aes_key, &mut |_| {}).map(|(data, _)| data)
+    }
+
+    /// 使用已打开的文件句柄检测版本并解密，解密出的每一段数据依次交给 `sink`
+    ///
+    /// 返回明文和检测到的版本，调用方不需要再次检测。v3 文件在解密前确定实际使用的
+    /// XOR 密钥，`sink` 收到的始终是最终的明文。
+    pub fn decrypt_with_sink(
+        file: &mut File,
+        xor_key: XorKey,
+        aes_key: Option<&[u8]>,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> Result<(Vec<u8>, DatVersion), DecryptError> {
+        let version = VersionDetector::detect_from(file)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let data = match version {
+            DatVersion::V3 => {
+                let key = V3Decryptor::resolve_key(file, xor_key)?;
+                V3Decryptor::decrypt_file_with_sink(file, key, sink)
+            }
+            DatVersion::V4V1 | DatVersion::V4V2 => {
+                let key = aes_key.ok_or(DecryptError::AesDecryptError(
+                    "v4 版本需要提供 AES 密钥".to_string(),
+                ))?;
+                V4Decryptor::decrypt_file_with_sink(file, version, xor_key.key, key, sink)
+            }
+            DatVersion::Unknown => Err(DecryptError::UnsupportedVersion),
+        }?;
+        Ok((data, version))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    #[allow(deprecated)]
+    use ::aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+    use ::aes::Aes128;
+
+    pub(crate) const TEST_AES_KEY: &[u8; 16] = b"0123456789abcdef";
+
+    /// 按 v4 格式构造测试文件内容: 文件头 + AES 段 + 原始段 + XOR 段
+    #[allow(deprecated)]
+    pub(crate) fn build_v4_fixture(
+        signature: &[u8],
+        plain: &[u8],
+        aes_size: usize,
+        xor_size: usize,
+        xor_key: u8,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(signature);
+        out.extend_from_slice(&(aes_size as u32).to_le_bytes());
+        out.extend_from_slice(&(xor_size as u32).to_le_bytes());
+        out.push(0);
+
+        // PKCS7 填充后按块加密
+        let mut aes_part = plain[..aes_size].to_vec();
+        let pad = 16 - aes_part.len() % 16;
+        aes_part.extend(std::iter::repeat_n(pad as u8, pad));
+        let cipher = Aes128::new_from_slice(TEST_AES_KEY).unwrap();
+        for chunk in aes_part.chunks_exact_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(chunk));
+        }
+        out.extend_from_slice(&aes_part);
+
+        let xor_start = plain.len() - xor_size;
+        out.extend_from_slice(&plain[aes_size..xor_start]);
+        out.extend_from_slice(&V3Decryptor::xor_decrypt(&plain[xor_start..], xor_key));
+        out
+    }
+
+    fn sample_plain() -> Vec<u8> {
+        (0..100u8).collect()
+    }
+
+    #[test]
+    fn test_shared_handle_matches_reopen_v3() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        std::fs::write(&path, V3Decryptor::xor_decrypt(&sample_plain(), 0x56)).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let shared = DatDecryptor::decrypt_with_handle(&mut file, 0x56, None).unwrap();
+        let reopened = DatDecryptor::decrypt_dat_v3(&path, 0x56).unwrap();
+
+        assert_eq!(shared, reopened);
+        assert_eq!(shared, sample_plain());
+    }
+
+    #[test]
+    fn test_shared_handle_matches_reopen_v4() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let fixture = build_v4_fixture(
+            VersionDetector::V4_V2_SIGNATURE,
+            &sample_plain(),
+            20,
+            10,
+            0x56,
+        );
+        std::fs::write(&path, fixture).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let shared =
+            DatDecryptor::decrypt_with_handle(&mut file, 0x56, Some(TEST_AES_KEY)).unwrap();
+        let reopened = DatDecryptor::decrypt_dat_v4(&path, 0x56, TEST_AES_KEY).unwrap();
+
+        assert_eq!(shared, reopened);
+        assert_eq!(shared, sample_plain());
+    }
+
+    #[test]
+    fn test_errors_carry_file_path() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = dir.path().join("missing.dat");
+        let err = DatDecryptor::decrypt(&missing, 0x56, None).unwrap_err();
+        assert_eq!(err.path(), Some(missing.display().to_string().as_str()));
+
+        let v4 = dir.path().join("v4.dat");
+        let fixture = build_v4_fixture(
+            VersionDetector::V4_V1_SIGNATURE,
+            &sample_plain(),
+            20,
+            10,
+            0x56,
+        );
+        std::fs::write(&v4, fixture).unwrap();
+        let err = DatDecryptor::decrypt(&v4, 0x56, None).unwrap_err();
+        assert_eq!(err.path(), Some(v4.display().to_string().as_str()));
+        assert!(matches!(
+            &err,
+            DecryptError::WithPath { source, .. } if matches!(**source, DecryptError::AesDecryptError(_))
+        ));
+
+        // 已带路径的错误不会重复包装
+        let rewrapped = err.clone().with_path("other.dat");
+        assert_eq!(rewrapped.path(), err.path());
+
+        // 转换为 AppError 后消息中包含路径
+        let message = String::from(crate::error::AppError::from(err));
+        assert!(message.contains("v4.dat"));
+
+        // 密钥错误导致的填充校验失败单独报告为 AES 密钥错误
+        let err = DatDecryptor::decrypt(&v4, 0x56, Some(b"fedcba9876543210")).unwrap_err();
+        match crate::error::AppError::from(err) {
+            crate::error::AppError::InvalidAesKey(msg) => assert!(msg.contains("v4.dat")),
+            other => panic!("期望 InvalidAesKey, 实际 {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_and_hash_matches_separate_hash() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // v3 文件跨越多个读取块
+        let plain: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+        let v3 = dir.path().join("v3.dat");
+        std::fs::write(&v3, V3Decryptor::xor_decrypt(&plain, 0x56)).unwrap();
+
+        let v4 = dir.path().join("v4.dat");
+        let fixture = build_v4_fixture(
+            VersionDetector::V4_V2_SIGNATURE,
+            &sample_plain(),
+            20,
+            10,
+            0x56,
+        );
+        std::fs::write(&v4, fixture).unwrap();
+
+        for path in [&v3, &v4] {
+            for algorithm in ["md5", "sha256"] {
+                let (data, digest) =
+                    DatDecryptor::decrypt_and_hash(path, 0x56, Some(TEST_AES_KEY), algorithm)
+                        .unwrap();
+                let separate = DatDecryptor::decrypt(path, 0x56, Some(TEST_AES_KEY)).unwrap();
+                assert_eq!(data, separate);
+                assert_eq!(
+                    digest,
+                    hash::tests::hash_hex(&separate, HashAlgorithm::parse(algorithm).unwrap())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decrypt_recovers_wrong_v3_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
+        let path = dir.path().join("a.dat");
+        std::fs::write(&path, V3Decryptor::xor_decrypt(&jpeg, 0x56)).unwrap();
+
+        let wrong = XorKey::recovering(0x00);
+        assert_eq!(DatDecryptor::decrypt(&path, wrong, None).unwrap(), jpeg);
+        assert_eq!(
+            DatDecryptor::decrypt(&path, XorKey::recovering(0x56), None).unwrap(),
+            jpeg
+        );
+        assert_eq!(
+            DatDecryptor::decrypt_with_version(&path, wrong, None).unwrap(),
+            (jpeg.to_vec(), DatVersion::V3)
+        );
+
+        // 哈希、前缀和流式解密使用同一个推算结果
+        let (data, digest) = DatDecryptor::decrypt_and_hash(&path, wrong, None, "md5").unwrap();
+        assert_eq!(data, jpeg);
+        assert_eq!(digest, hash::tests::hash_hex(&jpeg, HashAlgorithm::Md5));
+        assert_eq!(
+            DatDecryptor::decrypt_prefix(&path, wrong, None, 4).unwrap(),
+            jpeg[..4]
+        );
+        assert_eq!(
+            DatDecryptor::decrypt_head(&path, wrong, None).unwrap(),
+            jpeg
+        );
+        let mut out = Vec::new();
+        DatDecryptor::decrypt_to_writer(&path, wrong, None, &mut out, 3).unwrap();
+        assert_eq!(out, jpeg);
+
+        // 不要求推算时严格使用给定密钥
+        assert_ne!(DatDecryptor::decrypt(&path, 0x00, None).unwrap(), jpeg);
+        assert_ne!(
+            DatDecryptor::decrypt_exact(&path, 0x00, None).unwrap(),
+            jpeg
+        );
+
+        // 无法识别的数据保持原样
+        let noise = dir.path().join("noise.dat");
+        std::fs::write(&noise, [0x01, 0x02, 0x03, 0x04]).unwrap();
+        assert_eq!(
+            DatDecryptor::decrypt(&noise, XorKey::recovering(0x10), None).unwrap(),
+            [0x11, 0x12, 0x13, 0x14]
+        );
+    }
+
+    #[test]
+    fn test_decrypt_prefix_matches_full_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain: Vec<u8> = (0..200u8).collect();
+
+        let v3 = dir.path().join("v3.dat");
+        std::fs::write(&v3, V3Decryptor::xor_decrypt(&plain, 0x56)).unwrap();
+        let v4 = dir.path().join("v4.dat");
+        let fixture = build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 40, 30, 0x56);
+        std::fs::write(&v4, fixture).unwrap();
+
+        // 分别落在 AES 段、原始段、XOR 段内以及超出文件长度
+        for path in [&v3, &v4] {
+            for len in [10, 100, 185, 1000] {
+                let prefix =
+                    DatDecryptor::decrypt_prefix(path, 0x56, Some(TEST_AES_KEY), len).unwrap();
+                assert_eq!(prefix, plain[..len.min(plain.len())]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decrypt_to_writer_independent_of_chunk_size() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let plain: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let v3 = dir.path().join("v3.dat");
+        std::fs::write(&v3, V3Decryptor::xor_decrypt(&plain, 0x56)).unwrap();
+
+        let v4 = dir.path().join("v4.dat");
+        let fixture = build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 1024, 5000, 0x56);
+        std::fs::write(&v4, fixture).unwrap();
+
+        for path in [&v3, &v4] {
+            let mut outputs = Vec::new();
+            for chunk_size in [7, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE] {
+                let mut out = Vec::new();
+                let written = DatDecryptor::decrypt_to_writer(
+                    path,
+                    0x56,
+                    Some(TEST_AES_KEY),
+                    &mut out,
+                    chunk_size,
+                )
+                .unwrap();
+                assert_eq!(written, out.len() as u64);
+                outputs.push(out);
+            }
+            assert!(outputs.iter().all(|out| *out == plain));
+        }
+
+        for chunk_size in [0, MAX_CHUNK_SIZE + 1] {
+            let err = DatDecryptor::decrypt_to_writer(&v3, 0x56, None, &mut Vec::new(), chunk_size)
+                .unwrap_err();
+            assert!(matches!(err, DecryptError::InvalidChunkSize(size) if size == chunk_size));
+        }
+    }
 }
@@ -1,14 +1,58 @@
 //! DAT v3 版本解密模块
 
 use super::error::DecryptError;
+use rayon::prelude::*;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+/// v3 文件使用的 XOR 密钥
+///
+/// `recover` 为 true 时，密钥解不出可识别的文件头就按图片魔数推算密钥 (见
+/// [`V3Decryptor::resolve_key`])。从 `u8` 转换得到的密钥不做推算。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorKey {
+    pub key: u8,
+    pub recover: bool,
+}
+
+impl XorKey {
+    /// 严格使用给定密钥
+    pub fn exact(key: u8) -> Self {
+        Self {
+            key,
+            recover: false,
+        }
+    }
+
+    /// 给定密钥解不出可识别的文件头时改用推算的密钥
+    pub fn recovering(key: u8) -> Self {
+        Self { key, recover: true }
+    }
+}
+
+impl From<u8> for XorKey {
+    fn from(key: u8) -> Self {
+        Self::exact(key)
+    }
+}
+
 /// v3 版本解密器
 pub struct V3Decryptor;
 
 impl V3Decryptor {
+    /// 分块读取时每块的大小
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    /// 超过该大小的数据分块后在线程池中并行 XOR
+    const PARALLEL_THRESHOLD: usize = 8 * 1024 * 1024;
+
+    /// 并行 XOR 时每个任务处理的字节数
+    const PARALLEL_CHUNK_SIZE: usize = 1024 * 1024;
+
+    /// 推算密钥时读取的文件开头字节数，足够覆盖 [`Self::has_known_header`] 检查的范围
+    const HEAD_PROBE_LEN: usize = 16;
+
     /// 解密 v3 版本的 DAT 文件
     ///
     /// # 参数
@@ -20,20 +64,87 @@ impl V3Decryptor {
     ///
     /// 解密后的字节数据
     pub fn decrypt<P: AsRef<Path>>(input_path: P, xor_key: u8) -> Result<Vec<u8>, DecryptError> {
-        let mut file = File::open(input_path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
+        let input_path = input_path.as_ref();
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| Self::decrypt_file(&mut file, xor_key))
+            .map_err(|e| e.with_path(input_path))
+    }
 
-        log::debug!("解密 v3 DAT 文件,大小: {} 字节", data.len());
+    /// 从已打开的文件句柄解密 v3 数据
+    ///
+    /// 从文件当前位置读取到末尾。
+    pub fn decrypt_file(file: &mut File, xor_key: u8) -> Result<Vec<u8>, DecryptError> {
+        Self::decrypt_file_with_sink(file, xor_key, &mut |_| {})
+    }
+
+    /// 从已打开的文件句柄分块解密 v3 数据，每解密一块就交给 `sink`
+    ///
+    /// 从文件当前位置读取到末尾，返回完整的解密数据。
+    pub fn decrypt_file_with_sink(
+        file: &mut File,
+        xor_key: u8,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> Result<Vec<u8>, DecryptError> {
+        let mut decrypted = Vec::new();
+        let mut buffer = vec![0u8; Self::CHUNK_SIZE];
 
-        // XOR 解密
-        let decrypted = Self::xor_decrypt(&data, xor_key);
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
 
-        log::debug!("v3 解密完成");
+            // XOR 解密
+            let chunk = &mut buffer[..read];
+            Self::xor_in_place(chunk, xor_key);
+            sink(chunk);
+            decrypted.extend_from_slice(chunk);
+        }
+
+        log::debug!("v3 解密完成,大小: {} 字节", decrypted.len());
 
         Ok(decrypted)
     }
 
+    /// 从 `reader` 按 `chunk_size` 分块读取，XOR 解密后写入 `writer`，返回写入的字节数
+    ///
+    /// 密钥为 0 时相当于原样复制。
+    pub fn xor_copy(
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        xor_key: u8,
+        chunk_size: usize,
+    ) -> Result<u64, DecryptError> {
+        let mut buffer = vec![0u8; chunk_size];
+        let mut written = 0u64;
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            let chunk = &mut buffer[..read];
+            Self::xor_in_place(chunk, xor_key);
+            writer.write_all(chunk)?;
+            written += read as u64;
+        }
+
+        Ok(written)
+    }
+
+    /// 只解密文件开头的 `len` 字节，用于快速判断密钥是否正确
+    pub fn decrypt_head_file(
+        file: &mut File,
+        xor_key: u8,
+        len: usize,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let mut data = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut data)?;
+        Ok(Self::xor_decrypt(&data, xor_key))
+    }
+
     /// XOR 解密
     ///
     /// # 参数
@@ -45,8 +156,126 @@ impl V3Decryptor {
     ///
     /// 解密后的数据
     pub fn xor_decrypt(data: &[u8], key: u8) -> Vec<u8> {
-        data.iter().map(|&b| b ^ key).collect()
+        // 直接从输入写到输出，避免先复制再原地 XOR 多遍历一次内存
+        let mut decrypted = vec![0u8; data.len()];
+        if data.len() >= Self::PARALLEL_THRESHOLD {
+            decrypted
+                .par_chunks_mut(Self::PARALLEL_CHUNK_SIZE)
+                .zip(data.par_chunks(Self::PARALLEL_CHUNK_SIZE))
+                .for_each(|(dst, src)| Self::xor_words(src, dst, key));
+        } else {
+            Self::xor_words(data, &mut decrypted, key);
+        }
+        decrypted
     }
+
+    /// 原地 XOR 解密
+    ///
+    /// 按 8 字节一组处理，超过 [`Self::PARALLEL_THRESHOLD`] 的数据分块并行处理。
+    /// 密钥为 0 时不做任何修改。
+    pub fn xor_in_place(data: &mut [u8], key: u8) {
+        if key == 0 {
+            return;
+        }
+
+        if data.len() >= Self::PARALLEL_THRESHOLD {
+            data.par_chunks_mut(Self::PARALLEL_CHUNK_SIZE)
+                .for_each(|chunk| Self::xor_words_in_place(chunk, key));
+        } else {
+            Self::xor_words_in_place(data, key);
+        }
+    }
+
+    /// 把 `src` 与密钥 XOR 后写入等长的 `dst`
+    ///
+    /// 密钥扩展成 u64 后按 8 字节一组处理，不足 8 字节的结尾逐字节处理。
+    fn xor_words(src: &[u8], dst: &mut [u8], key: u8) {
+        let wide_key = u64::from_ne_bytes([key; 8]);
+        let mut src_words = src.chunks_exact(8);
+        let mut dst_words = dst.chunks_exact_mut(8);
+        for (s, d) in (&mut src_words).zip(&mut dst_words) {
+            let value = u64::from_ne_bytes(s.try_into().unwrap()) ^ wide_key;
+            d.copy_from_slice(&value.to_ne_bytes());
+        }
+        for (s, d) in src_words.remainder().iter().zip(dst_words.into_remainder()) {
+            *d = s ^ key;
+        }
+    }
+
+    /// [`Self::xor_words`] 的原地版本
+    fn xor_words_in_place(data: &mut [u8], key: u8) {
+        let wide_key = u64::from_ne_bytes([key; 8]);
+        let mut words = data.chunks_exact_mut(8);
+        for word in &mut words {
+            let value = u64::from_ne_bytes(word.try_into().unwrap()) ^ wide_key;
+            word.copy_from_slice(&value.to_ne_bytes());
+        }
+        for byte in words.into_remainder() {
+            *byte ^= key;
+        }
+    }
+
+    /// 确定从文件当前位置开始的 v3 数据实际使用的 XOR 密钥
+    ///
+    /// `xor_key.recover` 为 true 且给定密钥解不出可识别的文件头时，从原始数据开头推算密钥，
+    /// 推算不出时仍使用给定密钥。只读取开头 [`Self::HEAD_PROBE_LEN`] 字节，读取后复位到原位置。
+    pub fn resolve_key(file: &mut File, xor_key: XorKey) -> Result<u8, DecryptError> {
+        if !xor_key.recover {
+            return Ok(xor_key.key);
+        }
+
+        let start = file.stream_position()?;
+        let raw_head = Self::decrypt_head_file(file, 0, Self::HEAD_PROBE_LEN)?;
+        file.seek(SeekFrom::Start(start))?;
+
+        if Self::has_known_header(&Self::xor_decrypt(&raw_head, xor_key.key)) {
+            return Ok(xor_key.key);
+        }
+        match Self::detect_xor_key(&raw_head) {
+            Some(detected) if detected != xor_key.key => {
+                log::info!(
+                    "XOR 密钥 0x{:02x} 解不出可识别的文件头，改用检测到的密钥 0x{:02x}",
+                    xor_key.key,
+                    detected
+                );
+                Ok(detected)
+            }
+            _ => Ok(xor_key.key),
+        }
+    }
+
+    /// 根据加密数据开头与已知图片魔数 (JPEG / PNG / GIF) 推算 XOR 密钥
+    ///
+    /// 用第一个字节推出密钥，再用后续字节 (最多到第三个) 校验，少于 2 字节时返回 None。
+    pub fn detect_xor_key(first_bytes: &[u8]) -> Option<u8> {
+        if first_bytes.len() < 2 {
+            return None;
+        }
+
+        let checked = &first_bytes[..first_bytes.len().min(3)];
+        Self::IMAGE_MAGICS.iter().find_map(|magic| {
+            let key = checked[0] ^ magic[0];
+            checked
+                .iter()
+                .zip(magic.iter())
+                .all(|(&b, &m)| b ^ key == m)
+                .then_some(key)
+        })
+    }
+
+    /// 解密结果是否以可识别的媒体文件头开头
+    pub fn has_known_header(data: &[u8]) -> bool {
+        Self::IMAGE_MAGICS
+            .iter()
+            .any(|magic| data.starts_with(magic))
+            || data.starts_with(b"RIFF")
+            || data.starts_with(b"wxgf")
+            || data.starts_with(b"WXGF")
+            || data.get(4..8) == Some(b"ftyp")
+    }
+
+    /// JPEG、PNG、GIF 的前 3 字节
+    const IMAGE_MAGICS: [[u8; 3]; 3] = [[0xFF, 0xD8, 0xFF], [0x89, 0x50, 0x4E], [0x47, 0x49, 0x46]];
 }
 
 #[cfg(test)]
@@ -61,4 +290,41 @@ mod tests {
         let decrypted = V3Decryptor::xor_decrypt(&encrypted, key);
         assert_eq!(data, decrypted);
     }
+
+    #[test]
+    fn test_xor_matches_bytewise() {
+        let data: Vec<u8> = (0..V3Decryptor::PARALLEL_THRESHOLD + 13)
+            .map(|i| (i * 31 % 251) as u8)
+            .collect();
+
+        // 覆盖不同长度和非 8 字节对齐的起始位置，以及并行路径
+        for (start, end) in [(0, 0), (0, 7), (3, 20), (1, 4099), (0, data.len())] {
+            let slice = &data[start..end];
+            let expected: Vec<u8> = slice.iter().map(|&b| b ^ 0xA7).collect();
+            let encrypted = V3Decryptor::xor_decrypt(slice, 0xA7);
+            assert_eq!(encrypted, expected);
+            assert_eq!(V3Decryptor::xor_decrypt(&encrypted, 0xA7), slice);
+        }
+
+        assert_eq!(V3Decryptor::xor_decrypt(&data[..9], 0), &data[..9]);
+
+        let mut in_place = data.clone();
+        V3Decryptor::xor_in_place(&mut in_place, 0xA7);
+        assert_eq!(in_place, V3Decryptor::xor_decrypt(&data, 0xA7));
+        V3Decryptor::xor_in_place(&mut in_place[3..20], 0xA7);
+        assert_eq!(in_place[3..20], data[3..20]);
+    }
+
+    #[test]
+    fn test_detect_xor_key() {
+        for plain in [&[0xFF, 0xD8, 0xFF, 0xE0][..], b"\x89PNG", b"GIF89a"] {
+            let encrypted = V3Decryptor::xor_decrypt(plain, 0x5A);
+            assert_eq!(V3Decryptor::detect_xor_key(&encrypted), Some(0x5A));
+            assert_eq!(V3Decryptor::detect_xor_key(&encrypted[..2]), Some(0x5A));
+        }
+
+        assert_eq!(V3Decryptor::detect_xor_key(&[]), None);
+        assert_eq!(V3Decryptor::detect_xor_key(&[0xA5]), None);
+        assert_eq!(V3Decryptor::detect_xor_key(&[0x01, 0x02, 0x03]), None);
+    }
 }
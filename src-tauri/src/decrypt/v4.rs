@@ -3,15 +3,27 @@
 use super::aes::AesHandler;
 use super::error::DecryptError;
 use super::v3::V3Decryptor;
+use super::version::{DatVersion, VersionDetector};
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 use std::path::Path;
 
 /// v4 版本文件头结构
+///
+/// 共 15 字节，V1 和 V2 布局相同：
+///
+/// | 偏移 | 长度 | 内容 |
+/// |------|------|------|
+/// | 0    | 6    | 签名 `\x07\x08V1\x08\x07` 或 `\x07\x08V2\x08\x07` |
+/// | 6    | 4    | AES 段明文长度 (小端)，密文按 PKCS7 对齐 |
+/// | 10   | 4    | 文件末尾 XOR 段长度 (小端) |
+/// | 14   | 1    | 未使用 |
+///
+/// 文件头之后依次为 AES 段、原始段 (未加密) 和 XOR 段。文件头中没有密钥索引等
+/// 额外字段，AES 密钥完全由调用方提供。
 #[derive(Debug)]
 pub struct V4Header {
     /// 签名 (6 字节)
-    #[allow(dead_code)] // 保留签名字段用于调试和验证
     pub signature: [u8; 6],
     /// AES 加密部分大小
     pub aes_size: u32,
@@ -24,6 +36,8 @@ impl V4Header {
     pub const SIZE: usize = 15;
 
     /// 从字节数组解析文件头
+    ///
+    /// 签名不是已知的 v4 签名时返回 [`DecryptError::HeaderParseError`]。
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecryptError> {
         if bytes.len() < Self::SIZE {
             return Err(DecryptError::HeaderParseError);
@@ -31,6 +45,12 @@ impl V4Header {
 
         let mut signature = [0u8; 6];
         signature.copy_from_slice(&bytes[0..6]);
+        if signature != VersionDetector::V4_V1_SIGNATURE
+            && signature != VersionDetector::V4_V2_SIGNATURE
+        {
+            log::warn!("未知的 v4 文件头签名: {:02x?}", signature);
+            return Err(DecryptError::HeaderParseError);
+        }
 
         let aes_size = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
         let xor_size = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
@@ -41,6 +61,28 @@ impl V4Header {
             xor_size,
         })
     }
+
+    /// 文件头签名对应的版本
+    pub fn version(&self) -> DatVersion {
+        VersionDetector::from_signature(&self.signature)
+    }
+
+    /// 检查各段大小不超过文件头之后的实际数据长度
+    ///
+    /// 截断的文件仍允许解密已有部分，但声明的大小超过整个文件时说明文件头已损坏，
+    /// 按这些大小分配内存可能耗尽内存。
+    pub fn validate_sizes(&self, data_len: u64) -> Result<(), DecryptError> {
+        if self.aes_size as u64 > data_len || self.xor_size as u64 > data_len {
+            log::warn!(
+                "v4 文件头段大小超出文件长度: AES {}, XOR {}, 数据 {} 字节",
+                self.aes_size,
+                self.xor_size,
+                data_len
+            );
+            return Err(DecryptError::HeaderParseError);
+        }
+        Ok(())
+    }
 }
 
 /// v4 版本解密器
@@ -52,29 +94,81 @@ impl V4Decryptor {
     /// # 参数
     ///
     /// * `input_path` - 输入文件路径
+    /// * `version` - 版本检测得到的 v4 子版本，须与文件头签名一致
     /// * `xor_key` - XOR 密钥
-    /// * `aes_key` - AES 密钥 (16 字节)
+    /// * `aes_key` - AES 密钥 (16 或 32 字节)
     ///
     /// # 返回
     ///
     /// 解密后的字节数据
     pub fn decrypt<P: AsRef<Path>>(
         input_path: P,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        let input_path = input_path.as_ref();
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| Self::decrypt_file(&mut file, version, xor_key, aes_key))
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    /// 从已打开的文件句柄解密 v4 数据
+    ///
+    /// 文件位置必须位于文件头起始处。
+    pub fn decrypt_file(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        Self::decrypt_file_with_sink(file, version, xor_key, aes_key, &mut |_| {})
+    }
+
+    /// 从已打开的文件句柄解密 v4 数据，每解密出一段 (AES / 原始 / XOR) 就交给 `sink`
+    ///
+    /// 文件位置必须位于文件头起始处。
+    pub fn decrypt_file_with_sink(
+        file: &mut File,
+        version: DatVersion,
         xor_key: u8,
         aes_key: &[u8],
+        sink: &mut dyn FnMut(&[u8]),
     ) -> Result<Vec<u8>, DecryptError> {
-        if aes_key.len() != 16 {
+        Self::decrypt_file_inner(file, version, xor_key, aes_key, false, sink)
+    }
+
+    /// 尽力解密被截断的 v4 文件，用于从损坏的缓存中恢复数据
+    ///
+    /// AES 段被截断时只解密其中完整的块，不校验填充；原始段和 XOR 段能读多少读多少。
+    /// 文件完整时与 [`V4Decryptor::decrypt_file`] 结果相同。文件位置必须位于文件头起始处。
+    pub fn decrypt_file_best_effort(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        Self::decrypt_file_inner(file, version, xor_key, aes_key, true, &mut |_| {})
+    }
+
+    /// `best_effort` 为 false 时截断的文件返回错误，为 true 时尽量解密已有部分
+    fn decrypt_file_inner(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+        best_effort: bool,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> Result<Vec<u8>, DecryptError> {
+        if !AesHandler::is_valid_key_len(aes_key.len()) {
             return Err(DecryptError::AesDecryptError(
-                "AES 密钥必须为 16 字节".to_string(),
+                "AES 密钥必须为 16 或 32 字节".to_string(),
             ));
         }
 
-        let mut file = File::open(input_path)?;
-
         // 读取文件头
-        let mut header_bytes = [0u8; V4Header::SIZE];
-        file.read_exact(&mut header_bytes)?;
-        let header = V4Header::from_bytes(&header_bytes)?;
+        let header = Self::read_header(file, version)?;
 
         log::debug!(
             "解密 v4 DAT 文件,AES 大小: {}, XOR 大小: {}",
@@ -83,31 +177,190 @@ impl V4Decryptor {
         );
 
         // 解密 AES 部分
-        let decrypted_aes = Self::decrypt_aes_section(&mut file, &header, aes_key)?;
+        let decrypted_aes = Self::decrypt_aes_section(file, &header, aes_key, best_effort)?;
+        sink(&decrypted_aes);
 
         // 处理剩余数据
-        let result = Self::decrypt_remaining_sections(&mut file, &header, xor_key, decrypted_aes)?;
+        let result = Self::decrypt_remaining_sections(
+            file,
+            &header,
+            xor_key,
+            decrypted_aes,
+            best_effort,
+            sink,
+        )?;
 
         log::debug!("v4 解密完成,总大小: {} 字节", result.len());
 
         Ok(result)
     }
 
+    /// 从已打开的文件句柄解密 v4 数据并写入 `writer`，返回写入的字节数
+    ///
+    /// AES 段一次性解密，原始段和 XOR 段按 `chunk_size` 分块读写，内存占用与文件大小无关。
+    /// 文件位置必须位于文件头起始处。
+    pub fn decrypt_file_to_writer(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+        writer: &mut dyn Write,
+        chunk_size: usize,
+    ) -> Result<u64, DecryptError> {
+        let header = Self::read_header(file, version)?;
+
+        let decrypted_aes = Self::decrypt_aes_section(file, &header, aes_key, false)?;
+        writer.write_all(&decrypted_aes)?;
+
+        let xor_size = header.xor_size as u64;
+        let raw_len = Self::raw_section_len(file, &header, false)?;
+
+        let raw_written = V3Decryptor::xor_copy(&mut file.take(raw_len), writer, 0, chunk_size)?;
+        let xor_written =
+            V3Decryptor::xor_copy(&mut file.take(xor_size), writer, xor_key, chunk_size)?;
+
+        Ok(decrypted_aes.len() as u64 + raw_written + xor_written)
+    }
+
+    /// 只解密明文的前 `len` 字节 (可能更少)，不读取文件其余部分
+    ///
+    /// AES 段总是完整解密，原始段和 XOR 段只读取需要的部分。
+    /// 文件位置必须位于文件头起始处。
+    pub fn decrypt_prefix_file(
+        file: &mut File,
+        version: DatVersion,
+        xor_key: u8,
+        aes_key: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, DecryptError> {
+        let header = Self::read_header(file, version)?;
+
+        let mut result = Self::decrypt_aes_section(file, &header, aes_key, false)?;
+        if result.len() >= len {
+            result.truncate(len);
+            return Ok(result);
+        }
+
+        let xor_size = header.xor_size as u64;
+        let raw_len = Self::raw_section_len(file, &header, false)?;
+
+        let wanted = (len - result.len()) as u64;
+        file.take(raw_len.min(wanted)).read_to_end(&mut result)?;
+
+        if result.len() < len {
+            let mut xor_data = Vec::new();
+            file.take(xor_size.min((len - result.len()) as u64))
+                .read_to_end(&mut xor_data)?;
+            result.extend(V3Decryptor::xor_decrypt(&xor_data, xor_key));
+        }
+
+        Ok(result)
+    }
+
+    /// 只解密文件头之后的 AES 部分，用于快速判断密钥是否正确
+    ///
+    /// 文件位置必须位于文件头起始处。
+    pub fn decrypt_head_file(
+        file: &mut File,
+        version: DatVersion,
+        aes_key: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        let header = Self::read_header(file, version)?;
+
+        Self::decrypt_aes_section(file, &header, aes_key, false)
+    }
+
+    /// 读取并校验文件头
+    ///
+    /// 文件头签名必须与检测到的版本一致，各段大小不能超过文件实际长度。
+    /// 文件位置必须位于文件头起始处，返回时位于文件头之后。
+    fn read_header(file: &mut File, version: DatVersion) -> Result<V4Header, DecryptError> {
+        let mut header_bytes = [0u8; V4Header::SIZE];
+        file.read_exact(&mut header_bytes)?;
+        let header = V4Header::from_bytes(&header_bytes)?;
+
+        // 签名与版本不一致说明检测与解析不同步
+        if header.version() != version {
+            log::warn!(
+                "v4 文件头签名与检测版本不一致: 期望 {:?}, 实际 {:?}",
+                version,
+                header.version()
+            );
+            return Err(DecryptError::HeaderParseError);
+        }
+
+        let data_len = file
+            .metadata()?
+            .len()
+            .saturating_sub(file.stream_position()?);
+        header.validate_sizes(data_len)?;
+
+        Ok(header)
+    }
+
     /// 解密 AES 加密部分
+    ///
+    /// 默认要求 AES 段完整并校验 PKCS7 填充；`best_effort` 为 true 时
+    /// 截断的 AES 段只解密完整的块。
     fn decrypt_aes_section(
         file: &mut File,
         header: &V4Header,
         aes_key: &[u8],
+        best_effort: bool,
     ) -> Result<Vec<u8>, DecryptError> {
         // 计算 AES 对齐后的大小
         let aes_size_aligned = AesHandler::align_size(header.aes_size as usize);
 
-        // 读取 AES 加密部分
-        let mut aes_data = vec![0u8; aes_size_aligned];
-        file.read_exact(&mut aes_data)?;
+        // 读取 AES 加密部分，文件被截断时能读多少读多少
+        let mut aes_data = Vec::new();
+        file.take(aes_size_aligned as u64)
+            .read_to_end(&mut aes_data)?;
 
-        // 解密 AES 部分
-        AesHandler::decrypt_ecb(&aes_data, aes_key)
+        if aes_data.len() == aes_size_aligned {
+            return AesHandler::decrypt_ecb(&aes_data, aes_key);
+        }
+        if !best_effort {
+            log::warn!(
+                "v4 AES 段被截断: 期望 {} 字节, 实际 {} 字节",
+                aes_size_aligned,
+                aes_data.len()
+            );
+            return Err(DecryptError::InvalidFormat);
+        }
+
+        // 截断的 AES 段没有填充块，只解密完整的块
+        let complete = aes_data.len() - aes_data.len() % AesHandler::BLOCK_SIZE;
+        log::warn!(
+            "v4 AES 段被截断: 期望 {} 字节, 实际 {} 字节, 只解密前 {} 字节",
+            aes_size_aligned,
+            aes_data.len(),
+            complete
+        );
+        aes_data.truncate(complete);
+        AesHandler::decrypt_ecb_blocks(&aes_data, aes_key)
+    }
+
+    /// 当前位置之后原始段的长度
+    ///
+    /// 剩余数据不足 XOR 段长度说明文件被截断：默认返回错误，`best_effort` 为 true 时视为没有原始段。
+    fn raw_section_len(
+        file: &mut File,
+        header: &V4Header,
+        best_effort: bool,
+    ) -> Result<u64, DecryptError> {
+        let remaining = file.metadata()?.len() - file.stream_position()?;
+        match remaining.checked_sub(header.xor_size as u64) {
+            Some(raw_len) => Ok(raw_len),
+            None if best_effort => Ok(0),
+            None => {
+                log::warn!(
+                    "v4 XOR 段被截断: 期望 {} 字节, 剩余 {} 字节",
+                    header.xor_size,
+                    remaining
+                );
+                Err(DecryptError::InvalidFormat)
+            }
+        }
     }
 
     /// 解密剩余部分 (原始数据 + XOR 数据)
@@ -116,15 +369,15 @@ impl V4Decryptor {
         header: &V4Header,
         xor_key: u8,
         mut result: Vec<u8>,
+        best_effort: bool,
+        sink: &mut dyn FnMut(&[u8]),
     ) -> Result<Vec<u8>, DecryptError> {
         let xor_size = header.xor_size as usize;
 
         if xor_size > 0 {
             // 读取中间的原始数据
             let mut raw_data = Vec::new();
-            let current_pos = file.stream_position()?;
-            let file_len = file.metadata()?.len();
-            let raw_len = file_len - current_pos - xor_size as u64;
+            let raw_len = Self::raw_section_len(file, header, best_effort)?;
 
             if raw_len > 0 {
                 let mut buffer = vec![0u8; raw_len as usize];
@@ -133,20 +386,158 @@ impl V4Decryptor {
             }
 
             // 读取并解密 XOR 部分
-            let mut xor_data = vec![0u8; xor_size];
-            file.read_exact(&mut xor_data)?;
+            let mut xor_data = Vec::new();
+            file.take(xor_size as u64).read_to_end(&mut xor_data)?;
             let xored_data = V3Decryptor::xor_decrypt(&xor_data, xor_key);
 
             // 组合所有部分
+            sink(&raw_data);
+            sink(&xored_data);
             result.extend_from_slice(&raw_data);
             result.extend_from_slice(&xored_data);
         } else {
             // 没有 XOR 部分,读取剩余原始数据
             let mut raw_data = Vec::new();
             file.read_to_end(&mut raw_data)?;
+            sink(&raw_data);
             result.extend_from_slice(&raw_data);
         }
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+
+    #[test]
+    fn test_signature_mismatch_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let plain: Vec<u8> = (0..64u8).collect();
+        let fixture = build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &plain, 20, 10, 0x56);
+        std::fs::write(&path, fixture).unwrap();
+
+        let result = V4Decryptor::decrypt(&path, DatVersion::V4V1, 0x56, TEST_AES_KEY);
+        assert!(matches!(
+            result,
+            Err(DecryptError::WithPath { source, .. }) if matches!(*source, DecryptError::HeaderParseError)
+        ));
+
+        let result = V4Decryptor::decrypt(&path, DatVersion::V4V2, 0x56, TEST_AES_KEY);
+        assert_eq!(result.unwrap(), plain);
+    }
+
+    #[test]
+    fn test_invalid_header_is_rejected() {
+        let mut bytes = [0u8; V4Header::SIZE];
+        bytes[..6].copy_from_slice(b"\x07\x08V9\x08\x07");
+        assert!(matches!(
+            V4Header::from_bytes(&bytes),
+            Err(DecryptError::HeaderParseError)
+        ));
+
+        // 声明的段大小超过文件长度时拒绝解密，而不是按声明大小读取
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let plain: Vec<u8> = (0..64u8).collect();
+        for size_offset in [6, 10] {
+            let mut fixture =
+                build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 20, 10, 0x56);
+            fixture[size_offset..size_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            std::fs::write(&path, fixture).unwrap();
+
+            let result = V4Decryptor::decrypt(&path, DatVersion::V4V1, 0x56, TEST_AES_KEY);
+            assert!(matches!(
+                result,
+                Err(DecryptError::WithPath { source, .. }) if matches!(*source, DecryptError::HeaderParseError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_block_aligned_aes_section_keeps_cursor_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let plain: Vec<u8> = (0..80u8).collect();
+
+        // 32 字节明文加密后为 48 字节 (含一整块填充)，之后的原始段和 XOR 段要从正确位置开始
+        for aes_size in [16, 32] {
+            let fixture =
+                build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, aes_size, 16, 0x56);
+            assert_eq!(
+                fixture.len(),
+                V4Header::SIZE + AesHandler::align_size(aes_size) + plain.len() - aes_size
+            );
+            std::fs::write(&path, fixture).unwrap();
+
+            let result = V4Decryptor::decrypt(&path, DatVersion::V4V1, 0x56, TEST_AES_KEY);
+            assert_eq!(result.unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn test_truncated_aes_section_keeps_complete_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let plain: Vec<u8> = (0..64u8).collect();
+        let mut fixture = build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 40, 10, 0x56);
+
+        // AES 段对齐后 48 字节，截断到第三块中间
+        fixture.truncate(V4Header::SIZE + 40);
+        std::fs::write(&path, fixture).unwrap();
+
+        // 默认严格解密，截断的文件报错
+        let result = V4Decryptor::decrypt(&path, DatVersion::V4V1, 0x56, TEST_AES_KEY);
+        assert!(matches!(
+            result,
+            Err(DecryptError::WithPath { source, .. }) if matches!(*source, DecryptError::InvalidFormat)
+        ));
+
+        let mut out = Vec::new();
+        let mut file = File::open(&path).unwrap();
+        let result = V4Decryptor::decrypt_file_to_writer(
+            &mut file,
+            DatVersion::V4V1,
+            0x56,
+            TEST_AES_KEY,
+            &mut out,
+            16,
+        );
+        assert!(matches!(result, Err(DecryptError::InvalidFormat)));
+
+        // 尽力解密时保留完整的块
+        let mut file = File::open(&path).unwrap();
+        let result =
+            V4Decryptor::decrypt_file_best_effort(&mut file, DatVersion::V4V1, 0x56, TEST_AES_KEY)
+                .unwrap();
+        assert_eq!(result, plain[..32]);
+    }
+
+    #[test]
+    fn test_truncated_xor_section_is_strict_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dat");
+        let plain: Vec<u8> = (0..64u8).collect();
+        let mut fixture = build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 16, 40, 0x56);
+
+        // AES 段完整 (32 字节)，之后只剩 XOR 段的前 20 字节
+        fixture.truncate(V4Header::SIZE + 32 + 20);
+        std::fs::write(&path, fixture).unwrap();
+
+        let result = V4Decryptor::decrypt(&path, DatVersion::V4V1, 0x56, TEST_AES_KEY);
+        assert!(matches!(
+            result,
+            Err(DecryptError::WithPath { source, .. }) if matches!(*source, DecryptError::InvalidFormat)
+        ));
+
+        let mut file = File::open(&path).unwrap();
+        let result =
+            V4Decryptor::decrypt_file_best_effort(&mut file, DatVersion::V4V1, 0x56, TEST_AES_KEY)
+                .unwrap();
+        assert_eq!(result[..16], plain[..16]);
+        assert_eq!(result.len(), 36);
+    }
+}
@@ -11,6 +11,11 @@ use std::path::Path;
 /// - V3: 无签名，仅使用 XOR 加密
 /// - V4V1: 带 `\x07\x08V1\x08\x07` 签名，使用固定 AES + XOR 混合加密
 /// - V4V2: 带 `\x07\x08V2\x08\x07` 签名，使用动态 AES + XOR 混合加密
+///
+/// 两种 v4 子版本的文件结构和解密流程完全相同 (见 [`super::v4::V4Header`])，
+/// 区别只在 AES 密钥的来源："固定" 指所有文件共用同一个密钥，"动态" 指密钥随账号
+/// 不同，需要由用户从自己的微信客户端获取。文件本身不携带任何用于推导密钥的数据，
+/// 因此两者都使用调用方提供的 AES 密钥解密；密钥错误时会得到填充校验失败。
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DatVersion {
     /// v3 版本 (仅 XOR 加密，无签名)
@@ -43,18 +48,35 @@ impl VersionDetector {
     ///
     /// 返回检测到的 DAT 版本
     pub fn detect<P: AsRef<Path>>(input_path: P) -> Result<DatVersion, DecryptError> {
-        let mut file = File::open(input_path)?;
+        let input_path = input_path.as_ref();
+        File::open(input_path)
+            .map_err(DecryptError::from)
+            .and_then(|mut file| Self::detect_from(&mut file))
+            .map_err(|e| e.with_path(input_path))
+    }
+
+    /// 从已打开的读取器检测 DAT 文件版本
+    ///
+    /// 会从读取器当前位置消费最多 6 字节，调用方需自行复位读取位置。
+    pub fn detect_from<R: Read>(reader: &mut R) -> Result<DatVersion, DecryptError> {
         let mut signature = [0u8; 6];
 
         // 尝试读取签名，如果失败或不匹配，则为 V3 (无签名)
-        if file.read_exact(&mut signature).is_err() {
+        if reader.read_exact(&mut signature).is_err() {
             return Ok(DatVersion::V3);
         }
 
-        match &signature {
-            s if s == Self::V4_V1_SIGNATURE => Ok(DatVersion::V4V1),
-            s if s == Self::V4_V2_SIGNATURE => Ok(DatVersion::V4V2),
-            _ => Ok(DatVersion::V3), // 无签名视为 V3
+        Ok(Self::from_signature(&signature))
+    }
+
+    /// 根据文件开头的 6 字节签名判断版本
+    ///
+    /// 版本检测与 v4 文件头校验共用此函数，保证两处对签名的解释一致。
+    pub fn from_signature(signature: &[u8]) -> DatVersion {
+        match signature {
+            s if s == Self::V4_V1_SIGNATURE => DatVersion::V4V1,
+            s if s == Self::V4_V2_SIGNATURE => DatVersion::V4V2,
+            _ => DatVersion::V3, // 无签名视为 V3
         }
     }
 }
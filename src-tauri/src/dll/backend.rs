@@ -0,0 +1,164 @@
+//! WXGF / WXAM 解码后端
+//!
+//! Windows 上通过 VoipEngine.dll 解码 HEVC 码流；其他平台没有 DLL，
+//! 只能处理 WXGF 头后面直接包裹 JPEG / PNG / GIF 的数据，其余情况返回
+//! `AppError::UnsupportedImageFormat`，调用方据此回退为原始数据。
+
+use crate::error::AppError;
+use std::io::Cursor;
+
+/// WXGF 转换后端，`format` 为目标格式 ("jpeg"、"gif" 或 "png")
+pub trait WxamBackend: Send + Sync {
+    /// 后端名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 把 WXGF 数据转换为指定格式的图片
+    fn convert(&self, data: &[u8], format: &str) -> Result<Vec<u8>, AppError>;
+}
+
+/// 调用 VoipEngine.dll 的后端
+#[cfg(windows)]
+pub struct DllBackend;
+
+#[cfg(windows)]
+impl WxamBackend for DllBackend {
+    fn name(&self) -> &'static str {
+        "VoipEngine.dll"
+    }
+
+    fn convert(&self, data: &[u8], format: &str) -> Result<Vec<u8>, AppError> {
+        super::wxam_to_image(data, format)
+    }
+}
+
+/// 纯 Rust 后端：在 WXGF 头之后查找内嵌的标准图片并用 `image` 库转换
+///
+/// 不能解码 HEVC 码流，普通 WXGF 表情会返回 `UnsupportedImageFormat`。
+pub struct PayloadBackend;
+
+impl WxamBackend for PayloadBackend {
+    fn name(&self) -> &'static str {
+        "内嵌图片"
+    }
+
+    fn convert(&self, data: &[u8], format: &str) -> Result<Vec<u8>, AppError> {
+        let target = match format.to_lowercase().as_str() {
+            "jpeg" | "jpg" => image::ImageFormat::Jpeg,
+            "gif" => image::ImageFormat::Gif,
+            "png" => image::ImageFormat::Png,
+            _ => return Err(AppError::UnsupportedImageFormat(format.to_string())),
+        };
+
+        let (payload, source) = find_embedded_image(data).ok_or_else(|| {
+            AppError::UnsupportedImageFormat("WXGF (HEVC) 需要 VoipEngine.dll 解码".to_string())
+        })?;
+
+        // 格式相同时原样返回，保留 GIF 动画
+        if source == target {
+            return Ok(payload.to_vec());
+        }
+
+        let img = image::load_from_memory_with_format(payload, source)
+            .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?;
+        // JPEG 不支持透明通道
+        let img = if target == image::ImageFormat::Jpeg {
+            image::DynamicImage::ImageRgb8(img.to_rgb8())
+        } else {
+            img
+        };
+
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, target)
+            .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?;
+        Ok(out.into_inner())
+    }
+}
+
+/// 内嵌图片的文件头
+const EMBEDDED_SIGNATURES: &[(&[u8], image::ImageFormat)] = &[
+    (&[0xFF, 0xD8, 0xFF], image::ImageFormat::Jpeg),
+    (
+        &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A],
+        image::ImageFormat::Png,
+    ),
+    (b"GIF87a", image::ImageFormat::Gif),
+    (b"GIF89a", image::ImageFormat::Gif),
+];
+
+/// 在 WXGF 魔数之后查找第一个能被 `image` 库解析的内嵌图片，返回从文件头到数据末尾的切片
+///
+/// HEVC 码流中可能偶然出现相同字节，因此每个候选位置都会实际解码一次确认。
+pub fn find_embedded_image(data: &[u8]) -> Option<(&[u8], image::ImageFormat)> {
+    if data.len() < 4 || !(&data[..4] == b"wxgf" || &data[..4] == b"WXGF") {
+        return None;
+    }
+
+    (4..data.len()).find_map(|offset| {
+        let rest = &data[offset..];
+        let (_, format) = EMBEDDED_SIGNATURES
+            .iter()
+            .find(|(magic, _)| rest.starts_with(magic))?;
+        image::load_from_memory_with_format(rest, *format)
+            .is_ok()
+            .then_some((rest, *format))
+    })
+}
+
+/// 当前平台的 WXGF 转换后端：Windows 使用 DLL，其他平台使用纯 Rust 后端
+pub fn backend() -> &'static dyn WxamBackend {
+    #[cfg(windows)]
+    {
+        &DllBackend
+    }
+
+    #[cfg(not(windows))]
+    {
+        &PayloadBackend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(img: image::RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_payload_backend_converts_embedded_image() {
+        let png = encode(
+            image::RgbaImage::from_pixel(3, 2, image::Rgba([10, 20, 30, 255])),
+            image::ImageFormat::Png,
+        );
+        let mut data = b"wxgf\x00\x01\x02\x03".to_vec();
+        data.extend_from_slice(&png);
+
+        let backend = PayloadBackend;
+        assert_eq!(backend.convert(&data, "png").unwrap(), png);
+
+        let jpeg = backend.convert(&data, "jpeg").unwrap();
+        assert!(jpeg.starts_with(&[0xFF, 0xD8, 0xFF]));
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+
+        assert!(matches!(
+            backend.convert(&data, "bmp"),
+            Err(AppError::UnsupportedImageFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_payload_backend_rejects_plain_hevc() {
+        // 偶然出现的 JPEG 文件头无法解码，不应被当作内嵌图片
+        let data = b"wxgf\x00\x00\x00\x01\x40\x01\xFF\xD8\xFF\x12\x34".to_vec();
+        assert!(find_embedded_image(&data).is_none());
+        assert!(find_embedded_image(b"not wxgf").is_none());
+        assert!(matches!(
+            PayloadBackend.convert(&data, "jpeg"),
+            Err(AppError::UnsupportedImageFormat(_))
+        ));
+    }
+}
@@ -1,7 +1,17 @@
 //! DLL 相关模块
 //!
-//! 包含与 Windows DLL 交互的功能，例如 WXAM 图片解码
+//! 包含与 Windows DLL 交互的功能，例如 WXAM 图片解码；
+//! 以及不依赖 DLL、各平台通用的 WXGF 文件头解析、PNG 转换、解码后端和 DLL 调用线程池
 
+pub mod backend;
+pub mod png;
+pub mod worker_pool;
+#[cfg(windows)]
 pub mod wxam_decoder;
+pub mod wxgf_header;
 
+pub use backend::{backend, WxamBackend};
+pub use worker_pool::{pool, DllWorkerPool};
+#[cfg(windows)]
 pub use wxam_decoder::{wxam_to_image, ImageFormat, WxAMDecoder};
+pub use wxgf_header::{parse_wxgf_header, WxgfInfo};
@@ -0,0 +1,59 @@
+//! DLL 输出的 PNG 转换
+//!
+//! VoipEngine.dll 只能输出 JPEG 和 GIF，其中只有 GIF 保留透明信息。
+//! 该模块把 GIF 结果重新编码为 PNG，供需要透明通道的静态图使用，各平台通用。
+//!
+//! PNG 来自 GIF 的调色板图像：最多 256 色，透明度只有全透明和不透明两级，
+//! 因此并非无损输出，颜色和半透明边缘都已在 DLL 的 GIF 编码中被量化。
+
+use crate::error::AppError;
+use std::io::Cursor;
+
+/// 把 GIF 数据的首帧重新编码为 PNG，保留透明像素 (1 位透明度，最多 256 色)
+pub fn gif_to_png(gif: &[u8]) -> Result<Vec<u8>, AppError> {
+    let img = image::load_from_memory_with_format(gif, image::ImageFormat::Gif)
+        .map_err(|e| AppError::WxamDecodeFailed(format!("无法解析 GIF 输出: {}", e)))?;
+
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, image::ImageFormat::Png)
+        .map_err(|e| AppError::WxamDecodeFailed(format!("PNG 编码失败: {}", e)))?;
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(img: image::RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    fn has_transparency(png: &[u8]) -> bool {
+        image::load_from_memory_with_format(png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+            .pixels()
+            .any(|pixel| pixel[3] < u8::MAX)
+    }
+
+    #[test]
+    fn test_gif_to_png_keeps_transparency() {
+        let mut img = image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 10, 255]));
+        img.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+        let gif = encode(img, image::ImageFormat::Gif);
+
+        let png = gif_to_png(&gif).unwrap();
+        assert!(png.starts_with(&[0x89, b'P', b'N', b'G']));
+        assert!(has_transparency(&png));
+
+        let opaque = encode(
+            image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 10, 255])),
+            image::ImageFormat::Gif,
+        );
+        assert!(!has_transparency(&gif_to_png(&opaque).unwrap()));
+
+        assert!(gif_to_png(b"not a gif").is_err());
+    }
+}
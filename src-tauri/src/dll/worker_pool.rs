@@ -0,0 +1,132 @@
+//! DLL 调用专用线程池
+//!
+//! WXGF 转换每次调用都会让 DLL 分配很大的输出缓冲区，并发过高时容易耗尽内存。
+//! 所有 DLL 调用都提交到这个固定大小的线程池执行，与普通解密的并发限制互不影响。
+
+use crate::error::AppError;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// 全局线程池的工作线程数
+pub const DLL_POOL_SIZE: usize = 2;
+
+/// 全局线程池的排队任务上限，队列满时提交方阻塞等待
+pub const DLL_QUEUE_SIZE: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// 固定大小、有界队列的线程池
+pub struct DllWorkerPool {
+    sender: SyncSender<Job>,
+    size: usize,
+}
+
+impl DllWorkerPool {
+    /// 创建包含 `size` 个工作线程、最多排队 `queue` 个任务的线程池
+    pub fn new(size: usize, queue: usize) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..size {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("dll-worker-{}", index))
+                .spawn(move || Self::worker_loop(&receiver))
+                .expect("无法创建 DLL 工作线程");
+        }
+
+        Self { sender, size }
+    }
+
+    /// 工作线程数
+    #[allow(dead_code)] // 供调用方查询并发上限
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 在线程池中执行 `job` 并阻塞等待结果
+    ///
+    /// 任务发生 panic 时返回错误，工作线程继续处理后续任务。
+    pub fn run<T, F>(&self, job: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (result_tx, result_rx) = mpsc::channel();
+
+        self.sender
+            .send(Box::new(move || {
+                let _ = result_tx.send(job());
+            }))
+            .map_err(|_| AppError::Internal("DLL 线程池已关闭".to_string()))?;
+
+        result_rx
+            .recv()
+            .map_err(|_| AppError::Internal("DLL 任务执行失败".to_string()))
+    }
+
+    fn worker_loop(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            // 只在取任务时持有锁，执行任务时其他线程可以继续取任务
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(job)).is_err() {
+                log::error!("DLL 任务发生 panic");
+            }
+        }
+    }
+}
+
+/// 全局 DLL 线程池
+pub fn pool() -> &'static DllWorkerPool {
+    static POOL: OnceLock<DllWorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| DllWorkerPool::new(DLL_POOL_SIZE, DLL_QUEUE_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_concurrency_never_exceeds_pool_size() {
+        let pool = Arc::new(DllWorkerPool::new(2, 4));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let callers: Vec<_> = (0..16)
+            .map(|i| {
+                let pool = Arc::clone(&pool);
+                let active = Arc::clone(&active);
+                let peak = Arc::clone(&peak);
+                thread::spawn(move || {
+                    pool.run(move || {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(5));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        i * 2
+                    })
+                    .unwrap()
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = callers.into_iter().map(|c| c.join().unwrap()).collect();
+        assert_eq!(results, (0..16).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= pool.size());
+    }
+
+    #[test]
+    fn test_panicking_job_does_not_kill_worker() {
+        let pool = DllWorkerPool::new(1, 1);
+        assert!(pool.run(|| -> u8 { panic!("boom") }).is_err());
+        assert_eq!(pool.run(|| 7).unwrap(), 7);
+    }
+}
@@ -3,8 +3,10 @@
 //! 该模块提供了将微信 WXAM 格式文件转换为标准图片格式(JPEG/GIF)的功能。
 
 use crate::error::AppError;
-use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{OnceLock, RwLock};
 use windows::core::PCWSTR;
 use windows::Win32::Foundation::HMODULE;
 use windows::Win32::System::LibraryLoader::LoadLibraryW;
@@ -21,14 +23,27 @@ pub enum ImageFormat {
 
 /// WXAM 解码配置结构体
 #[repr(C)]
+#[derive(Debug)]
 struct WxAMConfig {
     /// 解码模式
     mode: i32,
-    /// 保留字段
+    /// 保留字段 (附加标志)
+    ///
+    /// 目前只验证过 0 (默认行为)。DLL 可能通过该字段接受质量、速度或透明通道
+    /// 相关的标志，但其他取值的效果尚无记录，仅原样传给 DLL 供高级用户试验。
     reserved: i32,
 }
 
 /// DLL 函数指针类型
+///
+/// 调用约定 (按实际行为整理，DLL 无公开文档):
+///
+/// - `output_size_ptr` 传入时为输出缓冲区容量，成功时被改写为实际输出字节数
+/// - 返回 0 表示成功，非 0 为错误代码
+/// - DLL 不支持用空指针查询所需大小
+///
+/// 尚未验证 DLL 是否把传入的容量当作写入上限，因此始终传入
+/// [`WxAMDecoder::MAX_OUTPUT_SIZE`] 大小的缓冲区，不按输入大小缩小。
 type WxamDecFunction = unsafe extern "system" fn(
     input_addr: i64,
     input_size: i32,
@@ -54,17 +69,33 @@ unsafe impl Sync for DllHolder {}
 // 全局 DLL 实例
 static DLL_INSTANCE: OnceLock<Result<DllHolder, AppError>> = OnceLock::new();
 
+// 默认解码使用的附加标志，0 表示保持原有行为
+static EXTRA_FLAGS: AtomicI32 = AtomicI32::new(0);
+
+// 用户指定的 DLL 所在目录，优先于可执行文件目录查找
+static CUSTOM_DLL_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+thread_local! {
+    // 每个线程复用的输出缓冲区，首次解码时按 MAX_OUTPUT_SIZE 分配
+    //
+    // 解码只在 DLL 线程池中进行，缓冲区数量以线程池大小为上限
+    static OUTPUT_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
 /// WXAM 格式解码器
 ///
 /// 负责加载 DLL 并提供 WXAM 到图片格式的转换功能。
 pub struct WxAMDecoder;
 
 impl WxAMDecoder {
-    /// 最大输出大小 (52MB)
-    const MAX_OUTPUT_SIZE: usize = 52 * 1024 * 1024;
+    /// 最大输出大小 (52MB)，每次解码传给 DLL 的缓冲区容量
+    pub const MAX_OUTPUT_SIZE: usize = 52 * 1024 * 1024;
 
     /// DLL 文件名
-    const DLL_NAME: &'static str = "VoipEngine.dll";
+    pub const DLL_NAME: &'static str = "VoipEngine.dll";
+
+    /// 解码函数的导出符号名
+    pub const SYMBOL_NAME: &'static str = "wxam_dec_wxam2pic_5";
 
     /// 加载 VoipEngine.dll
     fn load_dll() -> Result<&'static DllHolder, AppError> {
@@ -74,18 +105,76 @@ impl WxAMDecoder {
             .map_err(|e| e.clone())
     }
 
-    /// 内部 DLL 加载实现
-    fn load_dll_internal() -> Result<DllHolder, AppError> {
-        // 获取 DLL 路径
-        let dll_path = std::env::current_exe()
+    /// DLL 所在路径
+    ///
+    /// 返回 [`Self::search_paths`] 中第一个存在的路径；都不存在时返回优先级最高的路径。
+    pub fn dll_path() -> PathBuf {
+        let candidates = Self::search_paths();
+        candidates
+            .iter()
+            .find(|path| path.exists())
+            .unwrap_or(&candidates[0])
+            .clone()
+    }
+
+    /// 按优先级排列的 DLL 查找路径: 用户指定的目录，然后是可执行文件所在目录
+    pub fn search_paths() -> Vec<PathBuf> {
+        let exe_dir = std::env::current_exe()
             .ok()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(Self::DLL_NAME);
+            .unwrap_or_else(|| PathBuf::from("."));
+        let custom_dir = CUSTOM_DLL_DIR.read().unwrap().clone();
+        Self::candidate_paths(custom_dir.as_deref(), &exe_dir)
+    }
+
+    /// 由用户指定目录和可执行文件目录生成查找路径，两者相同时只保留一个
+    fn candidate_paths(custom_dir: Option<&Path>, exe_dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = custom_dir
+            .into_iter()
+            .chain(std::iter::once(exe_dir))
+            .map(|dir| dir.join(Self::DLL_NAME))
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    /// 设置额外的 DLL 查找目录，`None` 表示只在可执行文件目录查找
+    ///
+    /// DLL 只在首次转换时加载一次，已尝试加载后修改需重启程序才会生效。
+    pub fn set_dll_dir(dir: Option<PathBuf>) {
+        *CUSTOM_DLL_DIR.write().unwrap() = dir;
+    }
 
-        if !dll_path.exists() {
-            return Err(AppError::DllNotFound(dll_path.display().to_string()));
+    /// 判断 DLL 是否可用
+    ///
+    /// 已尝试加载时返回加载结果，否则只检查 DLL 文件是否存在，不会触发加载。
+    pub fn is_available() -> bool {
+        match DLL_INSTANCE.get() {
+            Some(result) => result.is_ok(),
+            None => Self::dll_path().exists(),
         }
+    }
+
+    /// 已缓存的 DLL 加载结果，尚未尝试加载时返回 None
+    ///
+    /// 只查询状态，不会触发加载。
+    pub fn load_result() -> Option<Result<(), AppError>> {
+        DLL_INSTANCE
+            .get()
+            .map(|result| result.as_ref().map(|_| ()).map_err(|e| e.clone()))
+    }
+
+    /// 内部 DLL 加载实现
+    fn load_dll_internal() -> Result<DllHolder, AppError> {
+        // 按优先级查找 DLL，全部不存在时在错误中列出所有查找过的路径
+        let candidates = Self::search_paths();
+        let Some(dll_path) = candidates.iter().find(|path| path.exists()).cloned() else {
+            let searched: Vec<String> = candidates
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(AppError::DllNotFound(searched.join("; ")));
+        };
 
         // 转换为 UTF-16
         let dll_path_wide: Vec<u16> = dll_path
@@ -101,15 +190,13 @@ impl WxAMDecoder {
         };
 
         // 获取函数地址
-        let func_name = b"wxam_dec_wxam2pic_5\0";
+        let func_name = format!("{}\0", Self::SYMBOL_NAME);
         let func_ptr = unsafe {
             windows::Win32::System::LibraryLoader::GetProcAddress(
                 handle,
                 windows::core::PCSTR::from_raw(func_name.as_ptr()),
             )
-            .ok_or_else(|| {
-                AppError::DllLoadFailed("无法找到函数 wxam_dec_wxam2pic_5".to_string())
-            })?
+            .ok_or_else(|| AppError::DllLoadFailed(format!("无法找到函数 {}", Self::SYMBOL_NAME)))?
         };
 
         let function: WxamDecFunction = unsafe { std::mem::transmute(func_ptr) };
@@ -137,6 +224,17 @@ impl WxAMDecoder {
     ///
     /// 当参数验证失败或解码失败时返回错误
     pub fn decode(data: &[u8], format: ImageFormat) -> Result<Vec<u8>, AppError> {
+        Self::decode_with_config(data, format, Self::extra_flags())
+    }
+
+    /// 使用指定附加标志将 WXAM 格式数据转换为图片格式
+    ///
+    /// `extra_flags` 写入配置结构体的 `reserved` 字段，0 与 [`Self::decode`] 的默认行为一致。
+    pub fn decode_with_config(
+        data: &[u8],
+        format: ImageFormat,
+        extra_flags: i32,
+    ) -> Result<Vec<u8>, AppError> {
         // 验证 DLL 是否已加载
         let dll_holder = Self::load_dll()?;
 
@@ -146,47 +244,74 @@ impl WxAMDecoder {
         }
 
         // 创建配置结构体
-        let config = WxAMConfig {
-            mode: format as i32,
-            reserved: 0,
-        };
-
-        // 准备输出缓冲区
-        let mut output_buffer = vec![0u8; Self::MAX_OUTPUT_SIZE];
-        let mut output_size = Self::MAX_OUTPUT_SIZE as i32;
+        let config = Self::build_config(format, extra_flags);
 
         log::debug!(
-            "开始解码 WXAM 数据,大小: {} 字节,格式: {:?}",
+            "开始解码 WXAM 数据,大小: {} 字节,格式: {:?},附加标志: {}",
             data.len(),
-            format
+            format,
+            extra_flags
         );
 
-        // 调用 DLL 函数
-        let result = unsafe {
-            (dll_holder.function)(
-                data.as_ptr() as i64,
-                data.len() as i32,
-                output_buffer.as_mut_ptr() as i64,
-                &mut output_size as *mut i32,
-                &config as *const WxAMConfig as i64,
-            )
-        };
+        OUTPUT_BUFFER.with(|buffer| {
+            let mut output_buffer = buffer.borrow_mut();
+            if output_buffer.len() < Self::MAX_OUTPUT_SIZE {
+                output_buffer.resize(Self::MAX_OUTPUT_SIZE, 0);
+            }
+            let mut output_size = Self::MAX_OUTPUT_SIZE as i32;
+
+            // 调用 DLL 函数
+            let result = unsafe {
+                (dll_holder.function)(
+                    data.as_ptr() as i64,
+                    data.len() as i32,
+                    output_buffer.as_mut_ptr() as i64,
+                    &mut output_size as *mut i32,
+                    &config as *const WxAMConfig as i64,
+                )
+            };
+
+            // 检查返回值
+            if result != 0 {
+                return Err(AppError::DllDecodeFailed(result));
+            }
+
+            // 截取有效数据
+            let output = Self::take_output(&output_buffer, output_size)?;
+
+            log::debug!("解码成功,输出大小: {} 字节", output.len());
+
+            Ok(output)
+        })
+    }
 
-        // 检查返回值
-        if result != 0 {
-            return Err(AppError::DllDecodeFailed(result));
+    /// 构造传给 DLL 的解码配置
+    fn build_config(format: ImageFormat, extra_flags: i32) -> WxAMConfig {
+        WxAMConfig {
+            mode: format as i32,
+            reserved: extra_flags,
         }
+    }
 
-        if output_size <= 0 {
-            return Err(AppError::InvalidOutputSize);
-        }
+    /// 当前默认解码使用的附加标志
+    pub fn extra_flags() -> i32 {
+        EXTRA_FLAGS.load(Ordering::Relaxed)
+    }
 
-        // 截取有效数据
-        output_buffer.truncate(output_size as usize);
+    /// 设置默认解码使用的附加标志，之后的 [`Self::decode`] 调用都会使用该值
+    pub fn set_extra_flags(flags: i32) {
+        EXTRA_FLAGS.store(flags, Ordering::Relaxed);
+    }
 
-        log::debug!("解码成功,输出大小: {} 字节", output_size);
+    /// 按 DLL 报告的大小从输出缓冲区复制结果
+    ///
+    /// 不信任 DLL 报告的大小: 非正数或超出缓冲区容量时均视为无效输出。
+    fn take_output(buffer: &[u8], output_size: i32) -> Result<Vec<u8>, AppError> {
+        if output_size <= 0 || output_size as usize > buffer.len() {
+            return Err(AppError::InvalidOutputSize);
+        }
 
-        Ok(output_buffer)
+        Ok(buffer[..output_size as usize].to_vec())
     }
 }
 
@@ -195,15 +320,22 @@ impl WxAMDecoder {
 /// # 参数
 ///
 /// * `data` - WXAM 格式的原始字节数据
-/// * `format` - 目标图片格式字符串 ("jpeg" 或 "gif"),默认为 "jpeg"
+/// * `format` - 目标图片格式字符串 ("jpeg"、"gif" 或 "png")
+///
+/// DLL 本身不支持 PNG: "png" 先按 GIF 模式解码，再把首帧重新编码为 PNG，保留透明像素。
+/// 结果受 GIF 限制，最多 256 色且透明度只有 1 位，并非无损。
 ///
 /// # 返回
 ///
-/// 转换后的图片字节数据,失败时返回 None
+/// 转换后的图片字节数据;失败时返回具体错误,DLL 解码失败时为带错误代码的 `AppError::DllDecodeFailed`
 pub fn wxam_to_image(data: &[u8], format: &str) -> Result<Vec<u8>, AppError> {
     let image_format = match format.to_lowercase().as_str() {
         "jpeg" => ImageFormat::Jpeg,
         "gif" => ImageFormat::Gif,
+        "png" => {
+            let gif = WxAMDecoder::decode(data, ImageFormat::Gif)?;
+            return super::png::gif_to_png(&gif);
+        }
         _ => {
             return Err(AppError::UnsupportedImageFormat(format.to_string()));
         }
@@ -222,6 +354,50 @@ mod tests {
         assert!(matches!(result, Err(AppError::EmptyInput)));
     }
 
+    #[test]
+    fn test_default_config_has_zero_reserved() {
+        let config = WxAMDecoder::build_config(ImageFormat::Gif, WxAMDecoder::extra_flags());
+        assert_eq!(config.mode, ImageFormat::Gif as i32);
+        assert_eq!(config.reserved, 0);
+    }
+
+    #[test]
+    fn test_take_output_rejects_invalid_size() {
+        assert!(matches!(
+            WxAMDecoder::take_output(&[0u8; 8], 9),
+            Err(AppError::InvalidOutputSize)
+        ));
+        assert!(matches!(
+            WxAMDecoder::take_output(&[0u8; 8], 0),
+            Err(AppError::InvalidOutputSize)
+        ));
+        assert!(matches!(
+            WxAMDecoder::take_output(&[0u8; 8], -1),
+            Err(AppError::InvalidOutputSize)
+        ));
+        assert_eq!(WxAMDecoder::take_output(&[0u8; 8], 4).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_candidate_paths_prefer_custom_dir() {
+        let exe_dir = Path::new("C:/app");
+        assert_eq!(
+            WxAMDecoder::candidate_paths(Some(Path::new("D:/wechat")), exe_dir),
+            vec![
+                Path::new("D:/wechat").join(WxAMDecoder::DLL_NAME),
+                exe_dir.join(WxAMDecoder::DLL_NAME),
+            ]
+        );
+        assert_eq!(
+            WxAMDecoder::candidate_paths(Some(exe_dir), exe_dir),
+            vec![exe_dir.join(WxAMDecoder::DLL_NAME)]
+        );
+        assert_eq!(
+            WxAMDecoder::candidate_paths(None, exe_dir),
+            vec![exe_dir.join(WxAMDecoder::DLL_NAME)]
+        );
+    }
+
     #[test]
     fn test_format_conversion() {
         assert_eq!(ImageFormat::Jpeg as i32, 0);
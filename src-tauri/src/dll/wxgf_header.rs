@@ -0,0 +1,401 @@
+//! WXGF 文件头解析模块
+//!
+//! WXGF 以 `wxgf` 魔数开头，内部是 HEVC (H.265) 码流。该模块不调用 DLL，
+//! 直接从码流的 SPS 中读取图像尺寸，并统计编码图像数量判断是否为动图。
+//! 带透明通道的 WXGF 把 alpha 平面编码为另一条 HEVC 序列，表现为内容不同的第二个 SPS。
+
+use serde::Serialize;
+
+/// WXGF 文件信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WxgfInfo {
+    /// 图像宽度 (已应用裁剪窗口)
+    pub width: u32,
+    /// 图像高度 (已应用裁剪窗口)
+    pub height: u32,
+    /// 码流中的编码图像数量
+    pub frame_count: u32,
+    /// 是否为动图 (多于一帧)
+    pub animated: bool,
+    /// 是否带有单独编码的 alpha 平面
+    pub has_alpha: bool,
+}
+
+/// HEVC NAL 单元类型: 序列参数集
+const NAL_SPS: u8 = 33;
+/// HEVC NAL 单元类型: 视频编码层 (VCL) 的最大类型值
+const NAL_VCL_MAX: u8 = 31;
+
+/// 解析 WXGF 文件头信息
+///
+/// 数据不是 WXGF 或码流中找不到可解析的 SPS 时返回 None。
+pub fn parse_wxgf_header(data: &[u8]) -> Option<WxgfInfo> {
+    if data.len() < 4 || !(&data[..4] == b"wxgf" || &data[..4] == b"WXGF") {
+        return None;
+    }
+
+    let mut size = None;
+    let mut frame_count = 0u32;
+    // 第一个 SPS 属于颜色序列；内容不同的 SPS 开始 alpha 序列，其中的图像不计入帧数
+    let mut color_sps: Option<&[u8]> = None;
+    let mut in_color_sequence = true;
+    let mut has_alpha = false;
+
+    for nal in split_nal_units(&data[4..]) {
+        if nal.len() < 3 {
+            continue;
+        }
+
+        let nal_type = (nal[0] >> 1) & 0x3F;
+        if nal_type == NAL_SPS {
+            let first = *color_sps.get_or_insert(nal);
+            in_color_sequence = first == nal;
+            has_alpha |= !in_color_sequence;
+            if in_color_sequence && size.is_none() {
+                size = parse_sps_size(&remove_emulation_prevention(nal));
+            }
+        } else if nal_type <= NAL_VCL_MAX && nal[2] & 0x80 != 0 && in_color_sequence {
+            // first_slice_segment_in_pic_flag 置位表示一幅新图像的开始
+            frame_count += 1;
+        }
+    }
+
+    let (width, height) = size?;
+    let frame_count = frame_count.max(1);
+
+    Some(WxgfInfo {
+        width,
+        height,
+        frame_count,
+        animated: frame_count > 1,
+        has_alpha,
+    })
+}
+
+/// 按 Annex-B 起始码 (00 00 01) 切分 NAL 单元
+fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let mut end = starts
+                .get(index + 1)
+                .map(|&next| next - 3)
+                .unwrap_or(data.len());
+            // 四字节起始码的前导 0 不属于上一个 NAL 单元
+            while end > start && data[end - 1] == 0 {
+                end -= 1;
+            }
+            &data[start..end]
+        })
+        .collect()
+}
+
+/// 去除防竞争字节 (00 00 03 中的 03)
+fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+/// 从 SPS 中读取裁剪后的图像尺寸
+fn parse_sps_size(sps: &[u8]) -> Option<(u32, u32)> {
+    let mut reader = BitReader::new(sps);
+
+    // NAL 头 (2 字节)
+    reader.skip(16)?;
+    // sps_video_parameter_set_id
+    reader.skip(4)?;
+    let max_sub_layers_minus1 = reader.read_bits(3)? as usize;
+    // sps_temporal_id_nesting_flag
+    reader.skip(1)?;
+
+    skip_profile_tier_level(&mut reader, max_sub_layers_minus1)?;
+
+    // sps_seq_parameter_set_id
+    reader.read_ue()?;
+    let chroma_format_idc = reader.read_ue()?;
+    if chroma_format_idc == 3 {
+        // separate_colour_plane_flag
+        reader.skip(1)?;
+    }
+
+    let mut width = reader.read_ue()?;
+    let mut height = reader.read_ue()?;
+
+    // 裁剪窗口
+    if reader.read_bits(1)? == 1 {
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let left = reader.read_ue()?;
+        let right = reader.read_ue()?;
+        let top = reader.read_ue()?;
+        let bottom = reader.read_ue()?;
+        // 裁剪值来自文件，溢出时视为无效的 SPS
+        let crop_width = left.checked_add(right)?.checked_mul(sub_width)?;
+        let crop_height = top.checked_add(bottom)?.checked_mul(sub_height)?;
+        width = width.checked_sub(crop_width)?;
+        height = height.checked_sub(crop_height)?;
+    }
+
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// 跳过 profile_tier_level 结构
+fn skip_profile_tier_level(reader: &mut BitReader, max_sub_layers_minus1: usize) -> Option<()> {
+    // general_profile_space .. general_level_idc 共 96 位
+    reader.skip(96)?;
+
+    let mut profile_present = [false; 8];
+    let mut level_present = [false; 8];
+    for i in 0..max_sub_layers_minus1 {
+        profile_present[i] = reader.read_bits(1)? == 1;
+        level_present[i] = reader.read_bits(1)? == 1;
+    }
+    if max_sub_layers_minus1 > 0 {
+        // reserved_zero_2bits
+        reader.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for i in 0..max_sub_layers_minus1 {
+        if profile_present[i] {
+            reader.skip(88)?;
+        }
+        if level_present[i] {
+            reader.skip(8)?;
+        }
+    }
+
+    Some(())
+}
+
+/// 按位读取器
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bits(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..count {
+            let byte = *self.data.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        if self.pos + count > self.data.len() * 8 {
+            return None;
+        }
+        self.pos += count;
+        Some(())
+    }
+
+    /// 读取无符号指数哥伦布编码
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.read_bits(1)? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        let suffix = self.read_bits(leading_zeros)?;
+        Some((1u32 << leading_zeros) - 1 + suffix)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 按位写入器，用于构造测试码流
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            Self {
+                bytes: Vec::new(),
+                bits: 0,
+            }
+        }
+
+        fn write_bits(&mut self, value: u32, count: usize) {
+            for i in (0..count).rev() {
+                if self.bits.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                if (value >> i) & 1 == 1 {
+                    *self.bytes.last_mut().unwrap() |= 1 << (7 - self.bits % 8);
+                }
+                self.bits += 1;
+            }
+        }
+
+        fn write_ue(&mut self, value: u32) {
+            let code = value + 1;
+            let len = 32 - code.leading_zeros() as usize;
+            self.write_bits(0, len - 1);
+            self.write_bits(code, len);
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            // rbsp_stop_one_bit
+            self.write_bits(1, 1);
+            self.bytes
+        }
+    }
+
+    pub(crate) fn build_sps(width: u32, height: u32, crop_bottom: u32) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_bits((NAL_SPS as u32) << 9 | 1, 16);
+        w.write_bits(0, 4);
+        w.write_bits(0, 3);
+        w.write_bits(1, 1);
+        for _ in 0..12 {
+            w.write_bits(0, 8);
+        }
+        w.write_ue(0);
+        w.write_ue(1);
+        w.write_ue(width);
+        w.write_ue(height);
+        if crop_bottom > 0 {
+            w.write_bits(1, 1);
+            w.write_ue(0);
+            w.write_ue(0);
+            w.write_ue(0);
+            w.write_ue(crop_bottom);
+        } else {
+            w.write_bits(0, 1);
+        }
+        w.finish()
+    }
+
+    /// 插入防竞争字节，与编码器的输出保持一致
+    fn add_emulation_prevention(rbsp: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut zeros = 0;
+        for &byte in rbsp {
+            if zeros >= 2 && byte <= 3 {
+                out.push(3);
+                zeros = 0;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            out.push(byte);
+        }
+        out
+    }
+
+    pub(crate) fn build_wxgf(sps: &[u8], frames: usize) -> Vec<u8> {
+        let mut data = b"wxgf".to_vec();
+        data.extend_from_slice(&[0x01, 0x02, 0x03]);
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(&add_emulation_prevention(sps));
+        for _ in 0..frames {
+            // IDR_W_RADL 分片，first_slice_segment_in_pic_flag = 1
+            data.extend_from_slice(&[0, 0, 0, 1, 19 << 1, 1, 0x80, 0xAA]);
+            // 同一幅图像的后续分片
+            data.extend_from_slice(&[0, 0, 1, 1 << 1, 1, 0x00, 0xAA]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_static_wxgf() {
+        let info = parse_wxgf_header(&build_wxgf(&build_sps(240, 160, 0), 1)).unwrap();
+        assert_eq!(
+            info,
+            WxgfInfo {
+                width: 240,
+                height: 160,
+                frame_count: 1,
+                animated: false,
+                has_alpha: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_alpha_sequence_not_counted_as_frames() {
+        // 颜色序列和 alpha 序列各有一幅图像，仍是静态图
+        let mut data = build_wxgf(&build_sps(240, 160, 0), 1);
+        data.extend_from_slice(&build_wxgf(&build_sps(240, 160, 2), 1)[4..]);
+        let info = parse_wxgf_header(&data).unwrap();
+        assert_eq!((info.width, info.height), (240, 160));
+        assert_eq!(info.frame_count, 1);
+        assert!(info.has_alpha && !info.animated);
+
+        // 每帧重复发送相同的 SPS 不算 alpha 序列
+        let sps = build_sps(240, 160, 0);
+        let mut data = build_wxgf(&sps, 1);
+        data.extend_from_slice(&build_wxgf(&sps, 1)[4..]);
+        let info = parse_wxgf_header(&data).unwrap();
+        assert_eq!(info.frame_count, 2);
+        assert!(info.animated && !info.has_alpha);
+    }
+
+    #[test]
+    fn test_animated_wxgf_with_crop() {
+        // 4:2:0 下 crop_bottom = 4 表示裁掉 8 行
+        let info = parse_wxgf_header(&build_wxgf(&build_sps(128, 136, 4), 3)).unwrap();
+        assert_eq!((info.width, info.height), (128, 128));
+        assert_eq!(info.frame_count, 3);
+        assert!(info.animated);
+    }
+
+    #[test]
+    fn test_rejects_overflowing_crop() {
+        // 4:2:0 下裁剪值乘以 2 后超出 u32，不能溢出或回绕
+        let huge = build_sps(128, 136, 1 << 31);
+        assert!(parse_wxgf_header(&build_wxgf(&huge, 1)).is_none());
+
+        let max = build_sps(128, 136, u32::MAX - 1);
+        assert!(parse_wxgf_header(&build_wxgf(&max, 1)).is_none());
+    }
+
+    #[test]
+    fn test_rejects_non_wxgf() {
+        assert!(parse_wxgf_header(b"\xFF\xD8\xFF\xE0").is_none());
+        assert!(parse_wxgf_header(b"wxgf\x00\x00").is_none());
+    }
+
+    #[test]
+    fn test_emulation_prevention_removed() {
+        assert_eq!(
+            remove_emulation_prevention(&[0, 0, 3, 1, 0, 0, 3]),
+            vec![0, 0, 1, 0, 0]
+        );
+    }
+}
@@ -54,6 +54,9 @@ pub enum AppError {
     #[error("AES 解密失败: {0}")]
     AesDecryptError(String),
 
+    #[error("AES 密钥可能不正确: {0}")]
+    InvalidAesKey(String),
+
     #[error("解密失败: {0}")]
     DecryptFailed(String),
 
@@ -82,7 +85,14 @@ pub enum AppError {
     #[error("输出数据大小无效")]
     InvalidOutputSize,
 
+    // ===== 会话错误 =====
+    #[error("解密会话不存在: {0}")]
+    SessionNotFound(String),
+
     // ===== 通用错误 =====
+    #[error("参数无效: {0}")]
+    InvalidArgument(String),
+
     #[error("内部错误: {0}")]
     Internal(String),
 }
@@ -186,6 +196,10 @@ impl AppError {
                 "AES_DECRYPT_ERROR".to_string(),
                 format!("AES 解密失败: {}", msg),
             ),
+            AppError::InvalidAesKey(msg) => (
+                "INVALID_AES_KEY".to_string(),
+                format!("AES 密钥可能不正确，请检查设置中的 AES 密钥 ({})", msg),
+            ),
             AppError::DecryptFailed(msg) => {
                 ("DECRYPT_FAILED".to_string(), format!("解密失败: {}", msg))
             }
@@ -221,7 +235,16 @@ impl AppError {
                 "输出数据大小无效".to_string(),
             ),
 
+            // 会话错误
+            AppError::SessionNotFound(id) => (
+                "SESSION_NOT_FOUND".to_string(),
+                format!("解密会话不存在: {}", id),
+            ),
+
             // 通用错误
+            AppError::InvalidArgument(msg) => {
+                ("INVALID_ARGUMENT".to_string(), format!("参数无效: {}", msg))
+            }
             AppError::Internal(msg) => ("INTERNAL_ERROR".to_string(), format!("内部错误: {}", msg)),
         }
     }
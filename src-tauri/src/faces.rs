@@ -0,0 +1,103 @@
+//! 人脸检测模块
+//!
+//! 使用 rustface (SeetaFace) 在解码后的灰度图上检测人脸，需要启用 `faces` feature，
+//! 并在程序目录放置模型文件。
+
+use crate::error::AppError;
+use crate::FaceBox;
+use std::path::PathBuf;
+
+/// SeetaFace 正脸检测模型文件名
+const MODEL_NAME: &str = "seeta_fd_frontal_v1.0.bin";
+
+/// 小于该尺寸 (像素) 的人脸不检测
+const MIN_FACE_SIZE: u32 = 20;
+
+/// 检测分数阈值，越大误检越少
+const SCORE_THRESHOLD: f64 = 2.0;
+
+/// 查找模型文件: 优先使用与程序同目录的模型，其次使用当前目录下的模型
+pub fn find_model() -> Option<PathBuf> {
+    let bundled = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.join(MODEL_NAME)));
+    bundled
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(MODEL_NAME)))
+        .find(|p| p.exists())
+}
+
+/// 检测图片中的人脸，返回人脸框
+///
+/// 数据无法解码为图片时返回空列表。
+pub fn detect(data: &[u8]) -> Result<Vec<FaceBox>, AppError> {
+    let gray = match image::load_from_memory(data) {
+        Ok(img) => img.to_luma8(),
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let model = find_model().ok_or_else(|| {
+        AppError::FileNotFound(format!("人脸检测模型 {}，请将其放到程序目录", MODEL_NAME))
+    })?;
+    let model = model
+        .to_str()
+        .ok_or_else(|| AppError::InvalidPath(model.display().to_string()))?;
+
+    let mut detector = rustface::create_detector(model)
+        .map_err(|e| AppError::Internal(format!("人脸检测模型加载失败: {}", e)))?;
+    detector.set_min_face_size(MIN_FACE_SIZE);
+    detector.set_score_thresh(SCORE_THRESHOLD);
+    detector.set_pyramid_scale_factor(0.8);
+    detector.set_slide_window_step(4, 4);
+
+    let (width, height) = gray.dimensions();
+    let mut image = rustface::ImageData::new(gray.as_raw(), width, height);
+
+    Ok(detector
+        .detect(&mut image)
+        .into_iter()
+        .map(|face| {
+            let bbox = face.bbox();
+            FaceBox {
+                x: bbox.x().max(0) as u32,
+                y: bbox.y().max(0) as u32,
+                width: bbox.width(),
+                height: bbox.height(),
+                score: face.score(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_image_has_no_faces() {
+        assert!(detect(b"not an image").unwrap().is_empty());
+    }
+
+    #[test]
+    #[ignore = "需要在当前目录放置人脸检测模型 seeta_fd_frontal_v1.0.bin"]
+    fn test_plain_image_has_no_faces() {
+        let img = image::GrayImage::from_fn(96, 96, |x, y| image::Luma([((x + y) % 256) as u8]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        assert_eq!(detect(&png.into_inner()).unwrap().len(), 0);
+    }
+
+    #[test]
+    #[ignore = "需要在当前目录放置人脸检测模型 seeta_fd_frontal_v1.0.bin"]
+    fn test_fixture_faces_are_detected() {
+        // 从 rustface 自带的测试图片中裁出的两张正脸
+        let jpeg = include_bytes!("../tests/fixtures/two_faces.jpg");
+
+        let mut faces = detect(jpeg).unwrap();
+        assert_eq!(faces.len(), 2);
+        faces.sort_by_key(|face| face.x);
+        assert!(faces[0].x < faces[1].x && faces[0].y < faces[1].y);
+        assert!(faces.iter().all(|face| face.score >= SCORE_THRESHOLD));
+    }
+}
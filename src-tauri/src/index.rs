@@ -0,0 +1,116 @@
+//! 内容索引模块
+//!
+//! 记录每个图片文件解密后的内容哈希、格式和尺寸，持久化为 JSON 文件。
+//! 去重、搜索等功能查询索引即可，不需要重新解密整个文件夹。
+
+use crate::decrypt::{ContentHasher, HashAlgorithm};
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 索引文件名前缀，完整文件名为 `index-<根目录哈希>.json`
+const INDEX_FILE_PREFIX: &str = "index-";
+
+/// 单个文件的索引条目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// 解密后内容的 SHA-256
+    pub hash: String,
+    /// 识别出的 MIME 类型，无法识别时为 None
+    pub mime: Option<String>,
+    /// 图片尺寸 (宽, 高)，无法解析时为 None
+    pub dims: Option<(u32, u32)>,
+    /// 建立索引时文件的修改时间
+    pub mtime: u64,
+}
+
+/// 某个根目录下所有文件的索引，键为相对根目录的路径
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ContentIndex {
+    pub root: String,
+    pub entries: HashMap<String, IndexEntry>,
+}
+
+impl ContentIndex {
+    /// 创建空索引
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.display().to_string(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// 从文件加载索引；文件不存在、格式错误或属于其他根目录时返回空索引
+    pub fn load(path: &Path, root: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<ContentIndex>(&content).ok())
+            .filter(|index| index.root == root.display().to_string())
+            .unwrap_or_else(|| Self::new(root))
+    }
+
+    /// 保存索引，先写临时文件再重命名，中途中断不会留下损坏的索引
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        let content = serde_json::to_string(self)
+            .map_err(|e| AppError::ConfigSerializeError(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+        fs::rename(&tmp, path).map_err(|e| AppError::FileWriteError(e.to_string()))
+    }
+
+    /// 文件修改时间未变时返回已有条目
+    pub fn fresh_entry(&self, rel_path: &str, mtime: u64) -> Option<&IndexEntry> {
+        self.entries
+            .get(rel_path)
+            .filter(|entry| entry.mtime == mtime)
+    }
+}
+
+/// 根目录对应的索引文件路径，不同根目录使用不同的文件
+pub fn index_path_for_root(root: &Path) -> PathBuf {
+    let mut hasher = ContentHasher::new(HashAlgorithm::Sha256);
+    hasher.update(root.display().to_string().as_bytes());
+    let digest = hasher.finalize_hex();
+    PathBuf::from(format!("{}{}.json", INDEX_FILE_PREFIX, &digest[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: &str, mtime: u64) -> IndexEntry {
+        IndexEntry {
+            hash: hash.to_string(),
+            mime: Some("image/png".to_string()),
+            dims: Some((4, 3)),
+            mtime,
+        }
+    }
+
+    #[test]
+    fn test_save_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        let root = Path::new("/data/wechat");
+
+        let mut index = ContentIndex::new(root);
+        index.entries.insert("a.dat".to_string(), entry("abc", 10));
+        index.save(&path).unwrap();
+
+        let reloaded = ContentIndex::load(&path, root);
+        assert_eq!(reloaded.entries.get("a.dat"), Some(&entry("abc", 10)));
+        assert!(reloaded.fresh_entry("a.dat", 10).is_some());
+        assert!(reloaded.fresh_entry("a.dat", 11).is_none());
+
+        // 其他根目录的索引不会被复用
+        assert!(ContentIndex::load(&path, Path::new("/other"))
+            .entries
+            .is_empty());
+        assert_ne!(
+            index_path_for_root(root),
+            index_path_for_root(Path::new("/other"))
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use base64::Engine;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use tauri::State;
 use tokio::sync::Semaphore;
@@ -11,23 +12,65 @@ mod error;
 pub use error::{AppError, ErrorResponse};
 
 mod decrypt;
-use decrypt::DatDecryptor;
+use decrypt::aes::AesHandler;
+use decrypt::{DatDecryptor, DatVersion, V3Decryptor, VersionDetector, XorKey};
 
-#[cfg(windows)]
 pub mod dll;
 
+pub mod api;
+
+mod index;
+use index::{ContentIndex, IndexEntry};
+
+mod credentials;
+use credentials::SecretStore;
+
+mod cache;
+use cache::{CacheBackend, ImageCache};
+
+mod throttle;
+use throttle::RateLimiter;
+
+#[cfg(feature = "ffmpeg")]
+mod video;
+
+#[cfg(feature = "faces")]
+mod faces;
+
 // 配置文件路径
 const CONFIG_FILE: &str = "config.json";
 
+// 用户定义的 MIME 覆盖规则 (文件头前缀, MIME)，detect_mime_type 优先使用
+static MIME_OVERRIDES: std::sync::RwLock<Vec<(Vec<u8>, String)>> =
+    std::sync::RwLock::new(Vec::new());
+
 // 全局状态
 pub struct AppState {
     root_dir: Mutex<Option<PathBuf>>,
     xor_key: Mutex<u8>,
     aes_key: Mutex<Vec<u8>>,
-    // 图片缓存：存储解密后的图片数据以及 MIME 类型
-    image_cache: Arc<Mutex<HashMap<String, CachedImage>>>,
+    // 按文件夹覆盖的密钥：子目录可使用与全局不同的 (XOR, AES) 密钥
+    folder_keys: Mutex<HashMap<PathBuf, (u8, Vec<u8>)>>,
+    // 仅会话模式：密钥只保存在内存中，不写入配置文件
+    session_only_keys: AtomicBool,
+    // 推算 v3 文件的 XOR 密钥：配置的密钥解不出已知文件头时按文件头推算，未加密文件推算出密钥 0
+    plaintext_fallback: AtomicBool,
+    // 无界面模式：由脚本批量调用时不写入图片缓存，也不做预取
+    headless: AtomicBool,
+    // 图片缓存：存储解密后的图片数据以及 MIME 类型，可保存在内存或磁盘
+    image_cache: Arc<Mutex<ImageCache>>,
+    // get_image_with_etag 使用的 ETag 类型
+    etag_mode: Mutex<EtagMode>,
     // 限制同时进行的解密任务数量，避免阻塞
     decrypt_semaphore: Arc<Semaphore>,
+    // 解密会话：解密结果保存在临时文件中，供前端按块拉取
+    decrypt_sessions: Mutex<DecryptSessions>,
+    // 下一个解密会话的编号
+    next_session_id: AtomicU64,
+    // 取消正在进行的索引任务
+    index_cancel: Arc<AtomicBool>,
+    // 取消正在进行的文件夹导出
+    export_cancel: AtomicBool,
 }
 
 impl Default for AppState {
@@ -36,16 +79,137 @@ impl Default for AppState {
             root_dir: Mutex::new(None),
             xor_key: Mutex::new(0),
             aes_key: Mutex::new(Vec::new()),
-            image_cache: Arc::new(Mutex::new(HashMap::new())),
+            folder_keys: Mutex::new(HashMap::new()),
+            session_only_keys: AtomicBool::new(false),
+            plaintext_fallback: AtomicBool::new(false),
+            headless: AtomicBool::new(false),
+            image_cache: Arc::new(Mutex::new(ImageCache::default())),
+            etag_mode: Mutex::new(EtagMode::default()),
             decrypt_semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_DECRYPT)),
+            decrypt_sessions: Mutex::new(DecryptSessions::default()),
+            next_session_id: AtomicU64::new(1),
+            index_cancel: Arc::new(AtomicBool::new(false)),
+            export_cancel: AtomicBool::new(false),
         }
     }
 }
 
+impl AppState {
+    // 读取图片缓存
+    async fn cache_get(&self, key: &str) -> Option<CachedImage> {
+        cache_get(&self.image_cache, key).await
+    }
+
+    // 写入图片缓存，无界面模式下直接丢弃
+    async fn cache_insert(&self, key: String, image: CachedImage) {
+        if self.headless.load(Ordering::Relaxed) {
+            return;
+        }
+        cache_store(&self.image_cache, key, image).await;
+    }
+}
+
+// 读取图片缓存；锁内只查找条目，磁盘后端的文件在释放锁之后于阻塞线程中读取
+async fn cache_get(cache: &Mutex<ImageCache>, key: &str) -> Option<CachedImage> {
+    let hit = cache.lock().unwrap().lookup(key)?;
+    if !hit.needs_io() {
+        return hit.load();
+    }
+    tokio::task::spawn_blocking(move || hit.load())
+        .await
+        .unwrap_or_else(|err| {
+            log::warn!("读取缓存任务执行失败: {}", err);
+            None
+        })
+}
+
+// 写入图片缓存；锁内只登记条目，磁盘后端的文件在阻塞线程中写入，替换或淘汰的文件在释放锁之后删除
+async fn cache_store(cache: &Arc<Mutex<ImageCache>>, key: String, image: CachedImage) {
+    let pending = cache.lock().unwrap().prepare_insert(key, image);
+    let needs_io = pending.needs_io();
+    let cache = cache.clone();
+    let store = move || {
+        let pending = pending.write();
+        let stale = cache.lock().unwrap().finish_insert(pending);
+        cache::remove_files(stale);
+    };
+
+    if !needs_io {
+        store();
+    } else if let Err(err) = tokio::task::spawn_blocking(store).await {
+        log::warn!("写入缓存任务执行失败: {}", err);
+    }
+}
+
 // 解密任务并发限制
 const MAX_CONCURRENT_DECRYPT: usize = 4;
 // 预取任务数量限制
 const PREFETCH_LIMIT: usize = 4;
+// 建议前端缓存解密图片的时长（秒）
+const IMAGE_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+// 图片自定义协议名，前端使用 wxdat://localhost/<image_id>
+// （Windows 上为 http://wxdat.localhost/<image_id>）直接加载图片，image_id 需经过 URL 编码
+const IMAGE_SCHEME: &str = "wxdat";
+// 判断 AES 密钥类型时最多抽样的文件数量
+const AES_REQUIREMENT_SAMPLE_LIMIT: usize = 64;
+// 某一种 v4 子版本占比达到该比例（百分比）时视为该文件夹的主要类型
+const AES_REQUIREMENT_DOMINANT_PERCENT: usize = 90;
+// 流式解密未指定块大小时使用的默认值（字节）
+const STREAM_DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+// 检测文件夹 XOR 密钥时最多抽样的 v3 文件数量
+const XOR_DETECT_SAMPLE_LIMIT: usize = 32;
+// 静态 WXGF 转换的首选输出格式，体积较小
+const WXGF_PREFERRED_FORMAT: &str = "jpeg";
+// 动图 WXGF 转换的首选输出格式，保留动画
+const WXGF_ANIMATED_FORMAT: &str = "gif";
+// 带 alpha 平面的静态 WXGF 使用的输出格式
+const WXGF_TRANSPARENT_FORMAT: &str = "png";
+// 建立内容索引时默认每处理多少个文件保存一次，中断后可以从保存点继续
+const INDEX_SAVE_INTERVAL: usize = 50;
+// 以无界面模式启动的命令行参数
+const HEADLESS_ARG: &str = "--headless";
+// 贴纸的最大边长（像素）
+const STICKER_MAX_DIM: u32 = 512;
+// 相邻像素颜色相同的比例达到该值时视为截图
+const SCREENSHOT_FLAT_RATIO: f64 = 0.6;
+// 没有 EXIF 的 JPEG 短边达到该值且色块不明显时视为照片
+const PHOTO_MIN_DIM: u32 = 300;
+// 计算熵时使用的输出开头长度（字节），不足该长度时不计算
+const ENTROPY_SAMPLE_BYTES: usize = 1024;
+// 熵达到该值（比特/字节）时视为随机噪声
+const WRONG_KEY_ENTROPY: f64 = 7.5;
+// 读取网格元数据时解密的文件开头长度（字节）
+const GRID_HEAD_BYTES: usize = 64 * 1024;
+// 静态相册中大图的最长边和 WebP 质量
+const GALLERY_FULL_MAX_DIM: u32 = 2048;
+const GALLERY_FULL_QUALITY: u8 = 80;
+// 静态相册中缩略图的最长边和 WebP 质量
+const GALLERY_THUMB_MAX_DIM: u32 = 256;
+const GALLERY_THUMB_QUALITY: u8 = 60;
+// 静态相册清单文件名
+const GALLERY_MANIFEST: &str = "gallery.json";
+// 导出文件夹时写入的清单文件名
+const EXPORT_MANIFEST: &str = "manifest.json";
+// 导出文件名的默认模板，即以 hash 命名并按格式补全扩展名
+const DEFAULT_EXPORT_TEMPLATE: &str = "{hash}.{ext}";
+// 胶片条最多包含的图片数量和单格最大边长，保证拼接结果不超过 JPEG 的尺寸上限
+const FILMSTRIP_MAX_COUNT: usize = 64;
+const FILMSTRIP_MAX_THUMB_HEIGHT: u32 = 512;
+// get_thumbnail 生成的 JPEG 缩略图质量
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+// 识别为图片的文件扩展名
+const DAT_EXTENSION: &str = ".dat";
+// 朋友圈 (Sns) 无扩展名文件名允许的长度
+const SNS_FILENAME_LENGTHS: [usize; 2] = [30, 32];
+// 缩略图文件名后缀（不含扩展名）
+const THUMBNAIL_SUFFIX: &str = "_t";
+// 查找图片目录时的最大遍历深度和最多检查的条目数，保证在整个微信数据目录上也能较快返回
+const IMAGE_ROOT_MAX_DEPTH: usize = 8;
+const IMAGE_ROOT_MAX_ENTRIES: usize = 200_000;
+// 判定为图片目录所需的最少图片文件数，以及图片文件占目录内文件的最低比例
+const IMAGE_ROOT_MIN_FILES: usize = 5;
+const IMAGE_ROOT_MIN_DENSITY: f64 = 0.5;
 
 // 图片缓存实体
 #[derive(Clone)]
@@ -55,10 +219,48 @@ struct CachedImage {
 }
 
 // 配置结构
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 struct Config {
     xor: u8,
     aes: String,
+    // 按文件夹覆盖的密钥，键为文件夹绝对路径
+    #[serde(default)]
+    folder_keys: HashMap<String, FolderKeyConfig>,
+    // 收藏的图片，键为根目录绝对路径，值为相对根目录的图片路径
+    #[serde(default)]
+    favorites: HashMap<String, Vec<String>>,
+    // AES 密钥保存在系统凭据存储中时使用的目标名称，此时 aes 字段为空
+    #[serde(default)]
+    credential_target: Option<String>,
+    // 图片缓存后端："memory" 或 "disk"，为空时使用内存
+    #[serde(default)]
+    cache_backend: String,
+    // ETag 类型："strong" 或 "weak"，为空时使用 strong
+    #[serde(default)]
+    etag_mode: String,
+    // 自定义 MIME 识别规则，优先于内置的文件头识别
+    #[serde(default)]
+    mime_overrides: Vec<MimeOverrideConfig>,
+    // aes 字段的编码："hex" 或 "text"，为空时自动识别（32/64 位十六进制串按 hex 解码）
+    #[serde(default)]
+    aes_encoding: String,
+    // VoipEngine.dll 所在目录，优先于程序目录查找，为空时只查找程序目录
+    #[serde(default)]
+    dll_dir: String,
+}
+
+// 单条 MIME 覆盖规则：以 magic（十六进制）开头的数据识别为 mime
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct MimeOverrideConfig {
+    magic: String,
+    mime: String,
+}
+
+// 单个文件夹的覆盖密钥
+#[derive(Serialize, Deserialize, Clone)]
+struct FolderKeyConfig {
+    xor: u8,
+    aes: String,
 }
 
 // 目录树节点
@@ -67,6 +269,8 @@ struct TreeNode {
     name: String,
     path: String,
     children: Vec<TreeNode>,
+    // 因深度或条目数限制而省略了部分子目录
+    truncated: bool,
 }
 
 // 图片文件信息
@@ -87,6 +291,8 @@ struct ImageBatch {
     page: usize,
     page_size: usize,
     has_more: bool,
+    // 递归扫描因深度或条目数限制提前停止
+    truncated: bool,
 }
 
 // 带解密数据的图片信息（现在只包含元数据，不包含图片数据）
@@ -100,6 +306,26 @@ struct ImageWithData {
     mime_type: Option<String>,
     // 用于前端获取图片的唯一标识符
     image_id: String,
+    // 像素尺寸，只从文件开头读取；无法识别时为 0
+    width: u32,
+    height: u32,
+    // DAT 版本：v3 只需 XOR 密钥，v4v1 / v4v2 还需要 AES 密钥；无法读取时为 unknown
+    version: String,
+}
+
+// 网格布局所需的元数据：不含图片数据，尺寸和类型只从文件开头读取
+#[derive(Serialize, Debug)]
+struct GridItem {
+    image_id: String,
+    name: String,
+    size: u64,
+    modified: u64,
+    is_thumbnail: bool,
+    // image / wxgf / video / unknown
+    kind: String,
+    mime_type: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
 }
 
 #[derive(Serialize)]
@@ -108,38 +334,576 @@ struct ImageDataResponse {
     mime_type: String,
 }
 
-// 读取配置文件
-fn read_key_from_config() -> (u8, Vec<u8>) {
-    let content = match fs::read_to_string(CONFIG_FILE) {
-        Ok(c) => c,
-        Err(_) => return (0, vec![]),
-    };
+// 使用指定密钥解密任意文件的完整结果
+#[derive(Serialize, Debug)]
+struct DecryptedFile {
+    // 标准化后的数据（base64）
+    data: String,
+    mime_type: String,
+    // 图片尺寸，无法解析时为 None
+    width: Option<u32>,
+    height: Option<u32>,
+    // DAT 版本：v3 / v4v1 / v4v2
+    version: String,
+    // 解密后原始数据的类型：image / wxgf / video / unknown
+    kind: String,
+}
+
+// ETag 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum EtagMode {
+    // 按解密后的内容计算，需要先解密
+    #[default]
+    Strong,
+    // 按源文件大小和修改时间生成，不需要解密，文件未变化时足够可靠
+    Weak,
+}
+
+impl EtagMode {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "strong" => Some(Self::Strong),
+            "weak" => Some(Self::Weak),
+            _ => None,
+        }
+    }
+}
+
+// 带缓存提示的图片响应
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum ImageResponse {
+    Ok {
+        data: Vec<u8>,
+        mime_type: String,
+        etag: String,
+        // 建议的缓存时长（秒）
+        max_age: u64,
+    },
+    // 内容未变化，不返回数据
+    NotModified {
+        etag: String,
+    },
+}
+
+// 强制解密时命中的策略
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ForceStrategy {
+    // 当前配置的密钥即可解密
+    ConfiguredKeys,
+    // 对 v3 文件穷举出了正确的 XOR 密钥
+    XorBruteforce,
+    // v4 文件用已知的另一组密钥（全局、文件夹覆盖或凭据存储中的密钥）解密成功
+    KeyTable,
+    // 文件本身未加密
+    Plaintext,
+}
+
+// 强制解密时逐个尝试的一组已知密钥
+#[derive(Clone, Debug)]
+struct KeyCandidate {
+    // 密钥来源："global"、"credential" 或覆盖密钥所在的文件夹路径
+    source: String,
+    xor: u8,
+    aes: Vec<u8>,
+}
+
+// try_force_decrypt 命中的结果
+struct ForcedData {
+    data: Vec<u8>,
+    strategy: ForceStrategy,
+    // 实际生效的 XOR 密钥（明文时为 None）
+    xor_key: Option<u8>,
+    // 命中的密钥来源，只在按已知密钥逐个尝试时有值
+    key_source: Option<String>,
+}
+
+// 强制解密结果
+#[derive(Serialize)]
+struct ForceResult {
+    // base64 编码的图片数据
+    data: String,
+    mime_type: String,
+    strategy: ForceStrategy,
+    // 实际生效的 XOR 密钥（明文时为 None）
+    xor_key: Option<u8>,
+    // 命中的已知密钥来源，见 KeyCandidate::source
+    key_source: Option<String>,
+}
+
+// 流式解密的数据块事件（decrypt-chunk）
+#[derive(Serialize, Clone)]
+struct DecryptChunkEvent {
+    file_path: String,
+    // 块序号，从 0 开始，前端据此排序
+    seq: usize,
+    // base64 编码的数据片段
+    data: String,
+}
+
+// 流式解密的结束事件（decrypt-end）
+#[derive(Serialize, Clone)]
+struct DecryptEndEvent {
+    file_path: String,
+    total_len: usize,
+    chunk_count: usize,
+    mime_type: String,
+}
+
+// 文件夹 XOR 密钥检测结果
+#[derive(Serialize, Debug, PartialEq)]
+struct FolderXorGuess {
+    // 多数样本一致认可的密钥，没有可用样本时为 None
+    xor_key: Option<u8>,
+    // 认可该密钥的样本比例（0.0 ~ 1.0）
+    confidence: f64,
+    // 抽样的 v3 文件数量
+    sampled: usize,
+}
+
+// 一组候选密钥
+#[derive(Deserialize, Debug, Clone)]
+struct KeySet {
+    xor: u8,
+    aes: String,
+}
+
+// 使用一组密钥解密的结果
+#[derive(Serialize, Debug)]
+struct KeyTrial {
+    // 解密输出是否以已知媒体魔数开头
+    valid: bool,
+    // 输出前 16 字节的十六进制表示
+    head_hex: String,
+    // 输出开头部分的香农熵（比特/字节），样本不足时为 None
+    entropy: Option<f64>,
+    // 熵接近 8 且不是以压缩数据开头的格式，很可能是 AES 密钥错误解出的噪声
+    likely_wrong_key: bool,
+    // 解密失败时的错误信息
+    error: Option<String>,
+}
+
+// 用样本文件校验密钥的结果
+#[derive(Serialize, Debug)]
+struct KeyValidation {
+    // 解密出的文件头是否为可识别的图片/视频格式
+    valid: bool,
+    // 样本文件的 DAT 版本
+    detected_version: String,
+    // 解密出的格式，无法识别时为 None
+    detected_mime: Option<String>,
+    // 解密失败时的错误信息
+    error: Option<String>,
+}
+
+// 两组密钥的对比结果
+#[derive(Serialize, Debug)]
+struct KeyCompare {
+    a: KeyTrial,
+    b: KeyTrial,
+}
+
+// 构建信息，供用户在反馈问题时附带
+#[derive(Serialize, Debug)]
+struct AppInfo {
+    version: String,
+    // 操作系统与架构，例如 windows-x86_64
+    platform: String,
+    // 编译时启用的可选功能
+    features: Vec<String>,
+    // 程序目录中是否附带了 VoipEngine.dll（仅 Windows）
+    dll_bundled: bool,
+}
+
+// 图片质量评估结果
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct QualityScore {
+    // 清晰度：灰度图拉普拉斯响应的方差，越大越清晰
+    sharpness: f64,
+    // 亮度：平均灰度，范围 0.0（全黑）~ 1.0（全白）
+    brightness: f64,
+}
+
+// 从损坏文件中切出的单张图片
+#[derive(Serialize)]
+struct CarvedImage {
+    // 图片在解密数据中的偏移
+    offset: usize,
+    mime_type: String,
+    // 写入 output_dir 后的文件路径
+    path: String,
+    // 图片字节数
+    size: usize,
+}
+
+// 单个文件的恢复结果
+#[derive(Serialize)]
+struct RecoveredImage {
+    image_id: String,
+    // 恢复方式："decrypt" 正常解密，"xor" 解密失败后按原始 XOR 处理
+    method: String,
+    images: Vec<CarvedImage>,
+    // 正常解密失败时的错误信息
+    error: Option<String>,
+}
+
+// 建立内容索引的进度事件（index-progress）
+#[derive(Serialize, Clone)]
+struct IndexProgressEvent {
+    processed: usize,
+    total: usize,
+    path: String,
+}
+
+// 建立内容索引的结果汇总
+#[derive(Serialize, Debug, Default)]
+struct IndexSummary {
+    total: usize,
+    // 新建或更新的条目数
+    indexed: usize,
+    // 修改时间未变而跳过的文件数
+    skipped: usize,
+    // 解密失败的文件数
+    failed: usize,
+    index_path: String,
+    // 被 cancel_index 中断，已处理的部分已保存，重新调用 build_index 即可继续
+    cancelled: bool,
+}
+
+// 静态相册中的单张图片，路径相对于相册目录
+#[derive(Serialize, Deserialize, Debug)]
+struct GalleryItem {
+    // 源图片 ID（相对根目录的路径）
+    source: String,
+    // 大图的宽高
+    width: u32,
+    height: u32,
+    full: String,
+    thumbnail: String,
+}
+
+// 静态相册清单，同时写入相册目录下的 gallery.json
+#[derive(Serialize, Deserialize, Debug)]
+struct GalleryManifest {
+    items: Vec<GalleryItem>,
+    // 解密失败或不是图片而跳过的文件数
+    skipped: usize,
+}
+
+// 建立索引时单个文件的任务
+struct IndexJob {
+    path: PathBuf,
+    rel_path: String,
+    mtime: u64,
+    xor_key: XorKey,
+    aes_key: Option<Vec<u8>>,
+}
+
+// 计算可释放空间时需要哈希的文件
+struct HashJob {
+    path: PathBuf,
+    size: u64,
+    xor_key: XorKey,
+    aes_key: Option<Vec<u8>>,
+}
 
-    let config = match serde_json::from_str::<Config>(&content) {
-        Ok(c) => c,
-        Err(_) => return (0, vec![]),
+// 动图的单帧
+#[derive(Serialize, Debug)]
+struct AnimationFrame {
+    // PNG 格式的 data URL
+    data_url: String,
+    // 该帧的显示时长，静态图片为 0
+    delay_ms: u32,
+}
+
+// 逐帧拆分后的动图
+#[derive(Serialize, Debug)]
+struct AnimationData {
+    frames: Vec<AnimationFrame>,
+    // 循环次数，0 表示无限循环
+    loop_count: u32,
+}
+
+// 检测到的人脸框（像素坐标）
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct FaceBox {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    // 检测分数，越大越可信
+    score: f64,
+}
+
+// WXGF 转换 DLL 的状态
+#[derive(Serialize, Debug)]
+struct DllStatus {
+    // 当前平台是否支持 DLL 转换（仅 Windows）
+    supported: bool,
+    // 查找 DLL 的路径
+    path: Option<String>,
+    // DLL 文件是否存在
+    found: bool,
+    // 是否已成功加载，尚未尝试加载时为 None
+    loaded: Option<bool>,
+    // 成功解析的导出函数
+    symbol: Option<String>,
+    // 加载失败时的错误信息
+    error: Option<String>,
+    // 给用户的提示信息
+    message: String,
+}
+
+// 单个 DAT 版本的统计信息
+#[derive(Serialize, Default, Debug, PartialEq, Eq)]
+struct VersionStats {
+    count: usize,
+    total_bytes: u64,
+    average_size: u64,
+}
+
+// 串行化配置文件的读取-修改-写回，避免并发的命令互相覆盖对方的修改
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+// 读取配置文件，文件不存在时返回 None
+//
+// 格式错误时返回 ConfigParseError 而不是 None，调用方不会再用默认配置覆盖用户的文件
+fn read_config() -> Result<Option<Config>, AppError> {
+    read_config_file(Path::new(CONFIG_FILE))
+}
+
+fn read_config_file(path: &Path) -> Result<Option<Config>, AppError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(AppError::FileReadError(e.to_string())),
     };
+    serde_json::from_str::<Config>(&content)
+        .map(Some)
+        .map_err(|e| AppError::ConfigParseError(format!("{}: {}", path.display(), e)))
+}
+
+// 在配置锁内读取配置、交给 update 修改后写回；配置文件无法解析时不写入
+fn update_config<T>(update: impl FnOnce(&mut Config) -> T) -> Result<T, AppError> {
+    update_config_file(Path::new(CONFIG_FILE), update)
+}
 
-    let aes_bytes = config.aes.as_bytes().to_vec();
-    let aes_key = if aes_bytes.len() >= 16 {
-        aes_bytes[..16].to_vec()
+fn update_config_file<T>(
+    path: &Path,
+    update: impl FnOnce(&mut Config) -> T,
+) -> Result<T, AppError> {
+    let _guard = CONFIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut config = read_config_file(path)?.unwrap_or_default();
+    let result = update(&mut config);
+    write_config_file(path, &config)?;
+    Ok(result)
+}
+
+fn write_config_file(path: &Path, config: &Config) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| AppError::ConfigSerializeError(e.to_string()))?;
+    fs::write(path, json).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+    Ok(())
+}
+
+// 将配置中的 AES 字符串转换为密钥字节
+//
+// 32 或 64 位十六进制串解码为 16 / 32 字节的原始密钥，其他按文本处理
+fn aes_key_from_str(aes: &str) -> Vec<u8> {
+    if is_hex_aes_key(aes) {
+        if let Some(bytes) = parse_hex_bytes(aes) {
+            return bytes;
+        }
+    }
+    aes_key_from_text(aes)
+}
+
+// 是否为十六进制编码的 16 / 32 字节密钥
+fn is_hex_aes_key(aes: &str) -> bool {
+    matches!(aes.len(), 32 | 64) && aes.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// 按文本取 AES 密钥字节（足够 32 字节时取 32 字节用于 AES-256，否则最多取 16 字节）
+fn aes_key_from_text(aes: &str) -> Vec<u8> {
+    let aes_bytes = aes.as_bytes();
+    let len = if aes_bytes.len() >= 32 {
+        32
     } else {
-        aes_bytes
+        aes_bytes.len().min(16)
+    };
+    aes_bytes[..len].to_vec()
+}
+
+// 按指定编码解析 AES 密钥："hex" 必须是有效的十六进制，"text" 按原始文本，为空时自动识别
+fn parse_aes_key(aes: &str, encoding: &str) -> Result<Vec<u8>, AppError> {
+    match encoding.to_lowercase().as_str() {
+        "" | "auto" => Ok(aes_key_from_str(aes)),
+        "text" => Ok(aes_key_from_text(aes)),
+        "hex" if aes.is_empty() => Ok(Vec::new()),
+        "hex" => parse_hex_bytes(aes)
+            .filter(|bytes| AesHandler::is_valid_key_len(bytes.len()))
+            .ok_or_else(|| {
+                AppError::ConfigParseError(format!(
+                    "AES 密钥不是有效的 32 或 64 位十六进制串: {}",
+                    aes
+                ))
+            }),
+        other => Err(AppError::ConfigParseError(format!(
+            "未知的 AES 密钥编码: {}",
+            other
+        ))),
+    }
+}
+
+// 把 AES 密钥字节转换为可写回配置的字符串，能被 aes_key_from_str 还原为相同字节
+//
+// 可打印文本原样保存，原始字节或会被误认为十六进制的文本保存为十六进制
+fn aes_key_to_str(aes: &[u8]) -> String {
+    match std::str::from_utf8(aes) {
+        Ok(text) if !is_hex_aes_key(text) && text.chars().all(|c| c.is_ascii_graphic()) => {
+            text.to_string()
+        }
+        _ => aes.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+// 读取配置文件，AES 密钥保存在凭据存储中时从凭据存储读取
+fn read_key_from_config() -> Result<(u8, Vec<u8>), AppError> {
+    match read_config()? {
+        Some(config) => keys_from_config(config, platform_secret_store().as_ref()),
+        None => Ok((0, vec![])),
+    }
+}
+
+// 从配置中取出 (XOR 密钥, AES 密钥字节)
+//
+// 配置中的明文密钥按 aes_encoding 解析；密钥存储中的字符串由 aes_key_to_str 生成，
+// 始终按自动识别还原，与 aes_encoding 无关（十六进制样式的文本密钥在存储中是十六进制）
+fn keys_from_config(config: Config, store: &dyn SecretStore) -> Result<(u8, Vec<u8>), AppError> {
+    let aes = match &config.credential_target {
+        Some(target) if config.aes.is_empty() => aes_key_from_str(
+            &store
+                .load(target)
+                .unwrap_or_else(|e| {
+                    log::warn!("从凭据存储读取密钥失败: {}", e);
+                    None
+                })
+                .unwrap_or_default(),
+        ),
+        _ => parse_aes_key(&config.aes, &config.aes_encoding)?,
     };
+    Ok((config.xor, aes))
+}
+
+// 配置文件作为密钥存储，用于没有系统凭据存储的平台（不区分目标名称）
+//
+// 与其他存储一样读写 aes_key_to_str 格式的字符串，因此保存时把 aes_encoding 重置为自动识别
+#[cfg(not(windows))]
+struct ConfigSecretStore;
+
+#[cfg(not(windows))]
+impl SecretStore for ConfigSecretStore {
+    fn save(&self, _target: &str, secret: &str) -> Result<(), AppError> {
+        update_config(|config| {
+            config.aes = secret.to_string();
+            config.aes_encoding.clear();
+        })
+    }
+
+    fn load(&self, _target: &str) -> Result<Option<String>, AppError> {
+        let Some(config) = read_config()? else {
+            return Ok(None);
+        };
+        let aes = parse_aes_key(&config.aes, &config.aes_encoding)?;
+        Ok((!aes.is_empty()).then(|| aes_key_to_str(&aes)))
+    }
+}
+
+// 当前平台的密钥存储：Windows 使用凭据管理器，其他平台回退到配置文件
+fn platform_secret_store() -> Box<dyn SecretStore> {
+    #[cfg(windows)]
+    {
+        Box::new(credentials::CredentialManager)
+    }
+
+    #[cfg(not(windows))]
+    {
+        Box::new(ConfigSecretStore)
+    }
+}
 
-    (config.xor, aes_key)
+// 读取配置文件中按文件夹覆盖的密钥
+fn read_folder_keys_from_config() -> Result<HashMap<PathBuf, (u8, Vec<u8>)>, AppError> {
+    Ok(read_config()?
+        .map(|config| {
+            config
+                .folder_keys
+                .into_iter()
+                .map(|(path, keys)| (PathBuf::from(path), (keys.xor, aes_key_from_str(&keys.aes))))
+                .collect()
+        })
+        .unwrap_or_default())
 }
 
 // 保存配置文件
-fn save_key_to_config(xor: u8, aes: &str) -> Result<(), AppError> {
-    let config = Config {
-        xor,
-        aes: aes.to_string(),
+fn save_key_to_config(xor: u8, aes: &str, encoding: &str) -> Result<(), AppError> {
+    update_config(|config| {
+        config.xor = xor;
+        config.aes = aes.to_string();
+        config.aes_encoding = encoding.to_lowercase();
+    })
+}
+
+// 保存按文件夹覆盖的密钥
+fn save_folder_keys_to_config(
+    folder_keys: &HashMap<PathBuf, (u8, Vec<u8>)>,
+) -> Result<(), AppError> {
+    let entries: HashMap<String, FolderKeyConfig> = folder_keys
+        .iter()
+        .map(|(path, (xor, aes))| {
+            (
+                path.to_string_lossy().to_string(),
+                FolderKeyConfig {
+                    xor: *xor,
+                    aes: aes_key_to_str(aes),
+                },
+            )
+        })
+        .collect();
+    update_config(|config| config.folder_keys = entries)
+}
+
+// 查找路径最近的祖先目录上设置的覆盖密钥
+fn find_folder_override(
+    folder_keys: &HashMap<PathBuf, (u8, Vec<u8>)>,
+    path: &Path,
+) -> Option<(u8, Vec<u8>)> {
+    path.ancestors()
+        .find_map(|ancestor| folder_keys.get(ancestor))
+        .cloned()
+}
+
+// 解析文件实际使用的密钥：优先使用最近祖先目录的覆盖密钥，否则使用全局密钥
+//
+// 只有当 AES 密钥长度为 16 或 32 字节时才返回它。开启 plaintext_fallback 时 XOR 密钥
+// 允许在解密时按文件头推算（未加密文件推算出密钥 0），这里不读取文件
+fn resolve_keys(state: &AppState, path: &Path) -> (XorKey, Option<Vec<u8>>) {
+    let override_keys = find_folder_override(&state.folder_keys.lock().unwrap(), path);
+    let (xor, aes) = match override_keys {
+        Some(keys) => keys,
+        None => (
+            *state.xor_key.lock().unwrap(),
+            state.aes_key.lock().unwrap().clone(),
+        ),
     };
-    let json = serde_json::to_string_pretty(&config)
-        .map_err(|e| AppError::ConfigSerializeError(e.to_string()))?;
-    fs::write(CONFIG_FILE, json).map_err(|e| AppError::FileWriteError(e.to_string()))?;
-    Ok(())
+
+    let xor_key = XorKey {
+        key: xor,
+        recover: state.plaintext_fallback.load(Ordering::Relaxed),
+    };
+    let aes_option = AesHandler::is_valid_key_len(aes.len()).then_some(aes);
+    (xor_key, aes_option)
 }
 
 // 打开文件夹对话框
@@ -162,10 +926,13 @@ async fn open_folder_dialog(
         *state.root_dir.lock().unwrap() = Some(path_buf.to_path_buf());
         state.image_cache.lock().unwrap().clear();
 
-        // 读取配置文件中的密钥
-        let (xor, aes) = read_key_from_config();
-        *state.xor_key.lock().unwrap() = xor;
-        *state.aes_key.lock().unwrap() = aes;
+        // 读取配置文件中的密钥（仅会话模式下保留内存中的密钥）
+        if !state.session_only_keys.load(Ordering::Relaxed) {
+            let (xor, aes) = read_key_from_config()?;
+            *state.xor_key.lock().unwrap() = xor;
+            *state.aes_key.lock().unwrap() = aes;
+            *state.folder_keys.lock().unwrap() = read_folder_keys_from_config()?;
+        }
 
         Ok(path_str)
     } else {
@@ -182,62 +949,70 @@ fn get_folder_tree(state: State<AppState>) -> Result<TreeNode, String> {
         .ok_or(AppError::RootDirNotSet)
         .map_err(|e| String::from(e))?;
 
-    fn build_tree(dir_path: &Path) -> Result<TreeNode, AppError> {
-        let name = dir_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-        let path = dir_path.to_string_lossy().to_string();
-        let mut children = Vec::new();
-
-        let entries = match fs::read_dir(dir_path) {
-            Ok(entries) => entries,
-            Err(e) => {
-                log::warn!("无法读取目录 {}: {}", path, e);
-                return Ok(TreeNode {
-                    name,
-                    path,
-                    children,
-                });
-            }
-        };
-
-        for entry in entries.flatten() {
-            let file_type = match entry.file_type() {
-                Ok(ft) => ft,
-                Err(_) => continue,
-            };
+    let mut walk = WalkState::new(WalkOptions::default());
+    walk.enter_dir(root_path, 0);
+    Ok(build_tree(root_path, 0, &mut walk))
+}
 
-            if !file_type.is_dir() {
-                continue;
-            }
+// 递归构建目录树，达到遍历限制的节点标记为 truncated
+fn build_tree(dir_path: &Path, depth: usize, walk: &mut WalkState) -> TreeNode {
+    let name = dir_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_string();
+    let path = dir_path.to_string_lossy().to_string();
+    let mut children = Vec::new();
+    let mut truncated = false;
 
-            let child = match build_tree(&entry.path()) {
-                Ok(child) => child,
-                Err(e) => {
-                    log::warn!("递归构建树失败: {}", e);
-                    continue;
-                }
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("无法读取目录 {}: {}", path, e);
+            return TreeNode {
+                name,
+                path,
+                children,
+                truncated,
             };
+        }
+    };
 
-            children.push(child);
+    for entry in entries.flatten() {
+        if !walk.take_entry() {
+            truncated = true;
+            break;
         }
 
-        Ok(TreeNode {
-            name,
-            path,
-            children,
-        })
+        if !matches!(walk.entry_kind(&entry), Some((true, _))) {
+            continue;
+        }
+
+        if depth + 1 > walk.options.max_depth {
+            truncated = true;
+        }
+        if walk.enter_dir(&entry.path(), depth + 1) {
+            children.push(build_tree(&entry.path(), depth + 1, walk));
+        }
     }
 
-    build_tree(root_path).map_err(|e| String::from(e))
+    TreeNode {
+        name,
+        path,
+        children,
+        truncated,
+    }
 }
 
-// 获取文件夹中的图片
+// 获取文件夹中的图片（不递归子目录）
+//
+// deduplicate 默认为 true，与批量解密、导出等命令一样对同一 hash 只保留最佳版本；
+// 需要查看原始文件列表（例如排查缩略图和高清图是否都存在）时传 false。
+// 目前前端没有依赖原始列表的调用方，去重分组详情请使用 preview_dedup。
 #[tauri::command]
 fn get_images_in_folder(
     folder_path: String,
+    deduplicate: Option<bool>,
     state: State<AppState>,
 ) -> Result<Vec<ImageInfo>, String> {
     let root_dir = state.root_dir.lock().unwrap();
@@ -246,125 +1021,237 @@ fn get_images_in_folder(
         .ok_or(AppError::RootDirNotSet)
         .map_err(|e| String::from(e))?;
 
-    let folder = Path::new(&folder_path);
-    if !folder.starts_with(root_path) {
-        return Err(String::from(AppError::InvalidPath(folder_path)));
+    let folder = resolve_folder_in_root(root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    Ok(list_folder_images(
+        &folder,
+        root_path,
+        deduplicate.unwrap_or(true),
+    ))
+}
+
+// 列出文件夹自身的图片，deduplicate 为 true 时按 hash 去重
+fn list_folder_images(folder: &Path, root_path: &Path, deduplicate: bool) -> Vec<ImageInfo> {
+    let images = collect_images(folder, root_path, false, false);
+    if deduplicate {
+        deduplicate_images_by_hash(images)
+    } else {
+        images
     }
+}
 
-    let mut images = Vec::new();
+// 计算目录签名：对各条目的名称、大小和修改时间做哈希，不读取文件内容
+fn compute_folder_signature(folder: &Path) -> Result<String, AppError> {
+    use std::hash::{Hash, Hasher};
 
-    let entries = match fs::read_dir(folder) {
-        Ok(entries) => entries,
-        Err(e) => {
-            log::warn!("无法读取文件夹 {}: {}", folder_path, e);
-            return Ok(images);
-        }
-    };
+    let entries = fs::read_dir(folder).map_err(|e| AppError::FileReadError(e.to_string()))?;
 
-    for entry in entries.flatten() {
-        let file_type = match entry.file_type() {
-            Ok(ft) => ft,
-            Err(_) => continue,
-        };
+    let mut stamps: Vec<(String, u64, u128)> = entries
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (len, modified) = entry
+                .metadata()
+                .map(|m| {
+                    let modified = m
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_nanos())
+                        .unwrap_or(0);
+                    (m.len(), modified)
+                })
+                .unwrap_or((0, 0));
+            (name, len, modified)
+        })
+        .collect();
 
-        if !file_type.is_file() {
-            continue;
-        }
+    // read_dir 的返回顺序不固定，排序后再哈希保证签名稳定
+    stamps.sort();
 
-        let path = entry.path();
-        let filename = match path.file_name().and_then(|n| n.to_str()) {
-            Some(name) => name,
-            None => continue,
-        };
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    stamps.hash(&mut hasher);
 
-        // 检查是否是 .dat 文件或 Sns 缓存文件
-        let is_dat = filename.to_lowercase().ends_with(".dat");
-        let is_sns = is_valid_sns_filename(filename);
+    Ok(format!("{}-{:016x}", stamps.len(), hasher.finish()))
+}
 
-        if !is_dat && !is_sns {
-            continue;
-        }
+// 获取文件夹签名，前端据此判断缓存的列表是否过期
+#[tauri::command]
+fn folder_signature(folder_path: String, state: State<AppState>) -> Result<String, String> {
+    let root_dir = state.root_dir.lock().unwrap();
+    let root_path = root_dir
+        .as_ref()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
 
-        // 检查是否是缩略图
-        let is_thumbnail = filename.to_lowercase().ends_with("_t.dat");
+    let folder = resolve_folder_in_root(root_path, &folder_path).map_err(|e| String::from(e))?;
 
-        let rel_path = match path.strip_prefix(root_path) {
-            Ok(p) => p,
-            Err(_) => continue,
-        };
+    compute_folder_signature(&folder).map_err(|e| String::from(e))
+}
 
-        // 获取文件元数据
-        let metadata = match fs::metadata(&path) {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+// 递归遍历目录时的限制
+#[derive(Clone, Copy, Debug)]
+struct WalkOptions {
+    // 最大递归深度，起始目录为 0
+    max_depth: usize,
+    // 是否跟随指向目录的符号链接
+    follow_symlinks: bool,
+    // 最多检查的目录条目数
+    max_entries: usize,
+}
 
-        let size = metadata.len();
-        let modified = metadata
-            .modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-
-        images.push(ImageInfo {
-            path: rel_path.to_string_lossy().to_string(),
-            name: filename.to_string(),
-            size,
-            modified,
-            is_thumbnail,
-        });
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 32,
+            follow_symlinks: false,
+            max_entries: 1_000_000,
+        }
     }
+}
 
-    Ok(images)
+// 一次遍历过程中的计数和已访问目录
+struct WalkState {
+    options: WalkOptions,
+    entries: usize,
+    // 是否因达到限制而提前停止
+    truncated: bool,
+    // 跟随符号链接时已进入过的目录（规范化路径），用于打破循环
+    visited: HashSet<PathBuf>,
 }
 
-// 批量获取图片（带排序、筛选和分页）
-#[tauri::command]
-async fn get_images_batch(
-    folder_path: String,
-    page: usize,
-    page_size: usize,
-    sort_by: String,
-    sort_order: String,
-    hide_thumbnails: bool,
-    state: State<'_, AppState>,
-) -> Result<ImageBatch, String> {
-    let root_dir = state.root_dir.lock().unwrap().clone();
-    let root_path = root_dir
-        .as_ref()
-        .ok_or(AppError::RootDirNotSet)
-        .map_err(|e| String::from(e))?
-        .clone();
+impl WalkState {
+    fn new(options: WalkOptions) -> Self {
+        Self {
+            options,
+            entries: 0,
+            truncated: false,
+            visited: HashSet::new(),
+        }
+    }
 
-    let folder = Path::new(&folder_path);
-    if !folder.starts_with(&root_path) {
-        return Err(String::from(AppError::InvalidPath(folder_path)));
+    // 判断是否进入目录：超出深度时标记截断，已访问过的目录（符号链接循环）直接跳过
+    fn enter_dir(&mut self, dir: &Path, depth: usize) -> bool {
+        if depth > self.options.max_depth {
+            self.truncated = true;
+            return false;
+        }
+        if self.options.follow_symlinks {
+            return match fs::canonicalize(dir) {
+                Ok(canonical) => self.visited.insert(canonical),
+                Err(_) => false,
+            };
+        }
+        true
+    }
+
+    // 记录检查了一个条目，超出条目上限时标记截断并返回 false
+    fn take_entry(&mut self) -> bool {
+        if self.entries >= self.options.max_entries {
+            self.truncated = true;
+            return false;
+        }
+        self.entries += 1;
+        true
+    }
+
+    // 返回条目是否为 (目录, 文件)；不跟随符号链接时符号链接两者都不是
+    fn entry_kind(&self, entry: &fs::DirEntry) -> Option<(bool, bool)> {
+        let file_type = entry.file_type().ok()?;
+        if file_type.is_symlink() && self.options.follow_symlinks {
+            let metadata = fs::metadata(entry.path()).ok()?;
+            return Some((metadata.is_dir(), metadata.is_file()));
+        }
+        Some((file_type.is_dir(), file_type.is_file()))
     }
+}
+
+// 收集文件夹中的图片信息，recursive 为 true 时递归子目录
+//
+// 使用默认的遍历限制，不会跟随符号链接
+fn collect_images(
+    folder: &Path,
+    root_path: &Path,
+    hide_thumbnails: bool,
+    recursive: bool,
+) -> Vec<ImageInfo> {
+    walk_images(
+        folder,
+        root_path,
+        hide_thumbnails,
+        recursive,
+        WalkOptions::default(),
+    )
+    .0
+}
 
-    // 获取所有图片信息
+// 按指定遍历限制收集图片，返回 (图片列表, 是否因限制提前停止)
+fn walk_images(
+    folder: &Path,
+    root_path: &Path,
+    hide_thumbnails: bool,
+    recursive: bool,
+    options: WalkOptions,
+) -> (Vec<ImageInfo>, bool) {
+    let mut walk = WalkState::new(options);
     let mut images = Vec::new();
+    if walk.enter_dir(folder, 0) {
+        collect_images_into(
+            folder,
+            root_path,
+            hide_thumbnails,
+            recursive,
+            0,
+            &mut walk,
+            &mut images,
+        );
+    }
+    (images, walk.truncated)
+}
+
+fn collect_images_into(
+    folder: &Path,
+    root_path: &Path,
+    hide_thumbnails: bool,
+    recursive: bool,
+    depth: usize,
+    walk: &mut WalkState,
+    images: &mut Vec<ImageInfo>,
+) {
     let entries = match fs::read_dir(folder) {
         Ok(entries) => entries,
         Err(e) => {
-            log::warn!("无法读取文件夹 {}: {}", folder_path, e);
-            return Ok(ImageBatch {
-                images: vec![],
-                total: 0,
-                page,
-                page_size,
-                has_more: false,
-            });
+            log::warn!("无法读取文件夹 {}: {}", folder.display(), e);
+            return;
         }
     };
 
     for entry in entries.flatten() {
-        let file_type = match entry.file_type() {
-            Ok(ft) => ft,
-            Err(_) => continue,
+        if !walk.take_entry() {
+            return;
+        }
+
+        let (is_dir, is_file) = match walk.entry_kind(&entry) {
+            Some(kind) => kind,
+            None => continue,
         };
 
-        if !file_type.is_file() {
+        if is_dir {
+            if recursive && walk.enter_dir(&entry.path(), depth + 1) {
+                collect_images_into(
+                    &entry.path(),
+                    root_path,
+                    hide_thumbnails,
+                    recursive,
+                    depth + 1,
+                    walk,
+                    images,
+                );
+            }
+            continue;
+        }
+
+        if !is_file {
             continue;
         }
 
@@ -374,52 +1261,147 @@ async fn get_images_batch(
             None => continue,
         };
 
-        let is_dat = filename.to_lowercase().ends_with(".dat");
+        let is_dat = filename.to_lowercase().ends_with(DAT_EXTENSION);
         let is_sns = is_valid_sns_filename(filename);
 
         if !is_dat && !is_sns {
             continue;
         }
 
-        let is_thumbnail = filename.to_lowercase().ends_with("_t.dat") || filename.ends_with("_t");
+        let info = match image_info_from_path(&path, root_path) {
+            Some(info) => info,
+            None => continue,
+        };
 
         // 筛选缩略图
-        if hide_thumbnails && is_thumbnail {
+        if hide_thumbnails && info.is_thumbnail {
             continue;
         }
 
-        let rel_path = match path.strip_prefix(&root_path) {
-            Ok(p) => p,
-            Err(_) => continue,
-        };
+        images.push(info);
+    }
+}
 
-        let metadata = match fs::metadata(&path) {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+// 在 base_dir 下查找直接包含较多 DAT / Sns 文件的目录，按图片数量从多到少排列
+//
+// 只统计目录自身的文件，不跟随符号链接，遍历深度和条目数受 options 限制
+fn scan_image_roots(base_dir: &Path, options: WalkOptions) -> Vec<(PathBuf, usize)> {
+    let mut walk = WalkState::new(options);
+    let mut roots = Vec::new();
+    if walk.enter_dir(base_dir, 0) {
+        scan_image_roots_into(base_dir, 0, &mut walk, &mut roots);
+    }
 
-        let size = metadata.len();
-        let modified = metadata
-            .modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-
-        images.push(ImageInfo {
-            path: rel_path.to_string_lossy().to_string(),
-            name: filename.to_string(),
-            size,
-            modified,
-            is_thumbnail,
-        });
+    roots.sort_by(|(a_path, a_count), (b_path, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_path.cmp(b_path))
+    });
+    roots
+}
+
+fn scan_image_roots_into(
+    dir: &Path,
+    depth: usize,
+    walk: &mut WalkState,
+    roots: &mut Vec<(PathBuf, usize)>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("无法读取文件夹 {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut files = 0usize;
+    let mut images = 0usize;
+    let mut subdirs = Vec::new();
+
+    for entry in entries.flatten() {
+        if !walk.take_entry() {
+            break;
+        }
+
+        match walk.entry_kind(&entry) {
+            Some((true, _)) => subdirs.push(entry.path()),
+            Some((_, true)) => {
+                files += 1;
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name.to_lowercase().ends_with(DAT_EXTENSION) || is_valid_sns_filename(&name) {
+                    images += 1;
+                }
+            }
+            _ => {}
+        }
     }
 
-    // 去重：为同一hash的图片组选择最佳版本（优先级：_t > 无后缀 > _h）
-    images = deduplicate_images_by_hash(images);
+    if images >= IMAGE_ROOT_MIN_FILES && images as f64 >= files as f64 * IMAGE_ROOT_MIN_DENSITY {
+        roots.push((dir.to_path_buf(), images));
+    }
+
+    for subdir in subdirs {
+        if walk.enter_dir(&subdir, depth + 1) {
+            scan_image_roots_into(&subdir, depth + 1, walk, roots);
+        }
+    }
+}
+
+// 在选定的微信数据目录下查找可能的图片目录，按图片数量从多到少返回绝对路径
+//
+// 只返回直接包含较多 DAT / Sns 文件的目录，供用户选择根目录时参考；遍历深度有限制
+#[tauri::command]
+async fn find_image_roots(base_dir: String) -> Result<Vec<String>, String> {
+    let base = PathBuf::from(&base_dir);
+    if !base.is_dir() {
+        return Err(String::from(AppError::InvalidPath(base_dir)));
+    }
+
+    let options = WalkOptions {
+        max_depth: IMAGE_ROOT_MAX_DEPTH,
+        max_entries: IMAGE_ROOT_MAX_ENTRIES,
+        ..Default::default()
+    };
+    let roots = tokio::task::spawn_blocking(move || scan_image_roots(&base, options))
+        .await
+        .map_err(|err| format!("查找图片目录失败: {}", err))?;
+
+    Ok(roots
+        .into_iter()
+        .map(|(path, _)| path.to_string_lossy().to_string())
+        .collect())
+}
 
-    // 排序
-    match (sort_by.as_str(), sort_order.as_str()) {
+// 读取单个文件的图片信息，文件不在根目录下或无法读取元数据时返回 None
+fn image_info_from_path(path: &Path, root_path: &Path) -> Option<ImageInfo> {
+    let filename = path.file_name()?.to_str()?;
+    let is_thumbnail = filename
+        .to_lowercase()
+        .ends_with(&format!("{}{}", THUMBNAIL_SUFFIX, DAT_EXTENSION))
+        || filename.ends_with(THUMBNAIL_SUFFIX);
+
+    let rel_path = path.strip_prefix(root_path).ok()?;
+    let metadata = fs::metadata(path).ok()?;
+
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    Some(ImageInfo {
+        path: rel_path.to_string_lossy().to_string(),
+        name: filename.to_string(),
+        size,
+        modified,
+        is_thumbnail,
+    })
+}
+
+// 按指定字段和顺序排序图片
+fn sort_images(images: &mut [ImageInfo], sort_by: &str, sort_order: &str) {
+    match (sort_by, sort_order) {
         ("name", "asc") => images.sort_by(|a, b| a.name.cmp(&b.name)),
         ("name", "desc") => images.sort_by(|a, b| b.name.cmp(&a.name)),
         ("time", "asc") => images.sort_by(|a, b| a.modified.cmp(&b.modified)),
@@ -428,95 +1410,59 @@ async fn get_images_batch(
         ("size", "desc") => images.sort_by(|a, b| b.size.cmp(&a.size)),
         _ => {}
     }
+}
 
+// 分页：返回当前页的图片、总数以及是否还有更多
+fn paginate_images(
+    images: Vec<ImageInfo>,
+    page: usize,
+    page_size: usize,
+) -> (Vec<ImageInfo>, usize, bool) {
     let total = images.len();
     let start = page * page_size;
     let end = (start + page_size).min(total);
     let has_more = end < total;
 
-    // 分页
-    let page_images: Vec<ImageInfo> = images.into_iter().skip(start).take(page_size).collect();
-
-    let xor_key = *state.xor_key.lock().unwrap();
-    let aes_key = state.aes_key.lock().unwrap().clone();
-    let aes_key_option = if aes_key.len() == 16 {
-        Some(aes_key)
-    } else {
-        None
-    };
-    let cache = state.image_cache.clone();
-    let semaphore = state.decrypt_semaphore.clone();
-
-    let mut images_with_data = Vec::with_capacity(page_images.len());
-
-    for (index, img_info) in page_images.into_iter().enumerate() {
-        let image_id = img_info.path.clone();
-        let cached_mime = {
-            let cache_map = cache.lock().unwrap();
-            cache_map
-                .get(&image_id)
-                .map(|entry| entry.mime_type.clone())
-        };
-
-        images_with_data.push(ImageWithData {
-            path: img_info.path.clone(),
-            name: img_info.name.clone(),
-            size: img_info.size,
-            modified: img_info.modified,
-            is_thumbnail: img_info.is_thumbnail,
-            image_id: image_id.clone(),
-            mime_type: cached_mime.clone(),
-        });
-
-        if index >= PREFETCH_LIMIT || cached_mime.is_some() {
-            continue;
-        }
-
-        let root_path_clone = root_path.clone();
-        let cache_clone = cache.clone();
-        let semaphore_clone = semaphore.clone();
-        let aes_key_clone = aes_key_option.clone();
-        let image_info_clone = img_info.clone();
-        let xor_key_clone = xor_key;
+    let page_images = images.into_iter().skip(start).take(page_size).collect();
+    (page_images, total, has_more)
+}
 
-        tokio::spawn(async move {
-            let full_path = root_path_clone.join(&image_info_clone.path);
+// 对排序后的图片分页，构造批量响应，并在后台预取当前页前几张图片
+//
+// 读取每张图片版本和尺寸的文件 I/O 在阻塞线程中进行，不占用异步运行时
+async fn build_image_batch(
+    state: &AppState,
+    root_path: &Path,
+    images: Vec<ImageInfo>,
+    page: usize,
+    page_size: usize,
+) -> Result<ImageBatch, AppError> {
+    let (page_images, total, has_more) = paginate_images(images, page, page_size);
 
-            let permit = match semaphore_clone.acquire_owned().await {
-                Ok(permit) => permit,
-                Err(err) => {
-                    log::warn!("获取解密许可失败 {}: {}", image_info_clone.path, err);
-                    return;
-                }
-            };
+    let jobs: Vec<_> = page_images
+        .into_iter()
+        .map(|info| {
+            let path = root_path.join(&info.path);
+            let (xor_key, aes_key) = resolve_keys(state, &path);
+            let cached_mime = state.image_cache.lock().unwrap().mime_type(&info.path);
+            (info, path, xor_key, aes_key, cached_mime)
+        })
+        .collect();
 
-            let decrypt_result = tokio::task::spawn_blocking(move || {
-                DatDecryptor::decrypt(&full_path, xor_key_clone, aes_key_clone.as_deref())
-                    .map(|data| normalize_decrypted_image(data))
+    let images_with_data: Vec<ImageWithData> = tokio::task::spawn_blocking(move || {
+        jobs.into_iter()
+            .map(|(info, path, xor_key, aes_key, cached_mime)| {
+                image_with_data(info, &path, xor_key, aes_key.as_deref(), cached_mime)
             })
-            .await;
-
-            drop(permit);
+            .collect()
+    })
+    .await
+    .map_err(|err| AppError::Internal(format!("读取文件头任务执行失败: {}", err)))?;
 
-            match decrypt_result {
-                Ok(Ok((normalized_data, mime_type))) => {
-                    let mut cache_map = cache_clone.lock().unwrap();
-                    cache_map.insert(
-                        image_info_clone.path.clone(),
-                        CachedImage {
-                            data: normalized_data,
-                            mime_type,
-                        },
-                    );
-                }
-                Ok(Err(err)) => {
-                    log::warn!("解密失败 {}: {:?}", image_info_clone.path, err);
-                }
-                Err(err) => {
-                    log::warn!("解密任务执行失败 {}: {}", image_info_clone.path, err);
-                }
-            }
-        });
+    for image in images_with_data.iter().take(PREFETCH_LIMIT) {
+        if image.mime_type.is_none() {
+            spawn_prefetch(state, root_path, image.image_id.clone());
+        }
     }
 
     Ok(ImageBatch {
@@ -525,307 +1471,7900 @@ async fn get_images_batch(
         page,
         page_size,
         has_more,
+        truncated: false,
     })
 }
 
-// 检测图片 MIME 类型
-fn detect_mime_type(data: &[u8]) -> &'static str {
-    if data.len() < 4 {
-        return "application/octet-stream";
-    }
-
-    // JPEG: FF D8 FF
-    if data.len() >= 3 && data[0] == 0xFF && data[1] == 0xD8 && data[2] == 0xFF {
-        return "image/jpeg";
-    }
-
-    // PNG: 89 50 4E 47
-    if data.len() >= 4 && data[0] == 0x89 && data[1] == 0x50 && data[2] == 0x4E && data[3] == 0x47 {
-        return "image/png";
-    }
+// 构造单张图片的批量响应项：只打开一次文件，读取 DAT 版本后用同一个句柄解密文件开头
+// 读取图片尺寸，不解码像素；无法识别时尺寸为 (0, 0)
+fn image_with_data(
+    info: ImageInfo,
+    path: &Path,
+    xor_key: XorKey,
+    aes_key: Option<&[u8]>,
+    cached_mime: Option<String>,
+) -> ImageWithData {
+    let (version, (width, height)) = match fs::File::open(path) {
+        Ok(mut file) => {
+            let version = VersionDetector::detect_from(&mut file).unwrap_or(DatVersion::Unknown);
+            let dimensions = DatDecryptor::decrypt_prefix_from(
+                &mut file,
+                version,
+                xor_key,
+                aes_key,
+                GRID_HEAD_BYTES,
+            )
+            .ok()
+            .and_then(|head| image_dimensions(&head))
+            .unwrap_or((0, 0));
+            (version, dimensions)
+        }
+        Err(_) => (DatVersion::Unknown, (0, 0)),
+    };
 
-    // GIF: 47 49 46
-    if data.len() >= 3 && data[0] == 0x47 && data[1] == 0x49 && data[2] == 0x46 {
-        return "image/gif";
+    ImageWithData {
+        image_id: info.path.clone(),
+        path: info.path,
+        name: info.name,
+        size: info.size,
+        modified: info.modified,
+        is_thumbnail: info.is_thumbnail,
+        mime_type: cached_mime,
+        width,
+        height,
+        version: version_label(version).to_string(),
     }
+}
 
-    // WebP: 52 49 46 46 ... 57 45 42 50
-    if data.len() >= 12
-        && data[0] == 0x52
-        && data[1] == 0x49
-        && data[2] == 0x46
-        && data[3] == 0x46
-        && data[8] == 0x57
-        && data[9] == 0x45
-        && data[10] == 0x42
-        && data[11] == 0x50
-    {
-        return "image/webp";
+// 遍历文件夹，可选按修改时间筛选，再去重和排序，返回图片列表以及是否达到遍历限制
+//
+// 需要遍历目录并读取文件元数据，应在阻塞线程中调用
+fn list_sorted_images(
+    folder: &Path,
+    root_path: &Path,
+    hide_thumbnails: bool,
+    recursive: bool,
+    time_range: Option<(u64, u64)>,
+    sort_by: &str,
+    sort_order: &str,
+) -> (Vec<ImageInfo>, bool) {
+    let (mut images, truncated) = walk_images(
+        folder,
+        root_path,
+        hide_thumbnails,
+        recursive,
+        WalkOptions::default(),
+    );
+    if let Some((start_ts, end_ts)) = time_range {
+        filter_images_by_time(&mut images, start_ts, end_ts);
     }
 
-    "image/jpeg" // 默认为 JPEG
+    // 去重：为同一hash的图片组选择最佳版本（优先级：_t > 无后缀 > _h）
+    images = deduplicate_images_by_hash(images);
+    sort_images(&mut images, sort_by, sort_order);
+    (images, truncated)
 }
 
-/// 对解密后的图片数据进行规范化处理
-///
-/// - 检测带有 WXGF 头的数据并尝试通过 DLL 转换成标准图片
-/// - 返回转换后的数据及其 MIME 类型
-fn normalize_decrypted_image(data: Vec<u8>) -> (Vec<u8>, String) {
-    #[cfg(windows)]
-    if data.len() < 4 {
-        let mime = detect_mime_type(&data).to_string();
-        return (data, mime);
+// 在后台解密图片并写入缓存，无界面模式下不预取
+fn spawn_prefetch(state: &AppState, root_path: &Path, image_id: String) {
+    if state.headless.load(Ordering::Relaxed) {
+        return;
     }
 
-    let header = &data[..4];
-    if header != b"wxgf" && header != b"WXGF" {
-        let mime = detect_mime_type(&data).to_string();
-        return (data, mime);
-    }
+    let full_path = root_path.join(&image_id);
+    let cache_clone = state.image_cache.clone();
+    let semaphore_clone = state.decrypt_semaphore.clone();
+    let (xor_key_clone, aes_key_clone) = resolve_keys(state, &full_path);
 
-    match crate::dll::wxam_to_image(&data, "jpeg") {
-        Ok(converted) => {
-            log::debug!(
-                "检测到 WXGF 图片,已通过 DLL 转换,输出大小: {} 字节",
-                converted.len()
-            );
-            let mime = detect_mime_type(&converted).to_string();
-            return (converted, mime);
-        }
-        Err(err) => {
-            log::warn!("WXGF 图片转换失败: {}", err);
-        }
-    }
+    tokio::spawn(async move {
+        let permit = match semaphore_clone.acquire_owned().await {
+            Ok(permit) => permit,
+            Err(err) => {
+                log::warn!("获取解密许可失败 {}: {}", image_id, err);
+                return;
+            }
+        };
 
-    let mime = detect_mime_type(&data).to_string();
-    (data, mime)
-}
+        let decrypt_result = tokio::task::spawn_blocking(move || {
+            api::decrypt_image(&full_path, xor_key_clone, aes_key_clone.as_deref())
+        })
+        .await;
 
-// 提取文件名的hash部分（不包含后缀）
-fn extract_hash_from_filename(filename: &str) -> String {
-    let name_without_ext = filename
-        .trim_end_matches(".dat")
-        .trim_end_matches("_t")
-        .trim_end_matches("_h");
-    name_without_ext.to_string()
+        drop(permit);
+
+        match decrypt_result {
+            Ok(Ok((normalized_data, mime_type))) => {
+                cache_store(
+                    &cache_clone,
+                    image_id,
+                    CachedImage {
+                        data: normalized_data,
+                        mime_type,
+                    },
+                )
+                .await;
+            }
+            Ok(Err(err)) => {
+                log::warn!("解密失败 {}: {}", image_id, err);
+            }
+            Err(err) => {
+                log::warn!("解密任务执行失败 {}: {}", image_id, err);
+            }
+        }
+    });
 }
 
-// 获取图片版本优先级（数字越小优先级越高）
-fn get_image_priority(filename: &str) -> u8 {
-    let lower = filename.to_lowercase();
-    if lower.ends_with("_t.dat") || lower.ends_with("_t") {
-        0 // 缩略图优先级最高
-    } else if lower.ends_with("_h.dat") || lower.ends_with("_h") {
-        2 // 原图优先级最低
-    } else {
-        1 // 中等规格优先级居中
-    }
+// 批量获取图片（带排序、筛选和分页）
+//
+// recursive 为 true 时遍历子目录（微信按日期分子目录存放图片），使用默认的深度和条目数限制，
+// 不跟随符号链接；达到限制时返回的 truncated 为 true
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn get_images_batch(
+    folder_path: String,
+    page: usize,
+    page_size: usize,
+    sort_by: String,
+    sort_order: String,
+    hide_thumbnails: bool,
+    recursive: bool,
+    state: State<'_, AppState>,
+) -> Result<ImageBatch, String> {
+    folder_images_batch(
+        &state,
+        &folder_path,
+        page,
+        page_size,
+        &sort_by,
+        &sort_order,
+        hide_thumbnails,
+        recursive,
+    )
+    .await
+    .map_err(|e| String::from(e))
 }
 
-// 去重：为同一hash的图片组选择最佳版本
-fn deduplicate_images_by_hash(images: Vec<ImageInfo>) -> Vec<ImageInfo> {
-    use std::collections::HashMap;
+#[allow(clippy::too_many_arguments)]
+async fn folder_images_batch(
+    state: &AppState,
+    folder_path: &str,
+    page: usize,
+    page_size: usize,
+    sort_by: &str,
+    sort_order: &str,
+    hide_thumbnails: bool,
+    recursive: bool,
+) -> Result<ImageBatch, AppError> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)?;
 
-    let mut hash_map: HashMap<String, ImageInfo> = HashMap::new();
+    let folder = resolve_folder_in_root(&root_path, folder_path)?;
 
-    for img in images {
-        let hash = extract_hash_from_filename(&img.name);
+    let walk_root = root_path.clone();
+    let (sort_by, sort_order) = (sort_by.to_string(), sort_order.to_string());
+    let (images, truncated) = tokio::task::spawn_blocking(move || {
+        list_sorted_images(
+            &folder,
+            &walk_root,
+            hide_thumbnails,
+            recursive,
+            None,
+            &sort_by,
+            &sort_order,
+        )
+    })
+    .await
+    .map_err(|err| AppError::Internal(format!("遍历文件夹任务执行失败: {}", err)))?;
 
-        // 如果这个hash还没有记录，直接插入
-        let Some(existing) = hash_map.get(&hash) else {
-            hash_map.insert(hash, img);
-            continue;
-        };
+    let mut batch = build_image_batch(state, &root_path, images, page, page_size).await?;
+    batch.truncated = truncated;
+    Ok(batch)
+}
 
-        // 如果当前图片优先级更高，就更新
-        let current_priority = get_image_priority(&img.name);
-        let existing_priority = get_image_priority(&existing.name);
+// 根据解密后的文件开头判断 MIME 类型，无法识别时返回 None
+fn head_mime_type(head: &[u8]) -> Option<String> {
+    match media_kind(head) {
+        "image" => sniff_image_mime(head).map(|mime| mime.to_string()),
+        "wxgf" => Some("image/wxgf".to_string()),
+        "video" => Some("video/mp4".to_string()),
+        _ => None,
+    }
+}
 
-        if current_priority < existing_priority {
-            hash_map.insert(hash, img);
+// 只解密文件开头，从中读取网格布局需要的类型和尺寸
+fn grid_item(info: ImageInfo, path: &Path, xor_key: XorKey, aes_key: Option<&[u8]>) -> GridItem {
+    let head = match DatDecryptor::decrypt_prefix(path, xor_key, aes_key, GRID_HEAD_BYTES) {
+        Ok(head) => head,
+        Err(err) => {
+            log::warn!("读取文件头失败 {}: {:?}", info.path, err);
+            Vec::new()
         }
-    }
+    };
 
-    // 收集所有选中的图片
-    hash_map.into_values().collect()
-}
+    let kind = media_kind(&head);
+    let mime_type = head_mime_type(&head);
+    let dims = image_dimensions(&head);
 
-// 检查是否是有效的 Sns 文件名
-fn is_valid_sns_filename(filename: &str) -> bool {
-    let name = filename.trim_end_matches("_t");
-    let len = name.len();
-    (len == 30 || len == 32) && name.chars().all(|c| c.is_alphanumeric())
+    GridItem {
+        image_id: info.path,
+        name: info.name,
+        size: info.size,
+        modified: info.modified,
+        is_thumbnail: info.is_thumbnail,
+        kind: kind.to_string(),
+        mime_type,
+        width: dims.map(|(width, _)| width),
+        height: dims.map(|(_, height)| height),
+    }
 }
 
-// 解密 DAT 文件
+// 获取一页图片的网格布局元数据（类型和尺寸），只解密文件开头，不写入图片缓存
+//
+// 排序、筛选和分页与 get_images_batch 一致，前端可先完成布局再按需加载图片数据
 #[tauri::command]
-fn decrypt_dat_file(file_path: String, state: State<AppState>) -> Result<String, String> {
-    let root_dir = state.root_dir.lock().unwrap();
-    let root_path = root_dir
-        .as_ref()
+async fn get_grid_metadata(
+    folder_path: String,
+    page: usize,
+    page_size: usize,
+    sort_by: String,
+    sort_order: String,
+    hide_thumbnails: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<GridItem>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
         .ok_or(AppError::RootDirNotSet)
         .map_err(|e| String::from(e))?;
 
-    let full_path = root_path.join(&file_path);
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
 
-    if !full_path.exists() {
-        return Err(String::from(AppError::FileNotFound(file_path)));
-    }
+    let mut images = collect_images(&folder, &root_path, hide_thumbnails, false);
+    images = deduplicate_images_by_hash(images);
+    sort_images(&mut images, &sort_by, &sort_order);
+    let (page_images, _, _) = paginate_images(images, page, page_size);
 
-    let xor_key = *state.xor_key.lock().unwrap();
-    let aes_key = state.aes_key.lock().unwrap();
+    let jobs: Vec<(ImageInfo, PathBuf, XorKey, Option<Vec<u8>>)> = page_images
+        .into_iter()
+        .map(|info| {
+            let path = root_path.join(&info.path);
+            let (xor_key, aes_key) = resolve_keys(&state, &path);
+            (info, path, xor_key, aes_key)
+        })
+        .collect();
 
-    // 只有当 AES 密钥长度为 16 字节时才使用它
-    let aes_key_option = if aes_key.len() == 16 {
-        Some(aes_key.as_slice())
-    } else {
-        None
+    tokio::task::spawn_blocking(move || {
+        jobs.into_iter()
+            .map(|(info, path, xor_key, aes_key)| {
+                grid_item(info, &path, xor_key, aes_key.as_deref())
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| format!("网格元数据任务执行失败: {}", err))
+}
+
+// 根据可见窗口规划预取和淘汰
+//
+// 预取可见窗口之后 ahead 张未缓存的图片；淘汰距离可见窗口超过 ahead 张的已缓存图片。
+// 可见图片都不在列表中时不做任何处理。返回 (需要预取, 需要淘汰)
+fn plan_smart_prefetch(
+    ordered_ids: &[String],
+    visible_ids: &[String],
+    ahead: usize,
+    is_cached: impl Fn(&str) -> bool,
+) -> (Vec<String>, Vec<String>) {
+    let positions: Vec<usize> = visible_ids
+        .iter()
+        .filter_map(|id| ordered_ids.iter().position(|candidate| candidate == id))
+        .collect();
+
+    let (first, last) = match (positions.iter().min(), positions.iter().max()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return (Vec::new(), Vec::new()),
     };
 
-    // 解密文件
-    let decrypted_data = DatDecryptor::decrypt(&full_path, xor_key, aes_key_option)
-        .map_err(|e| String::from(AppError::DecryptFailed(format!("{:?}", e))))?;
+    let keep_start = first.saturating_sub(ahead);
+    let keep_end = last.saturating_add(ahead);
 
-    // 转换为 base64
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&decrypted_data);
+    let to_fetch = ordered_ids
+        .iter()
+        .skip(last + 1)
+        .take(ahead)
+        .filter(|id| !is_cached(id))
+        .cloned()
+        .collect();
 
-    Ok(base64_data)
+    let to_evict = ordered_ids
+        .iter()
+        .enumerate()
+        .filter(|(index, id)| (*index < keep_start || *index > keep_end) && is_cached(id))
+        .map(|(_, id)| id.clone())
+        .collect();
+
+    (to_fetch, to_evict)
 }
 
-// 获取缓存中的图片数据
+// 预取规划结果
+#[derive(Serialize)]
+struct SmartPrefetchResult {
+    // 已开始后台解密的图片
+    prefetched: Vec<String>,
+    // 已从缓存中移除的图片
+    evicted: Vec<String>,
+}
+
+// 按滚动位置预取：后台解密可见窗口之后的 ahead 张图片，并淘汰远离窗口的缓存
+//
+// 排序与筛选参数须与前端列表一致，才能确定"之后"和"远离"的图片
 #[tauri::command]
-async fn get_image_data(
-    image_id: String,
+#[allow(clippy::too_many_arguments)]
+async fn smart_prefetch(
+    folder_path: String,
+    visible_ids: Vec<String>,
+    ahead: usize,
+    sort_by: String,
+    sort_order: String,
+    hide_thumbnails: bool,
     state: State<'_, AppState>,
-) -> Result<ImageDataResponse, String> {
-    {
-        let cache = state.image_cache.lock().unwrap();
-        if let Some(cached) = cache.get(&image_id) {
-            return Ok(ImageDataResponse {
-                data: cached.data.clone(),
-                mime_type: cached.mime_type.clone(),
-            });
-        }
-    }
+) -> Result<SmartPrefetchResult, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
 
-    let root_path = {
-        let guard = state.root_dir.lock().unwrap();
-        guard
-            .as_ref()
-            .ok_or(AppError::RootDirNotSet)
-            .map_err(|e| String::from(e))?
-            .clone()
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let mut images = collect_images(&folder, &root_path, hide_thumbnails, false);
+    images = deduplicate_images_by_hash(images);
+    sort_images(&mut images, &sort_by, &sort_order);
+    let ordered_ids: Vec<String> = images.into_iter().map(|image| image.path).collect();
+
+    let (to_fetch, to_evict) = {
+        let cache = state.image_cache.lock().unwrap();
+        plan_smart_prefetch(&ordered_ids, &visible_ids, ahead, |id| {
+            cache.contains_key(id)
+        })
     };
 
-    let full_path = root_path.join(&image_id);
+    let stale: Vec<PathBuf> = {
+        let mut cache = state.image_cache.lock().unwrap();
+        to_evict.iter().flat_map(|id| cache.remove(id)).collect()
+    };
+    if !stale.is_empty() {
+        tokio::task::spawn_blocking(move || cache::remove_files(stale));
+    }
 
-    if !full_path.exists() {
-        return Err(String::from(AppError::FileNotFound(image_id)));
+    for id in &to_fetch {
+        spawn_prefetch(&state, &root_path, id.clone());
     }
 
-    let xor_key = *state.xor_key.lock().unwrap();
-    let aes_key_vec = state.aes_key.lock().unwrap().clone();
-    let aes_key_option = if aes_key_vec.len() == 16 {
-        Some(aes_key_vec)
-    } else {
-        None
-    };
+    Ok(SmartPrefetchResult {
+        prefetched: to_fetch,
+        evicted: to_evict,
+    })
+}
 
-    let cache = state.image_cache.clone();
-    let semaphore = state.decrypt_semaphore.clone();
-    let image_id_clone = image_id.clone();
-    let xor_key_clone = xor_key;
-    let aes_key_clone = aes_key_option.clone();
+// 筛选修改时间在 [start_ts, end_ts] 内的图片
+fn filter_images_by_time(images: &mut Vec<ImageInfo>, start_ts: u64, end_ts: u64) {
+    images.retain(|img| (start_ts..=end_ts).contains(&img.modified));
+}
 
-    let permit = semaphore
-        .acquire_owned()
-        .await
-        .map_err(|err| format!("获取解密许可失败: {}", err))?;
+// 获取修改时间在指定范围内的图片（先按时间筛选，再去重、排序和分页）
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn images_in_range(
+    folder_path: String,
+    start_ts: u64,
+    end_ts: u64,
+    recursive: bool,
+    page: usize,
+    page_size: usize,
+    sort_by: String,
+    sort_order: String,
+    state: State<'_, AppState>,
+) -> Result<ImageBatch, String> {
+    let root_dir = state.root_dir.lock().unwrap().clone();
+    let root_path = root_dir
+        .as_ref()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?
+        .clone();
 
-    let decrypt_result = tokio::task::spawn_blocking(move || {
-        let aes_key_ref = aes_key_clone.as_deref();
-        DatDecryptor::decrypt(&full_path, xor_key_clone, aes_key_ref)
-            .map(|data| normalize_decrypted_image(data))
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let walk_root = root_path.clone();
+    let (images, truncated) = tokio::task::spawn_blocking(move || {
+        list_sorted_images(
+            &folder,
+            &walk_root,
+            false,
+            recursive,
+            Some((start_ts, end_ts)),
+            &sort_by,
+            &sort_order,
+        )
     })
     .await
-    .map_err(|err| format!("解密任务执行失败: {}", err))?;
+    .map_err(|err| format!("遍历文件夹任务执行失败: {}", err))?;
 
-    drop(permit);
+    let mut batch = build_image_batch(&state, &root_path, images, page, page_size)
+        .await
+        .map_err(|e| String::from(e))?;
+    batch.truncated = truncated;
+    Ok(batch)
+}
 
-    let (normalized_data, mime_type) =
-        decrypt_result.map_err(|err| format!("解密失败: {:?}", err))?;
-
-    let mut cache_map = cache.lock().unwrap();
-    cache_map.insert(
-        image_id_clone,
-        CachedImage {
-            data: normalized_data.clone(),
-            mime_type: mime_type.clone(),
-        },
-    );
+// DAT 版本在统计结果中使用的名称
+fn version_label(version: DatVersion) -> &'static str {
+    match version {
+        DatVersion::V3 => "v3",
+        DatVersion::V4V1 => "v4v1",
+        DatVersion::V4V2 => "v4v2",
+        DatVersion::Unknown => "unknown",
+    }
+}
 
-    Ok(ImageDataResponse {
-        data: normalized_data,
-        mime_type,
-    })
+// 只读取文件头判断 DAT 版本，无法读取时返回 unknown
+fn file_version_label(path: &Path) -> &'static str {
+    api::detect_version(path)
+        .map(version_label)
+        .unwrap_or_else(|_| version_label(DatVersion::Unknown))
+}
+
+// 按 DAT 版本汇总文件数量与大小，只读取文件头，不做解密
+fn compute_version_breakdown(
+    root_path: &Path,
+    images: &[ImageInfo],
+) -> HashMap<String, VersionStats> {
+    let mut breakdown: HashMap<String, VersionStats> = HashMap::new();
+
+    for image in images {
+        let version = DatDecryptor::detect_version(root_path.join(&image.path))
+            .unwrap_or(DatVersion::Unknown);
+        let stats = breakdown
+            .entry(version_label(version).to_string())
+            .or_default();
+        stats.count += 1;
+        stats.total_bytes += image.size;
+    }
+
+    for stats in breakdown.values_mut() {
+        stats.average_size = stats.total_bytes / stats.count as u64;
+    }
+
+    breakdown
 }
 
-// 清除图片缓存（可选，用于释放内存）
+// 统计文件夹中各 DAT 版本的数量、总大小和平均大小
 #[tauri::command]
-fn clear_image_cache(state: State<AppState>) -> Result<(), String> {
-    let mut cache = state.image_cache.lock().unwrap();
-    cache.clear();
-    Ok(())
+async fn version_breakdown(
+    folder_path: String,
+    recursive: bool,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, VersionStats>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    tokio::task::spawn_blocking(move || {
+        let images = collect_images(&folder, &root_path, false, recursive);
+        compute_version_breakdown(&root_path, &images)
+    })
+    .await
+    .map_err(|err| format!("统计任务执行失败: {}", err))
 }
 
-// 更新密钥
+// 根据 V4V1 / V4V2 文件数量判断文件夹需要的 AES 密钥类型
+//
+// 返回 "fixed"（主要为 V1，需要固定密钥）、"dynamic"（主要为 V2，需要动态密钥）、
+// "mixed"（两者都有）或 "none"（没有 v4 文件，无需 AES 密钥）
+fn classify_aes_requirement(v1_count: usize, v2_count: usize) -> &'static str {
+    let total = v1_count + v2_count;
+    if total == 0 {
+        "none"
+    } else if v1_count * 100 >= total * AES_REQUIREMENT_DOMINANT_PERCENT {
+        "fixed"
+    } else if v2_count * 100 >= total * AES_REQUIREMENT_DOMINANT_PERCENT {
+        "dynamic"
+    } else {
+        "mixed"
+    }
+}
+
+// 抽样文件夹中的 v4 文件，统计 V4V1 / V4V2 数量后判断密钥类型
+fn sample_aes_requirement(folder: &Path, root_path: &Path) -> &'static str {
+    let mut v1_count = 0;
+    let mut v2_count = 0;
+
+    for image in collect_images(folder, root_path, false, false) {
+        match DatDecryptor::detect_version(root_path.join(&image.path)) {
+            Ok(DatVersion::V4V1) => v1_count += 1,
+            Ok(DatVersion::V4V2) => v2_count += 1,
+            _ => continue,
+        }
+        if v1_count + v2_count >= AES_REQUIREMENT_SAMPLE_LIMIT {
+            break;
+        }
+    }
+
+    classify_aes_requirement(v1_count, v2_count)
+}
+
+// 检测文件夹需要的 AES 密钥类型，用于引导前端的密钥输入界面
+//
+// 目前没有内置的 V1 固定密钥表，"fixed" 时仍需用户提供密钥
 #[tauri::command]
-fn update_keys(xor: u8, aes: String, state: State<AppState>) -> Result<(), String> {
-    *state.xor_key.lock().unwrap() = xor;
+fn aes_key_requirement(folder_path: String, state: State<AppState>) -> Result<String, String> {
+    let root_dir = state.root_dir.lock().unwrap();
+    let root_path = root_dir
+        .as_ref()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    Ok(sample_aes_requirement(&folder, root_path).to_string())
+}
+
+// 按文档顺序提取 HTML 中 <img> 的 src 属性，跳过网络地址和内联数据
+fn extract_bundle_image_refs(html: &str) -> Vec<String> {
+    let document = scraper::Html::parse_document(html);
+    let selector = scraper::Selector::parse("img[src]").unwrap();
+
+    document
+        .select(&selector)
+        .filter_map(|element| element.value().attr("src"))
+        .map(|src| src.trim().to_string())
+        .filter(|src| {
+            let lower = src.to_lowercase();
+            !src.is_empty()
+                && !lower.starts_with("http://")
+                && !lower.starts_with("https://")
+                && !lower.starts_with("data:")
+        })
+        .collect()
+}
+
+// 找出导出包引用的图片文件：相对路径相对 HTML 所在目录解析，只保留根目录下存在的文件
+//
+// html_or_dir 为目录时，按文件名顺序处理其中的所有 .html / .htm 文件。
+// 引用按规范化后的路径判断是否位于根目录下，"../" 或符号链接指向根目录之外时忽略；
+// 返回的路径以 root_path 开头，可直接用 strip_prefix 得到相对路径
+fn resolve_bundle_images(html_or_dir: &Path, root_path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let canonical_root = root_path
+        .canonicalize()
+        .map_err(|e| AppError::InvalidPath(format!("{}: {}", root_path.display(), e)))?;
 
-    let aes_bytes = aes.as_bytes().to_vec();
-    let aes_key = if aes_bytes.len() >= 16 {
-        aes_bytes[..16].to_vec()
+    let html_files = if html_or_dir.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(html_or_dir)
+            .map_err(|e| AppError::FileReadError(e.to_string()))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"))
+                    .unwrap_or(false)
+            })
+            .collect();
+        files.sort();
+        files
     } else {
-        aes_bytes
+        vec![html_or_dir.to_path_buf()]
     };
-    *state.aes_key.lock().unwrap() = aes_key;
 
-    // 保存到配置文件
-    save_key_to_config(xor, &aes).map_err(|e| String::from(e))?;
+    let mut seen = std::collections::HashSet::new();
+    let mut images = Vec::new();
 
-    // 更新密钥后清理缓存，避免旧密钥解密的数据残留
-    state.image_cache.lock().unwrap().clear();
+    for html_file in html_files {
+        let html =
+            fs::read_to_string(&html_file).map_err(|e| AppError::FileReadError(e.to_string()))?;
+        let base_dir = html_file.parent().unwrap_or(Path::new(""));
 
-    Ok(())
+        for src in extract_bundle_image_refs(&html) {
+            let Ok(canonical) = base_dir.join(&src).canonicalize() else {
+                continue;
+            };
+            let Ok(relative) = canonical.strip_prefix(&canonical_root) else {
+                continue;
+            };
+            if canonical.is_file() && seen.insert(canonical.clone()) {
+                images.push(root_path.join(relative));
+            }
+        }
+    }
+
+    Ok(images)
 }
 
-// 获取当前密钥
+// 解密微信聊天记录导出包（HTML 文件或其所在目录）引用的图片，按文档顺序返回
 #[tauri::command]
-fn get_keys(state: State<AppState>) -> Result<(u8, String), String> {
-    let xor = *state.xor_key.lock().unwrap();
-    let aes = state.aes_key.lock().unwrap();
-    let aes_str = String::from_utf8_lossy(&aes).to_string();
-    Ok((xor, aes_str))
+async fn decrypt_export_bundle(
+    html_or_dir: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<ImageWithData>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let bundle = resolve_folder_in_root(&root_path, &html_or_dir).map_err(|e| String::from(e))?;
+
+    let paths = resolve_bundle_images(&bundle, &root_path).map_err(|e| String::from(e))?;
+
+    let mut images = Vec::with_capacity(paths.len());
+    for path in paths {
+        let info = match image_info_from_path(&path, &root_path) {
+            Some(info) => info,
+            None => continue,
+        };
+
+        let (mime_type, (width, height)) = match load_image(&info.path, &state).await {
+            Ok(image) => {
+                let dims = image_dimensions(&image.data).unwrap_or((0, 0));
+                (Some(image.mime_type), dims)
+            }
+            Err(err) => {
+                log::warn!("解密失败 {}: {}", info.path, err);
+                (None, (0, 0))
+            }
+        };
+
+        let version = file_version_label(&root_path.join(&info.path)).to_string();
+        images.push(ImageWithData {
+            image_id: info.path.clone(),
+            version,
+            path: info.path,
+            name: info.name,
+            size: info.size,
+            modified: info.modified,
+            is_thumbnail: info.is_thumbnail,
+            mime_type,
+            width,
+            height,
+        });
+    }
+
+    Ok(images)
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_dialog::init())
-        .manage(AppState::default())
-        .invoke_handler(tauri::generate_handler![
-            open_folder_dialog,
-            get_folder_tree,
-            get_images_in_folder,
-            get_images_batch,
-            decrypt_dat_file,
-            update_keys,
-            get_keys,
-            get_image_data,
-            clear_image_cache
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+// 根据魔数识别图片 MIME 类型，无法识别时返回 None
+fn sniff_image_mime(data: &[u8]) -> Option<&'static str> {
+    // JPEG: FF D8 FF
+    if data.len() >= 3 && data[0] == 0xFF && data[1] == 0xD8 && data[2] == 0xFF {
+        return Some("image/jpeg");
+    }
+
+    // PNG: 89 50 4E 47
+    if data.len() >= 4 && data[0] == 0x89 && data[1] == 0x50 && data[2] == 0x4E && data[3] == 0x47 {
+        return Some("image/png");
+    }
+
+    // GIF: 47 49 46
+    if data.len() >= 3 && data[0] == 0x47 && data[1] == 0x49 && data[2] == 0x46 {
+        return Some("image/gif");
+    }
+
+    // WebP: 52 49 46 46 ... 57 45 42 50
+    if data.len() >= 12
+        && data[0] == 0x52
+        && data[1] == 0x49
+        && data[2] == 0x46
+        && data[3] == 0x46
+        && data[8] == 0x57
+        && data[9] == 0x45
+        && data[10] == 0x42
+        && data[11] == 0x50
+    {
+        return Some("image/webp");
+    }
+
+    // BMP: 42 4D，偏移 6 处的 4 个保留字节为 0，只匹配 "BM" 容易误判
+    if data.len() >= 10 && data.starts_with(b"BM") && data[6..10] == [0, 0, 0, 0] {
+        return Some("image/bmp");
+    }
+
+    // TIFF: 49 49 2A 00 (小端) 或 4D 4D 00 2A (大端)
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some("image/tiff");
+    }
+
+    // HEIC: 偏移 4 处为 ftyp，主品牌为 heic 或 heix
+    if data.len() >= 12 && &data[4..8] == b"ftyp" && matches!(&data[8..12], b"heic" | b"heix") {
+        return Some("image/heic");
+    }
+
+    None
+}
+
+// 检测图片 MIME 类型
+fn detect_mime_type(data: &[u8]) -> String {
+    detect_mime_with_overrides(&MIME_OVERRIDES.read().unwrap(), data)
+}
+
+// 先按用户定义的文件头前缀匹配（最长前缀优先），都不匹配时使用内置规则
+fn detect_mime_with_overrides(overrides: &[(Vec<u8>, String)], data: &[u8]) -> String {
+    let custom = overrides
+        .iter()
+        .filter(|(magic, _)| !magic.is_empty() && data.starts_with(magic))
+        .max_by_key(|(magic, _)| magic.len());
+    if let Some((_, mime)) = custom {
+        return mime.clone();
+    }
+
+    if data.len() < 4 {
+        return "application/octet-stream".to_string();
+    }
+
+    sniff_image_mime(data)
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+// 解析十六进制字节串，允许用空格分隔，如 "02 23 21 53"
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// 把配置中的 MIME 覆盖规则解析为 (文件头前缀, MIME)
+fn parse_mime_overrides(
+    overrides: &[MimeOverrideConfig],
+) -> Result<Vec<(Vec<u8>, String)>, AppError> {
+    overrides
+        .iter()
+        .map(|rule| {
+            let magic = parse_hex_bytes(&rule.magic).ok_or_else(|| {
+                AppError::InvalidArgument(format!("无效的文件头前缀: {}", rule.magic))
+            })?;
+            if rule.mime.trim().is_empty() {
+                return Err(AppError::InvalidArgument("MIME 类型不能为空".to_string()));
+            }
+            Ok((magic, rule.mime.trim().to_string()))
+        })
+        .collect()
+}
+
+/// 对解密后的图片数据进行规范化处理
+///
+/// - 检测带有 WXGF 头的数据并通过当前平台的解码后端转换成标准图片
+///   （Windows 使用 DLL，其他平台只能处理内嵌的标准图片）
+/// - 返回转换后的数据及其 MIME 类型；不足 4 字节的数据直接按内容识别
+fn normalize_decrypted_image(data: Vec<u8>) -> (Vec<u8>, String) {
+    let is_wxgf = data.len() >= 4 && (&data[..4] == b"wxgf" || &data[..4] == b"WXGF");
+    if !is_wxgf {
+        let mime = detect_mime_type(&data);
+        return (data, mime);
+    }
+
+    let data = match convert_wxgf_on_pool(data, dll::backend()) {
+        Ok(converted) => {
+            let mime = detect_mime_type(&converted);
+            return (converted, mime);
+        }
+        Err((data, err)) => {
+            log::warn!("WXGF 图片所有格式均转换失败,返回原始数据: {}", err);
+            data
+        }
+    };
+
+    let mime = detect_mime_type(&data);
+    (data, mime)
+}
+
+/// 在 DLL 线程池中用指定后端转换 WXGF 数据，失败时把原始数据和失败原因交还给调用方
+///
+/// 转换交给专用线程池执行，限制同时进行的转换数量。
+fn convert_wxgf_on_pool(
+    data: Vec<u8>,
+    backend: &'static dyn dll::WxamBackend,
+) -> Result<Vec<u8>, (Vec<u8>, AppError)> {
+    let shared = Arc::new(data);
+    let job_data = Arc::clone(&shared);
+    let converted = crate::dll::pool()
+        .run(move || {
+            let preferred = wxgf_preferred_format(&job_data);
+            log::debug!("使用 {} 转换 WXGF 图片", backend.name());
+            convert_wxgf_with_fallback(&job_data, preferred, |data, format| {
+                backend.convert(data, format)
+            })
+        })
+        .unwrap_or_else(|err| {
+            log::error!("WXGF 转换任务失败: {}", err);
+            Err(err)
+        });
+
+    converted.map_err(|err| {
+        let data = Arc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+        (data, err)
+    })
+}
+
+/// 根据 WXGF 文件头选择输出格式：动图转为 GIF 保留动画，带 alpha 平面的静态图转为 PNG，
+/// 其余静态图转为 JPEG
+///
+/// 透明通道只看文件头，不额外解码；文件头无法解析时按不透明的静态图处理。
+/// PNG 由 DLL 的 GIF 输出转换而来，最多 256 色且只有 1 位透明度。
+fn wxgf_preferred_format(data: &[u8]) -> &'static str {
+    match dll::parse_wxgf_header(data) {
+        Some(info) if info.animated => WXGF_ANIMATED_FORMAT,
+        Some(info) if info.has_alpha => WXGF_TRANSPARENT_FORMAT,
+        _ => WXGF_PREFERRED_FORMAT,
+    }
+}
+
+/// 按回退链转换 WXGF 数据
+///
+/// 依次尝试首选格式、JPEG、GIF,返回第一个转换成功的结果；
+/// 全部失败时返回首选格式的错误，保留 DLL 的错误代码。
+fn convert_wxgf_with_fallback<F>(
+    data: &[u8],
+    preferred: &str,
+    convert: F,
+) -> Result<Vec<u8>, AppError>
+where
+    F: Fn(&[u8], &str) -> Result<Vec<u8>, AppError>,
+{
+    let mut formats = vec![preferred];
+    for fallback in ["jpeg", "gif"] {
+        if !formats.contains(&fallback) {
+            formats.push(fallback);
+        }
+    }
+
+    let mut first_err = None;
+    for format in formats {
+        match convert(data, format) {
+            Ok(converted) => {
+                log::debug!(
+                    "检测到 WXGF 图片,已通过 DLL 转换为 {},输出大小: {} 字节",
+                    format,
+                    converted.len()
+                );
+                return Ok(converted);
+            }
+            Err(err) => {
+                log::warn!("WXGF 图片转换为 {} 失败: {}", format, err);
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(first_err.unwrap_or_else(|| AppError::WxamDecodeFailed("没有可尝试的格式".to_string())))
+}
+
+// 去掉一个不区分大小写的 ASCII 后缀，不匹配时返回 None
+fn strip_suffix_ignore_ascii_case<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(suffix.len())?;
+    let (stem, tail) = (name.get(..split)?, name.get(split..)?);
+    tail.eq_ignore_ascii_case(suffix).then_some(stem)
+}
+
+// 提取文件名的hash部分：先去掉 .dat 扩展名，再去掉一个 _t / _h 版本后缀
+fn extract_hash_from_filename(filename: &str) -> String {
+    let stem = strip_suffix_ignore_ascii_case(filename, DAT_EXTENSION).unwrap_or(filename);
+    strip_suffix_ignore_ascii_case(stem, THUMBNAIL_SUFFIX)
+        .or_else(|| strip_suffix_ignore_ascii_case(stem, "_h"))
+        .unwrap_or(stem)
+        .to_string()
+}
+
+// 获取图片版本优先级（数字越小优先级越高）
+fn get_image_priority(filename: &str) -> u8 {
+    let lower = filename.to_lowercase();
+    if lower.ends_with("_t.dat") || lower.ends_with("_t") {
+        0 // 缩略图优先级最高
+    } else if lower.ends_with("_h.dat") || lower.ends_with("_h") {
+        2 // 原图优先级最低
+    } else {
+        1 // 中等规格优先级居中
+    }
+}
+
+// 去重：为同一hash的图片组选择最佳版本
+fn deduplicate_images_by_hash(images: Vec<ImageInfo>) -> Vec<ImageInfo> {
+    use std::collections::HashMap;
+
+    let mut hash_map: HashMap<String, ImageInfo> = HashMap::new();
+
+    for img in images {
+        let hash = extract_hash_from_filename(&img.name);
+
+        // 如果这个hash还没有记录，直接插入
+        let Some(existing) = hash_map.get(&hash) else {
+            hash_map.insert(hash, img);
+            continue;
+        };
+
+        // 如果当前图片优先级更高，就更新
+        let current_priority = get_image_priority(&img.name);
+        let existing_priority = get_image_priority(&existing.name);
+
+        if current_priority < existing_priority {
+            hash_map.insert(hash, img);
+        }
+    }
+
+    // 收集所有选中的图片
+    hash_map.into_values().collect()
+}
+
+// 同一 hash 的一个文件版本
+#[derive(Serialize, Debug)]
+struct DedupVariant {
+    path: String,
+    name: String,
+    // get_image_priority 给出的优先级，数字越小越优先
+    priority: u8,
+}
+
+// 同一 hash 的所有文件版本及去重时选中的版本
+#[derive(Serialize, Debug)]
+struct DedupGroup {
+    hash: String,
+    variants: Vec<DedupVariant>,
+    // 去重后保留的文件路径
+    chosen: String,
+}
+
+// 按 hash 分组列出所有文件版本，选中的版本与 deduplicate_images_by_hash 的结果一致
+fn build_dedup_groups(images: Vec<ImageInfo>) -> Vec<DedupGroup> {
+    let chosen: HashMap<String, String> = deduplicate_images_by_hash(images.clone())
+        .into_iter()
+        .map(|img| (extract_hash_from_filename(&img.name), img.path))
+        .collect();
+
+    let mut groups: HashMap<String, Vec<DedupVariant>> = HashMap::new();
+    for img in images {
+        groups
+            .entry(extract_hash_from_filename(&img.name))
+            .or_default()
+            .push(DedupVariant {
+                priority: get_image_priority(&img.name),
+                path: img.path,
+                name: img.name,
+            });
+    }
+
+    let mut groups: Vec<DedupGroup> = groups
+        .into_iter()
+        .map(|(hash, mut variants)| {
+            variants.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.name.cmp(&b.name)));
+            DedupGroup {
+                chosen: chosen.get(&hash).cloned().unwrap_or_default(),
+                hash,
+                variants,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.hash.cmp(&b.hash));
+    groups
+}
+
+// 预览文件夹去重时的分组情况：每个 hash 下的所有版本以及最终显示哪一个
+//
+// 只读取文件元数据，不解密
+#[tauri::command]
+fn preview_dedup(folder_path: String, state: State<AppState>) -> Result<Vec<DedupGroup>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    Ok(build_dedup_groups(collect_images(
+        &folder, &root_path, false, false,
+    )))
+}
+
+// 同一 hash 的一个分辨率版本
+#[derive(Serialize, Debug)]
+struct ImageVariant {
+    image_id: String,
+    name: String,
+    size: u64,
+    // "thumbnail"（_t）、"normal"（无后缀）或 "original"（_h）
+    variant: &'static str,
+}
+
+// 找出 hash 相同的所有版本，按缩略图、普通、原图的顺序排列
+fn find_image_variants(images: Vec<ImageInfo>, hash: &str) -> Vec<ImageVariant> {
+    let mut variants: Vec<(u8, ImageVariant)> = images
+        .into_iter()
+        .filter(|img| extract_hash_from_filename(&img.name) == hash)
+        .map(|img| {
+            let priority = get_image_priority(&img.name);
+            let variant = match priority {
+                0 => "thumbnail",
+                2 => "original",
+                _ => "normal",
+            };
+            (
+                priority,
+                ImageVariant {
+                    image_id: img.path,
+                    name: img.name,
+                    size: img.size,
+                    variant,
+                },
+            )
+        })
+        .collect();
+    variants.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.name.cmp(&b.1.name)));
+    variants.into_iter().map(|(_, variant)| variant).collect()
+}
+
+// 列出文件夹中某个 hash 的所有版本（_t、无后缀、_h），供界面按需加载原图
+//
+// 图库仍按去重结果只显示一个版本；只读取文件元数据，不解密
+#[tauri::command]
+fn get_image_variants(
+    hash: String,
+    folder_path: String,
+    state: State<AppState>,
+) -> Result<Vec<ImageVariant>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    Ok(find_image_variants(
+        collect_images(&folder, &root_path, false, false),
+        &hash,
+    ))
+}
+
+// 并发计算文件解密后内容的 SHA-256，解密失败的文件为 None
+fn hash_files_concurrently(jobs: &[HashJob], workers: usize) -> Vec<Option<String>> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<String>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(i) else { break };
+                match DatDecryptor::decrypt_and_hash(
+                    &job.path,
+                    job.xor_key,
+                    job.aes_key.as_deref(),
+                    "sha256",
+                ) {
+                    Ok((_, hash)) => *results[i].lock().unwrap() = Some(hash),
+                    Err(err) => log::warn!("计算哈希失败 {}: {:?}", job.path.display(), err),
+                }
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|slot| slot.into_inner().unwrap())
+        .collect()
+}
+
+// 按内容哈希分组，每组保留最大的一个文件，其余文件的大小之和即为可释放的空间
+fn compute_reclaimable_space(jobs: &[HashJob], workers: usize) -> u64 {
+    let mut groups: HashMap<String, Vec<u64>> = HashMap::new();
+    for (job, hash) in jobs.iter().zip(hash_files_concurrently(jobs, workers)) {
+        if let Some(hash) = hash {
+            groups.entry(hash).or_default().push(job.size);
+        }
+    }
+
+    groups
+        .values()
+        .map(|sizes| sizes.iter().sum::<u64>() - sizes.iter().max().copied().unwrap_or(0))
+        .sum()
+}
+
+// 计算文件夹内内容完全相同的文件占用的多余空间（字节）
+//
+// 与 preview_dedup 按文件名分组不同，这里解密后按内容哈希判断重复
+#[tauri::command]
+async fn reclaimable_space(folder_path: String, state: State<'_, AppState>) -> Result<u64, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let jobs: Vec<HashJob> = collect_images(&folder, &root_path, false, false)
+        .into_iter()
+        .map(|image| {
+            let path = root_path.join(&image.path);
+            let (xor_key, aes_key) = resolve_keys(&state, &path);
+            HashJob {
+                path,
+                size: image.size,
+                xor_key,
+                aes_key,
+            }
+        })
+        .collect();
+
+    tokio::task::spawn_blocking(move || compute_reclaimable_space(&jobs, MAX_CONCURRENT_DECRYPT))
+        .await
+        .map_err(|err| format!("哈希任务执行失败: {}", err))
+}
+
+// 判断解密后原始数据（WXGF 转换之前）的媒体类型
+fn media_kind(data: &[u8]) -> &'static str {
+    // HEIC 与 MP4 同样以 ftyp 开头，需先按图片识别
+    if data.len() >= 4 && (&data[..4] == b"wxgf" || &data[..4] == b"WXGF") {
+        "wxgf"
+    } else if sniff_image_mime(data).is_some() {
+        "image"
+    } else if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        "video"
+    } else {
+        "unknown"
+    }
+}
+
+// 使用给定密钥解密任意路径的文件并标准化，不依赖 AppState
+fn decrypt_explicit_file(path: &Path, xor: u8, aes: &str) -> Result<DecryptedFile, AppError> {
+    let aes_key = api::parse_aes_key(aes);
+
+    let (raw, version) = DatDecryptor::decrypt_with_version(path, xor, aes_key.as_deref())?;
+    let kind = media_kind(&raw);
+
+    let (data, mime_type) = normalize_decrypted_image(raw);
+    let dims = image_dimensions(&data);
+
+    Ok(DecryptedFile {
+        data: base64::engine::general_purpose::STANDARD.encode(&data),
+        mime_type,
+        width: dims.map(|(width, _)| width),
+        height: dims.map(|(_, height)| height),
+        version: version_label(version).to_string(),
+        kind: kind.to_string(),
+    })
+}
+
+// 使用显式提供的密钥解密任意路径的文件，返回标准化后的数据、MIME、尺寸、版本和类型
+//
+// 不读取也不修改全局状态，可供外部工具直接调用。解密、WXGF 转换和 base64 编码在阻塞线程中进行
+#[tauri::command]
+async fn decrypt_explicit(abs_path: String, xor: u8, aes: String) -> Result<DecryptedFile, String> {
+    tokio::task::spawn_blocking(move || {
+        let path = Path::new(&abs_path);
+        if !path.is_file() {
+            return Err(AppError::FileNotFound(abs_path));
+        }
+        decrypt_explicit_file(path, xor, &aes)
+    })
+    .await
+    .map_err(|err| format!("解密任务执行失败: {}", err))?
+    .map_err(|e| String::from(e))
+}
+
+// 检查是否是有效的 Sns 文件名
+fn is_valid_sns_filename(filename: &str) -> bool {
+    let name = filename.strip_suffix(THUMBNAIL_SUFFIX).unwrap_or(filename);
+    SNS_FILENAME_LENGTHS.contains(&name.len()) && name.chars().all(|c| c.is_alphanumeric())
+}
+
+// 应用识别的文件名规则
+#[derive(Serialize)]
+struct RecognizedPatterns {
+    // 识别的扩展名（不区分大小写）
+    extensions: Vec<String>,
+    // 无扩展名的 Sns 文件名允许的长度
+    sns_filename_lengths: Vec<usize>,
+    // Sns 文件名允许的字符
+    sns_filename_charset: String,
+    // 缩略图后缀，可出现在扩展名之前或无扩展名文件名的末尾
+    thumbnail_suffixes: Vec<String>,
+}
+
+fn build_recognized_patterns() -> RecognizedPatterns {
+    RecognizedPatterns {
+        extensions: vec![DAT_EXTENSION.to_string()],
+        sns_filename_lengths: SNS_FILENAME_LENGTHS.to_vec(),
+        sns_filename_charset: "alphanumeric".to_string(),
+        thumbnail_suffixes: vec![THUMBNAIL_SUFFIX.to_string()],
+    }
+}
+
+// 列出扫描文件夹时识别的文件名规则，供帮助面板说明哪些文件会被跳过
+#[tauri::command]
+fn recognized_patterns() -> RecognizedPatterns {
+    build_recognized_patterns()
+}
+
+// 解密 DAT 文件，返回 base64 编码的原始明文
+#[tauri::command]
+fn decrypt_dat_file(file_path: String, state: State<AppState>) -> Result<String, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &file_path).map_err(|e| String::from(e))?;
+
+    let (xor_key, aes_key_option) = resolve_keys(&state, &full_path);
+
+    let decrypted_data = api::decrypt_raw(&full_path, xor_key, aes_key_option.as_deref())
+        .map_err(|e| String::from(e))?;
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(&decrypted_data))
+}
+
+// 根据标准化后的数据和 MIME 选择保存时使用的扩展名
+fn extension_for_mime(data: &[u8], mime: &str) -> &'static str {
+    match media_kind(data) {
+        "video" => "mp4",
+        "wxgf" => "wxgf",
+        "unknown" => "bin",
+        _ => match mime {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/bmp" => "bmp",
+            "image/tiff" => "tif",
+            "image/heic" => "heic",
+            _ => "jpg",
+        },
+    }
+}
+
+// 解密 DAT 文件、转换 WXGF 后写入磁盘，避免大文件的 base64 开销
+//
+// 扩展名按检测到的格式修正，返回实际写入的路径；非 WXGF 文件边解密边写入。
+// 先写入临时文件再重命名，解密失败时不影响目标位置已有的文件
+#[tauri::command]
+async fn decrypt_dat_to_file(
+    file_path: String,
+    output_path: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &file_path).map_err(|e| String::from(e))?;
+
+    let (xor_key, aes_key) = resolve_keys(&state, &full_path);
+
+    tokio::task::spawn_blocking(move || {
+        api::decrypt_to_file(
+            &full_path,
+            xor_key,
+            aes_key.as_deref(),
+            Path::new(&output_path),
+        )
+    })
+    .await
+    .map_err(|err| format!("解密任务执行失败: {}", err))?
+    .map(|path| path.to_string_lossy().to_string())
+    .map_err(|e| String::from(e))
+}
+
+// 判断数据是否以已知的图片/视频魔数开头（WXGF 可由 DLL 转换，同样视为有效）
+fn has_media_magic(data: &[u8]) -> bool {
+    if data.len() >= 4 && (&data[..4] == b"wxgf" || &data[..4] == b"WXGF") {
+        return true;
+    }
+
+    // MP4/MOV: 偏移 4 处为 ftyp
+    if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        return true;
+    }
+
+    sniff_image_mime(data).is_some()
+}
+
+// WXGF 转换所需的 DLL 是否可用（非 Windows 平台始终不可用）
+fn wxgf_dll_available() -> bool {
+    #[cfg(windows)]
+    {
+        crate::dll::WxAMDecoder::is_available()
+    }
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
+// 设置 WXGF 转换时传给 DLL 的附加标志（高级选项，0 为默认行为）
+//
+// 仅在 Windows 上生效，设置只保存在本次运行中
+#[tauri::command]
+fn set_wxgf_flags(flags: i32, state: State<AppState>) -> Result<(), String> {
+    #[cfg(windows)]
+    crate::dll::WxAMDecoder::set_extra_flags(flags);
+    #[cfg(not(windows))]
+    let _ = flags;
+
+    // 已缓存的 WXGF 转换结果是用旧标志生成的
+    state.image_cache.lock().unwrap().clear();
+
+    Ok(())
+}
+
+// 校验自定义 DLL 目录，空字符串表示清除设置
+fn parse_dll_dir(dir: &str) -> Result<Option<PathBuf>, AppError> {
+    let dir = dir.trim();
+    if dir.is_empty() {
+        return Ok(None);
+    }
+
+    let path = PathBuf::from(dir);
+    if !path.is_dir() {
+        return Err(AppError::InvalidPath(dir.to_string()));
+    }
+    Ok(Some(path))
+}
+
+// 设置 VoipEngine.dll 的查找目录并保存到配置文件，传空字符串恢复为只查找程序目录
+//
+// DLL 只加载一次，已尝试加载后需重启程序才会从新目录加载
+#[tauri::command]
+fn set_dll_dir(dir: String) -> Result<(), String> {
+    let parsed = parse_dll_dir(&dir).map_err(|e| String::from(e))?;
+
+    #[cfg(windows)]
+    crate::dll::WxAMDecoder::set_dll_dir(parsed.clone());
+
+    let dll_dir = parsed
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    update_config(|config| config.dll_dir = dll_dir).map_err(|e| String::from(e))
+}
+
+// 只解密文件开头，判断完整解密是否大概率成功
+fn check_decryptable(
+    path: &Path,
+    xor_key: XorKey,
+    aes_key: Option<&[u8]>,
+    dll_available: bool,
+) -> bool {
+    let head = match DatDecryptor::decrypt_head(path, xor_key, aes_key) {
+        Ok(head) => head,
+        Err(_) => return false,
+    };
+
+    // WXGF 需要 DLL 转换
+    if head.len() >= 4 && (&head[..4] == b"wxgf" || &head[..4] == b"WXGF") {
+        return dll_available;
+    }
+
+    has_media_magic(&head)
+}
+
+// 把前端传入的相对路径拼接到根目录下，并确认它没有越出根目录
+//
+// 含 ".." 或绝对路径时返回 InvalidPath；文件存在时再比较规范化后的路径，
+// 防止通过符号链接越界。返回未规范化的拼接路径，以便与按原路径记录的文件夹密钥匹配
+fn resolve_in_root(root_path: &Path, relative: &str) -> Result<PathBuf, AppError> {
+    use std::path::Component;
+
+    let relative_path = Path::new(relative);
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(AppError::InvalidPath(relative.to_string()));
+    }
+
+    let full_path = root_path.join(relative_path);
+    let canonical = full_path
+        .canonicalize()
+        .map_err(|_| AppError::FileNotFound(relative.to_string()))?;
+    let canonical_root = root_path
+        .canonicalize()
+        .map_err(|e| AppError::InvalidPath(format!("{}: {}", root_path.display(), e)))?;
+    if !canonical.starts_with(&canonical_root) {
+        return Err(AppError::InvalidPath(relative.to_string()));
+    }
+
+    Ok(full_path)
+}
+
+// 校验前端传入的文件夹（或导出包等文件）绝对路径位于根目录下
+//
+// 含 ".." 时返回 InvalidPath；再比较规范化后的路径，防止通过符号链接越界。
+// 返回未规范化的路径，遍历时仍可用 strip_prefix(root_path) 得到相对路径
+fn resolve_folder_in_root(root_path: &Path, folder_path: &str) -> Result<PathBuf, AppError> {
+    use std::path::Component;
+
+    let folder = Path::new(folder_path);
+    if !folder.starts_with(root_path)
+        || folder
+            .components()
+            .any(|component| matches!(component, Component::ParentDir))
+    {
+        return Err(AppError::InvalidPath(folder_path.to_string()));
+    }
+
+    let canonical = folder
+        .canonicalize()
+        .map_err(|_| AppError::FileNotFound(folder_path.to_string()))?;
+    let canonical_root = root_path
+        .canonicalize()
+        .map_err(|e| AppError::InvalidPath(format!("{}: {}", root_path.display(), e)))?;
+    if !canonical.starts_with(&canonical_root) {
+        return Err(AppError::InvalidPath(folder_path.to_string()));
+    }
+
+    Ok(folder.to_path_buf())
+}
+
+// 判断文件在当前状态下能否被解密，供前端提前禁用无法查看的条目
+//
+// 不修改任何状态：根目录未设置、文件不存在、缺少密钥、密钥错误或缺少 DLL 时均返回 false
+#[tauri::command]
+fn can_decrypt(file_path: String, state: State<AppState>) -> Result<bool, String> {
+    let root_path = match state.root_dir.lock().unwrap().clone() {
+        Some(root_path) => root_path,
+        None => return Ok(false),
+    };
+
+    let full_path = match resolve_in_root(&root_path, &file_path) {
+        Ok(full_path) if full_path.is_file() => full_path,
+        _ => return Ok(false),
+    };
+
+    let (xor_key, aes_key) = resolve_keys(&state, &full_path);
+
+    Ok(check_decryptable(
+        &full_path,
+        xor_key,
+        aes_key.as_deref(),
+        wxgf_dll_available(),
+    ))
+}
+
+// 根据 v3 文件开头的数据穷举 XOR 密钥（1..=255），解密后出现已知媒体魔数即视为命中
+fn guess_xor_key(data: &[u8]) -> Option<u8> {
+    let header = &data[..data.len().min(12)];
+    (1..=u8::MAX).find(|&key| has_media_magic(&V3Decryptor::xor_decrypt(header, key)))
+}
+
+// 依次尝试所有已知解密策略，返回第一个输出为有效媒体数据的结果
+//
+// key_table 为按顺序尝试的已知密钥，只用于 v4 文件
+fn try_force_decrypt(
+    path: &Path,
+    xor_key: u8,
+    aes_key: Option<&[u8]>,
+    key_table: &[KeyCandidate],
+) -> Option<ForcedData> {
+    let forced = |data, strategy, xor_key, key_source| ForcedData {
+        data,
+        strategy,
+        xor_key,
+        key_source,
+    };
+
+    // 1. 当前配置的密钥
+    if let Ok(data) = DatDecryptor::decrypt_exact(path, xor_key, aes_key) {
+        if has_media_magic(&data) {
+            return Some(forced(
+                data,
+                ForceStrategy::ConfiguredKeys,
+                Some(xor_key),
+                None,
+            ));
+        }
+    }
+
+    let version = DatDecryptor::detect_version(path).ok();
+
+    // 2. v3 文件穷举 XOR 密钥（密钥 0 等价于明文，留给最后一步）
+    if version == Some(DatVersion::V3) {
+        let raw = fs::read(path).ok()?;
+        if let Some(key) = guess_xor_key(&raw) {
+            let data = V3Decryptor::xor_decrypt(&raw, key);
+            return Some(forced(data, ForceStrategy::XorBruteforce, Some(key), None));
+        }
+    }
+
+    // 3. v4 文件逐个尝试已知密钥，跳过已经试过的 AES 密钥
+    if matches!(version, Some(DatVersion::V4V1 | DatVersion::V4V2)) {
+        let mut tried: Vec<&[u8]> = aes_key.into_iter().collect();
+        for candidate in key_table {
+            if tried.contains(&candidate.aes.as_slice()) {
+                continue;
+            }
+            tried.push(&candidate.aes);
+
+            if let Ok(data) = DatDecryptor::decrypt_exact(path, candidate.xor, Some(&candidate.aes))
+            {
+                if has_media_magic(&data) {
+                    return Some(forced(
+                        data,
+                        ForceStrategy::KeyTable,
+                        Some(candidate.xor),
+                        Some(candidate.source.clone()),
+                    ));
+                }
+            }
+        }
+    }
+
+    // 4. 视为未加密的原始数据
+    let raw = fs::read(path).ok()?;
+    if has_media_magic(&raw) {
+        return Some(forced(raw, ForceStrategy::Plaintext, None, None));
+    }
+
+    None
+}
+
+// 应用状态中已知的密钥：全局密钥以及各文件夹的覆盖密钥，AES 密钥长度无效的不返回
+fn known_key_candidates(state: &AppState) -> Vec<KeyCandidate> {
+    let mut candidates = vec![KeyCandidate {
+        source: "global".to_string(),
+        xor: *state.xor_key.lock().unwrap(),
+        aes: state.aes_key.lock().unwrap().clone(),
+    }];
+    candidates.extend(
+        state
+            .folder_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(folder, (xor, aes))| KeyCandidate {
+                source: folder.display().to_string(),
+                xor: *xor,
+                aes: aes.clone(),
+            }),
+    );
+    candidates.retain(|candidate| AesHandler::is_valid_key_len(candidate.aes.len()));
+    candidates
+}
+
+// 配置中记录的凭据存储目标里保存的 AES 密钥，没有配置或读取失败时返回 None
+fn credential_store_key(store: &dyn SecretStore) -> Option<Vec<u8>> {
+    let target = read_config().ok().flatten()?.credential_target?;
+    let aes = aes_key_from_str(&store.load(&target).ok().flatten()?);
+    AesHandler::is_valid_key_len(aes.len()).then_some(aes)
+}
+
+// 强制解密：依次尝试所有已知策略，用于常规解密失败时的兜底
+//
+// 读取、穷举和 WXGF 转换都在阻塞线程中进行，不持有根目录的锁
+#[tauri::command]
+async fn force_decrypt(
+    file_path: String,
+    state: State<'_, AppState>,
+) -> Result<ForceResult, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &file_path).map_err(|e| String::from(e))?;
+
+    let (xor_key, aes_key_option) = resolve_keys(&state, &full_path);
+    let mut key_table = known_key_candidates(&state);
+    let global_xor = *state.xor_key.lock().unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        if let Some(aes) = credential_store_key(platform_secret_store().as_ref()) {
+            key_table.push(KeyCandidate {
+                source: "credential".to_string(),
+                xor: global_xor,
+                aes,
+            });
+        }
+
+        let forced = try_force_decrypt(
+            &full_path,
+            xor_key.key,
+            aes_key_option.as_deref(),
+            &key_table,
+        )
+        .ok_or_else(|| AppError::DecryptFailed("所有解密策略均未得到有效图片".to_string()))?;
+
+        log::info!("强制解密成功 {}: 策略 {:?}", file_path, forced.strategy);
+
+        let (normalized_data, mime_type) = normalize_decrypted_image(forced.data);
+
+        Ok(ForceResult {
+            data: base64::engine::general_purpose::STANDARD.encode(&normalized_data),
+            mime_type,
+            strategy: forced.strategy,
+            xor_key: forced.xor_key,
+            key_source: forced.key_source,
+        })
+    })
+    .await
+    .map_err(|err| format!("强制解密任务执行失败: {}", err))?
+    .map_err(|e: AppError| String::from(e))
+}
+
+// 从缓存获取图片，未命中时解密并写入缓存
+async fn load_image(image_id: &str, state: &AppState) -> Result<CachedImage, String> {
+    if let Some(cached) = state.cache_get(image_id).await {
+        return Ok(cached);
+    }
+
+    let root_path = {
+        let guard = state.root_dir.lock().unwrap();
+        guard
+            .as_ref()
+            .ok_or(AppError::RootDirNotSet)
+            .map_err(|e| String::from(e))?
+            .clone()
+    };
+
+    let full_path = resolve_in_root(&root_path, image_id).map_err(|e| String::from(e))?;
+
+    let (xor_key_clone, aes_key_clone) = resolve_keys(state, &full_path);
+
+    let semaphore = state.decrypt_semaphore.clone();
+
+    let permit = semaphore
+        .acquire_owned()
+        .await
+        .map_err(|err| format!("获取解密许可失败: {}", err))?;
+
+    let decrypt_result = tokio::task::spawn_blocking(move || {
+        api::decrypt_image(&full_path, xor_key_clone, aes_key_clone.as_deref())
+    })
+    .await
+    .map_err(|err| format!("解密任务执行失败: {}", err))?;
+
+    drop(permit);
+
+    let (normalized_data, mime_type) =
+        decrypt_result.map_err(|err| format!("解密失败: {}", err))?;
+
+    let image = CachedImage {
+        data: normalized_data,
+        mime_type,
+    };
+
+    state
+        .cache_insert(image_id.to_string(), image.clone())
+        .await;
+
+    Ok(image)
+}
+
+// 获取缓存中的图片数据
+//
+// 数据需经 IPC 序列化复制，大图较慢；前端优先使用 wxdat 协议（见 IMAGE_SCHEME），
+// 此命令作为协议不可用时的回退
+#[tauri::command]
+async fn get_image_data(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<ImageDataResponse, String> {
+    let image = load_image(&image_id, &state).await?;
+
+    Ok(ImageDataResponse {
+        data: image.data,
+        mime_type: image.mime_type,
+    })
+}
+
+// 解码 URL 中的百分号编码，结果不是有效 UTF-8 或编码不完整时返回 None
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+// 从图片协议请求的 URI 路径中取出 image_id
+fn image_id_from_uri_path(path: &str) -> Option<String> {
+    percent_decode(path.trim_start_matches('/')).filter(|id| !id.is_empty())
+}
+
+// 构造图片协议的响应：成功时带上 MIME 和缓存时间，失败时返回 404
+fn image_protocol_response(result: Result<CachedImage, String>) -> tauri::http::Response<Vec<u8>> {
+    let builder = tauri::http::Response::builder();
+    let response = match result {
+        Ok(image) => builder
+            .status(200)
+            .header("Content-Type", image.mime_type)
+            .header("Cache-Control", format!("max-age={}", IMAGE_MAX_AGE_SECS))
+            .body(image.data),
+        Err(err) => builder
+            .status(404)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(err.into_bytes()),
+    };
+    response.unwrap_or_else(|err| {
+        log::error!("构造图片协议响应失败: {}", err);
+        tauri::http::Response::new(Vec::new())
+    })
+}
+
+// 根据图片内容计算强 ETag
+fn content_etag(data: &[u8]) -> String {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    data.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+// 根据源文件的大小和修改时间生成弱 ETag，格式为 W/"{大小}-{修改时间}"
+fn file_weak_etag(path: &Path) -> Result<String, AppError> {
+    let metadata = fs::metadata(path).map_err(|e| AppError::FileReadError(e.to_string()))?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    Ok(format!("W/\"{}-{}\"", metadata.len(), mtime))
+}
+
+// 构造条件请求的响应：ETag 与 If-None-Match 一致时返回空的 NotModified
+fn conditional_image_response(
+    image: CachedImage,
+    etag: String,
+    if_none_match: Option<&str>,
+) -> ImageResponse {
+    if if_none_match == Some(etag.as_str()) {
+        return ImageResponse::NotModified { etag };
+    }
+
+    ImageResponse::Ok {
+        data: image.data,
+        mime_type: image.mime_type,
+        etag,
+        max_age: IMAGE_MAX_AGE_SECS,
+    }
+}
+
+// 获取带 ETag 的图片数据，便于前端使用 fetch/Cache API 跨页面缓存
+//
+// 弱 ETag 模式下先比较源文件的弱 ETag，一致时不解密直接返回 NotModified
+#[tauri::command]
+async fn get_image_with_etag(
+    image_id: String,
+    if_none_match: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<ImageResponse, String> {
+    let mode = *state.etag_mode.lock().unwrap();
+
+    if mode == EtagMode::Weak {
+        let root_path = state
+            .root_dir
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(AppError::RootDirNotSet)
+            .map_err(|e| String::from(e))?;
+        let etag = resolve_in_root(&root_path, &image_id)
+            .and_then(|path| file_weak_etag(&path))
+            .map_err(|e| String::from(e))?;
+        if if_none_match.as_deref() == Some(etag.as_str()) {
+            return Ok(ImageResponse::NotModified { etag });
+        }
+
+        let image = load_image(&image_id, &state).await?;
+        return Ok(conditional_image_response(image, etag, None));
+    }
+
+    let image = load_image(&image_id, &state).await?;
+    let etag = content_etag(&image.data);
+    Ok(conditional_image_response(
+        image,
+        etag,
+        if_none_match.as_deref(),
+    ))
+}
+
+// 设置自定义 MIME 识别规则并保存到配置文件，传入空列表即恢复内置规则
+#[tauri::command]
+fn set_mime_overrides(
+    overrides: Vec<MimeOverrideConfig>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let parsed = parse_mime_overrides(&overrides).map_err(|e| String::from(e))?;
+    *MIME_OVERRIDES.write().unwrap() = parsed;
+
+    // 缓存中的 MIME 类型是按旧规则识别的
+    state.image_cache.lock().unwrap().clear();
+
+    update_config(|config| config.mime_overrides = overrides).map_err(|e| String::from(e))
+}
+
+// 设置 get_image_with_etag 使用的 ETag 类型（"strong" 或 "weak"）并保存到配置文件
+#[tauri::command]
+fn set_etag_mode(mode: String, state: State<AppState>) -> Result<(), String> {
+    let parsed = EtagMode::parse(&mode)
+        .ok_or_else(|| AppError::InvalidArgument(format!("不支持的 ETag 类型: {}", mode)))
+        .map_err(|e| String::from(e))?;
+
+    *state.etag_mode.lock().unwrap() = parsed;
+
+    update_config(|config| config.etag_mode = mode.to_lowercase()).map_err(|e| String::from(e))
+}
+
+// 清除图片缓存（可选，用于释放内存），磁盘后端同时删除临时文件
+#[tauri::command]
+fn clear_image_cache(state: State<AppState>) -> Result<(), String> {
+    let mut cache = state.image_cache.lock().unwrap();
+    cache.clear();
+    Ok(())
+}
+
+// reset_state 清除的内容
+#[derive(Serialize, Debug)]
+struct ResetSummary {
+    // 清除前的根目录，未设置时为 None
+    root_dir: Option<String>,
+    // 清除的图片缓存数量
+    cached_images: usize,
+    // 清除的解密会话数量
+    decrypt_sessions: usize,
+    // 密钥是否已恢复为配置文件中的值
+    keys_reset: bool,
+}
+
+// 重置会话状态：清除根目录、图片缓存和解密会话，用于同一次运行中切换微信账号
+//
+// reset_keys 为 true 时同时把全局密钥和按文件夹覆盖的密钥恢复为配置文件中的值
+#[tauri::command]
+fn reset_state(reset_keys: Option<bool>, state: State<AppState>) -> Result<ResetSummary, String> {
+    reset_app_state(&state, reset_keys.unwrap_or(false)).map_err(|e| String::from(e))
+}
+
+fn reset_app_state(state: &AppState, reset_keys: bool) -> Result<ResetSummary, AppError> {
+    // 先读取配置，读取失败时不改动任何状态
+    let keys = if reset_keys {
+        Some((read_key_from_config()?, read_folder_keys_from_config()?))
+    } else {
+        None
+    };
+
+    let root_dir = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .take()
+        .map(|root| root.to_string_lossy().to_string());
+
+    let cached_images = {
+        let mut cache = state.image_cache.lock().unwrap();
+        let count = cache.len();
+        cache.clear();
+        count
+    };
+
+    let decrypt_sessions = {
+        let mut sessions = state.decrypt_sessions.lock().unwrap();
+        let count = sessions.len();
+        sessions.clear();
+        count
+    };
+
+    let keys_reset = keys.is_some();
+    if let Some(((xor, aes), folder_keys)) = keys {
+        *state.xor_key.lock().unwrap() = xor;
+        *state.aes_key.lock().unwrap() = aes;
+        *state.folder_keys.lock().unwrap() = folder_keys;
+    }
+
+    Ok(ResetSummary {
+        root_dir,
+        cached_images,
+        decrypt_sessions,
+        keys_reset,
+    })
+}
+
+// 切换图片缓存后端（"memory" 或 "disk"）并保存到配置文件
+//
+// 磁盘后端把解密后的图片写入临时文件，内存中只保留路径，用内存换取磁盘读写
+#[tauri::command]
+fn set_cache_backend(backend: String, state: State<AppState>) -> Result<(), String> {
+    let parsed = CacheBackend::parse(&backend)
+        .ok_or_else(|| AppError::InvalidArgument(format!("不支持的缓存后端: {}", backend)))
+        .map_err(|e| String::from(e))?;
+
+    state.image_cache.lock().unwrap().set_backend(parsed);
+
+    update_config(|config| config.cache_backend = backend.to_lowercase())
+        .map_err(|e| String::from(e))
+}
+
+// 更新密钥
+//
+// aes_encoding 为 "hex" / "text"，不传时自动识别十六进制密钥
+#[tauri::command]
+fn update_keys(
+    xor: u8,
+    aes: String,
+    aes_encoding: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    store_keys(&state, xor, &aes, aes_encoding.as_deref().unwrap_or(""))
+        .map_err(|e| String::from(e))
+}
+
+// 更新全局密钥，仅会话模式下不写入配置文件
+fn store_keys(state: &AppState, xor: u8, aes: &str, encoding: &str) -> Result<(), AppError> {
+    let aes_key = parse_aes_key(aes, encoding)?;
+    *state.xor_key.lock().unwrap() = xor;
+    *state.aes_key.lock().unwrap() = aes_key;
+
+    // 保存到配置文件
+    if !state.session_only_keys.load(Ordering::Relaxed) {
+        save_key_to_config(xor, aes, encoding)?;
+    }
+
+    // 更新密钥后清理缓存，避免旧密钥解密的数据残留
+    state.image_cache.lock().unwrap().clear();
+
+    Ok(())
+}
+
+// 把当前的 AES 密钥保存到密钥存储
+//
+// 存储中保存 aes_key_to_str 的结果，读取时用 aes_key_from_str 还原为相同字节
+fn save_aes_to_store(
+    store: &dyn SecretStore,
+    target: &str,
+    state: &AppState,
+) -> Result<(), AppError> {
+    let aes = aes_key_to_str(&state.aes_key.lock().unwrap());
+    if aes.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "当前没有设置 AES 密钥".to_string(),
+        ));
+    }
+    store.save(target, &aes)
+}
+
+// 从密钥存储读取 AES 密钥并设为当前密钥
+fn load_aes_from_store(
+    store: &dyn SecretStore,
+    target: &str,
+    state: &AppState,
+) -> Result<(), AppError> {
+    let aes = store
+        .load(target)?
+        .ok_or_else(|| AppError::InvalidArgument(format!("密钥存储中没有 {} 的密钥", target)))?;
+
+    *state.aes_key.lock().unwrap() = aes_key_from_str(&aes);
+    state.image_cache.lock().unwrap().clear();
+    Ok(())
+}
+
+// 把当前的 AES 密钥保存到系统凭据存储（Windows 凭据管理器）
+//
+// 保存后配置文件只记录目标名称，不再保存明文 AES 密钥；其他平台回退到配置文件
+#[tauri::command]
+fn save_keys_to_credential_store(target: String, state: State<AppState>) -> Result<(), String> {
+    save_aes_to_store(platform_secret_store().as_ref(), &target, &state)
+        .map_err(|e| String::from(e))?;
+
+    #[cfg(windows)]
+    if !state.session_only_keys.load(Ordering::Relaxed) {
+        update_config(|config| {
+            config.aes.clear();
+            config.credential_target = Some(target);
+        })
+        .map_err(|e| String::from(e))?;
+    }
+
+    Ok(())
+}
+
+// 从系统凭据存储读取 AES 密钥并设为当前密钥
+#[tauri::command]
+fn load_keys_from_credential_store(target: String, state: State<AppState>) -> Result<(), String> {
+    load_aes_from_store(platform_secret_store().as_ref(), &target, &state)
+        .map_err(|e| String::from(e))
+}
+
+// 设置仅在本次运行中有效的密钥，并进入仅会话模式
+//
+// 之后的 update_keys / set_folder_keys 也不再写入配置文件，适用于共享电脑
+#[tauri::command]
+fn set_session_keys(xor: u8, aes: String, state: State<AppState>) -> Result<(), String> {
+    state.session_only_keys.store(true, Ordering::Relaxed);
+    store_keys(&state, xor, &aes, "").map_err(|e| String::from(e))
+}
+
+// 开启或关闭 v3 文件的 XOR 密钥推算：配置的密钥解不出已知文件头时按文件头推算密钥，
+// 未加密的文件推算出密钥 0。用于加密文件和明文图片混放、或密钥未配置的文件夹
+#[tauri::command]
+fn set_plaintext_fallback(enabled: bool, state: State<AppState>) -> Result<(), String> {
+    state.plaintext_fallback.store(enabled, Ordering::Relaxed);
+    // 切换后同一文件使用的密钥可能不同
+    state.image_cache.lock().unwrap().clear();
+    Ok(())
+}
+
+// 为指定文件夹设置覆盖密钥，该文件夹及其子目录中的文件优先使用这组密钥
+//
+// aes_encoding 与 update_keys 相同，为 "hex" / "text"，不传时自动识别十六进制密钥
+#[tauri::command]
+fn set_folder_keys(
+    folder_path: String,
+    xor: u8,
+    aes: String,
+    aes_encoding: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let aes_key =
+        parse_aes_key(&aes, aes_encoding.as_deref().unwrap_or("")).map_err(|e| String::from(e))?;
+
+    {
+        let root_dir = state.root_dir.lock().unwrap();
+        let root_path = root_dir
+            .as_ref()
+            .ok_or(AppError::RootDirNotSet)
+            .map_err(|e| String::from(e))?;
+
+        resolve_folder_in_root(root_path, &folder_path).map_err(|e| String::from(e))?;
+    }
+
+    store_folder_keys(&state, PathBuf::from(&folder_path), xor, aes_key)
+        .map_err(|e| String::from(e))
+}
+
+// 记录文件夹覆盖密钥，仅会话模式下不写入配置文件
+fn store_folder_keys(
+    state: &AppState,
+    folder: PathBuf,
+    xor: u8,
+    aes: Vec<u8>,
+) -> Result<(), AppError> {
+    let mut folder_keys = state.folder_keys.lock().unwrap();
+    folder_keys.insert(folder, (xor, aes));
+
+    // 保存到配置文件
+    if !state.session_only_keys.load(Ordering::Relaxed) {
+        save_folder_keys_to_config(&folder_keys)?;
+    }
+
+    // 该文件夹下已缓存的图片可能是用旧密钥解密的
+    state.image_cache.lock().unwrap().clear();
+
+    Ok(())
+}
+
+// 解密会话使用的临时文件，内存中只保留路径，避免与图片缓存各保存一份完整数据
+struct DecryptSessions {
+    // 存放会话文件的临时目录
+    dir: PathBuf,
+    // 会话 ID 到解密结果文件的映射
+    files: HashMap<String, PathBuf>,
+}
+
+impl Default for DecryptSessions {
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir().join(format!("wxdatviewer-sessions-{}", std::process::id())),
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl DecryptSessions {
+    // 在会话目录中分配一个新的文件路径（不含扩展名），目录不存在时创建
+    fn new_file_path(&self) -> Result<PathBuf, AppError> {
+        fs::create_dir_all(&self.dir).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+        Ok(self.dir.join(format!(
+            "session-{}",
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        )))
+    }
+
+    fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    // 删除会话及其文件，会话不存在时返回 false
+    fn remove(&mut self, session_id: &str) -> bool {
+        match self.files.remove(session_id) {
+            Some(path) => {
+                let _ = fs::remove_file(path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        for (_, path) in self.files.drain() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for DecryptSessions {
+    fn drop(&mut self) {
+        self.clear();
+        // 目录中还有其他文件时保留目录
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+// 登记一个解密会话并返回会话 ID，path 为保存解密结果的文件
+fn insert_decrypt_session(state: &AppState, path: PathBuf) -> String {
+    let id = state.next_session_id.fetch_add(1, Ordering::Relaxed);
+    let session_id = format!("session-{}", id);
+    state
+        .decrypt_sessions
+        .lock()
+        .unwrap()
+        .files
+        .insert(session_id.clone(), path);
+    session_id
+}
+
+// 从解密会话中读取 [offset, offset + len) 区间的数据，超出末尾的部分被截断
+fn read_session_chunk(
+    state: &AppState,
+    session_id: &str,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, AppError> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let path = state
+        .decrypt_sessions
+        .lock()
+        .unwrap()
+        .files
+        .get(session_id)
+        .cloned()
+        .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+
+    let read_error =
+        |e: std::io::Error| AppError::FileReadError(format!("{}: {}", path.display(), e));
+    let mut file = fs::File::open(&path).map_err(read_error)?;
+    let file_len = file.metadata().map_err(read_error)?.len();
+    file.seek(SeekFrom::Start(offset.min(file_len)))
+        .map_err(read_error)?;
+
+    let mut chunk = Vec::new();
+    file.take(len as u64)
+        .read_to_end(&mut chunk)
+        .map_err(read_error)?;
+    Ok(chunk)
+}
+
+// 把图片（image_id 为相对根目录的路径）解密到会话目录中的新文件，返回文件路径
+//
+// 不经过图片缓存，内存中不保留解密结果
+async fn decrypt_to_session_file(state: &AppState, image_id: &str) -> Result<PathBuf, AppError> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)?;
+    let full_path = resolve_in_root(&root_path, image_id)?;
+    let (xor_key, aes_key) = resolve_keys(state, &full_path);
+    let output = state.decrypt_sessions.lock().unwrap().new_file_path()?;
+
+    let permit = state
+        .decrypt_semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|err| AppError::Internal(format!("获取解密许可失败: {}", err)))?;
+
+    let result = tokio::task::spawn_blocking(move || {
+        api::decrypt_to_file(&full_path, xor_key, aes_key.as_deref(), &output)
+    })
+    .await
+    .map_err(|err| AppError::Internal(format!("解密任务执行失败: {}", err)))?;
+
+    drop(permit);
+    result
+}
+
+// 打开解密会话：把文件（file_path 为相对根目录的路径）解密到临时文件，返回会话 ID
+#[tauri::command]
+async fn open_decrypt_session(
+    file_path: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let path = decrypt_to_session_file(&state, &file_path)
+        .await
+        .map_err(|e| String::from(e))?;
+    Ok(insert_decrypt_session(&state, path))
+}
+
+// 按块读取解密会话中的数据，读到末尾后返回空数组
+#[tauri::command]
+fn read_decrypted_chunk(
+    session_id: String,
+    offset: u64,
+    len: usize,
+    state: State<AppState>,
+) -> Result<Vec<u8>, String> {
+    read_session_chunk(&state, &session_id, offset, len).map_err(|e| String::from(e))
+}
+
+// 关闭解密会话并删除临时文件
+#[tauri::command]
+fn close_decrypt_session(session_id: String, state: State<AppState>) -> Result<(), String> {
+    if state.decrypt_sessions.lock().unwrap().remove(&session_id) {
+        Ok(())
+    } else {
+        Err(String::from(AppError::SessionNotFound(session_id)))
+    }
+}
+
+// 把写入的数据切分为带序号的 base64 数据块事件依次交给 emit，最后一块可能不足 chunk_size
+struct ChunkEventWriter<F> {
+    file_path: String,
+    chunk_size: usize,
+    // 尚未凑满一块的数据
+    buffer: Vec<u8>,
+    // 下一块的序号，也是已发送的块数
+    seq: usize,
+    total_len: usize,
+    emit: F,
+}
+
+impl<F> ChunkEventWriter<F>
+where
+    F: FnMut(DecryptChunkEvent) -> Result<(), AppError>,
+{
+    // chunk_size 为 0 时使用默认块大小
+    fn new(file_path: &str, chunk_size: usize, emit: F) -> Self {
+        let chunk_size = if chunk_size == 0 {
+            STREAM_DEFAULT_CHUNK_SIZE
+        } else {
+            chunk_size
+        };
+
+        Self {
+            file_path: file_path.to_string(),
+            chunk_size,
+            buffer: Vec::new(),
+            seq: 0,
+            total_len: 0,
+            emit,
+        }
+    }
+
+    fn emit_chunk(&mut self, len: usize) -> Result<(), AppError> {
+        let chunk: Vec<u8> = self.buffer.drain(..len).collect();
+        (self.emit)(DecryptChunkEvent {
+            file_path: self.file_path.clone(),
+            seq: self.seq,
+            data: base64::engine::general_purpose::STANDARD.encode(chunk),
+        })?;
+        self.seq += 1;
+        Ok(())
+    }
+
+    // 发送剩余数据，返回结束事件
+    fn finish(mut self, mime_type: String) -> Result<DecryptEndEvent, AppError> {
+        if !self.buffer.is_empty() {
+            self.emit_chunk(self.buffer.len())?;
+        }
+
+        Ok(DecryptEndEvent {
+            file_path: self.file_path,
+            total_len: self.total_len,
+            chunk_count: self.seq,
+            mime_type,
+        })
+    }
+}
+
+impl<F> std::io::Write for ChunkEventWriter<F>
+where
+    F: FnMut(DecryptChunkEvent) -> Result<(), AppError>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.total_len += buf.len();
+        while self.buffer.len() >= self.chunk_size {
+            self.emit_chunk(self.chunk_size)
+                .map_err(|e| std::io::Error::other(String::from(e)))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// 解密图片并把数据按块交给 emit，返回结束事件
+//
+// 未缓存且不需要整体转换的文件边解密边发送，不在内存中保留完整明文，也不写入图片缓存；
+// 已缓存或需要转换的图片 (WXGF) 从完整数据切分
+async fn stream_decrypt_events<F>(
+    state: &AppState,
+    file_path: &str,
+    chunk_size: usize,
+    emit: F,
+) -> Result<DecryptEndEvent, String>
+where
+    F: FnMut(DecryptChunkEvent) -> Result<(), AppError> + Clone + Send + 'static,
+{
+    let cached = state.image_cache.lock().unwrap().contains_key(file_path);
+    if !cached {
+        let root_path = state
+            .root_dir
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(AppError::RootDirNotSet)
+            .map_err(|e| String::from(e))?;
+        let full_path = resolve_in_root(&root_path, file_path).map_err(|e| String::from(e))?;
+        let (xor_key, aes_key) = resolve_keys(state, &full_path);
+
+        let permit = state
+            .decrypt_semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|err| format!("获取解密许可失败: {}", err))?;
+
+        let id = file_path.to_string();
+        let emit = emit.clone();
+        let streamed = tokio::task::spawn_blocking(move || {
+            let Some(source) = api::stream_source(&full_path, xor_key, aes_key.as_deref())? else {
+                return Ok(None);
+            };
+            let mut writer = ChunkEventWriter::new(&id, chunk_size, emit);
+            api::stream_to_writer(&full_path, &source, aes_key.as_deref(), &mut writer)?;
+            writer.finish(source.mime_type()).map(Some)
+        })
+        .await
+        .map_err(|err| format!("解密任务执行失败: {}", err))?;
+
+        drop(permit);
+        if let Some(end) = streamed.map_err(|e: AppError| String::from(e))? {
+            return Ok(end);
+        }
+    }
+
+    let image = load_image(file_path, state).await?;
+    let mut writer = ChunkEventWriter::new(file_path, chunk_size, emit);
+    std::io::Write::write_all(&mut writer, &image.data)
+        .map_err(|e| String::from(AppError::Internal(e.to_string())))?;
+    writer.finish(image.mime_type).map_err(|e| String::from(e))
+}
+
+// 解密时以 decrypt-chunk 事件分块推送给前端，最后发送 decrypt-end 事件
+//
+// 避免单次返回数 MB 的数据阻塞 IPC 通道；每块解密出来后立即发送
+#[tauri::command]
+async fn stream_decrypt(
+    file_path: String,
+    chunk_size: usize,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    use tauri::Emitter;
+
+    let chunk_app = app.clone();
+    let emit = move |chunk: DecryptChunkEvent| {
+        chunk_app
+            .emit("decrypt-chunk", chunk)
+            .map_err(|e| AppError::Internal(e.to_string()))
+    };
+    let end = stream_decrypt_events(&state, &file_path, chunk_size, emit).await?;
+
+    app.emit("decrypt-end", end)
+        .map_err(|e| String::from(AppError::Internal(e.to_string())))
+}
+
+// 优化图片在图片缓存中使用的键，与原图缓存共享清理时机
+fn optimized_cache_key(image_id: &str, max_dim: u32, quality: u8) -> String {
+    format!("{}#optimized-{}-{}", image_id, max_dim, quality)
+}
+
+// 解码图片，按比例缩小到最长边不超过 max_dim（0 表示不缩放，较小的图片不放大）
+//
+// 优化图、缩略图和静态相册共用，保证各处的缩放结果一致
+fn decode_fitted(data: &[u8], max_dim: u32) -> Result<image::DynamicImage, AppError> {
+    let img = image::load_from_memory(data)
+        .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?;
+    Ok(fit_within(img, max_dim))
+}
+
+// 按比例缩小到最长边不超过 max_dim，0 表示不缩放
+fn fit_within(img: image::DynamicImage, max_dim: u32) -> image::DynamicImage {
+    if max_dim > 0 && (img.width() > max_dim || img.height() > max_dim) {
+        img.resize(max_dim, max_dim, image::imageops::FilterType::Triangle)
+    } else {
+        img
+    }
+}
+
+// 以指定质量把图片编码为有损 WebP
+fn encode_webp(img: &image::DynamicImage, quality: u8) -> Vec<u8> {
+    let rgba = img.to_rgba8();
+    webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+        .encode(quality.min(100) as f32)
+        .to_vec()
+}
+
+// 解码图片，按最长边缩放到 max_dim 以内（0 表示不缩放），再以指定质量编码为有损 WebP
+fn encode_optimized_webp(data: &[u8], max_dim: u32, quality: u8) -> Result<Vec<u8>, AppError> {
+    Ok(encode_webp(&decode_fitted(data, max_dim)?, quality))
+}
+
+// 获取缩小后的 WebP 版本，供带宽受限的远程前端使用
+#[tauri::command]
+async fn get_optimized(
+    image_id: String,
+    max_dim: u32,
+    quality: u8,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    let cache_key = optimized_cache_key(&image_id, max_dim, quality);
+    if let Some(cached) = state.cache_get(&cache_key).await {
+        return Ok(cached.data);
+    }
+
+    let image = load_image(&image_id, &state).await?;
+
+    let optimized =
+        tokio::task::spawn_blocking(move || encode_optimized_webp(&image.data, max_dim, quality))
+            .await
+            .map_err(|err| format!("图片优化任务执行失败: {}", err))?
+            .map_err(|e| String::from(e))?;
+
+    state
+        .cache_insert(
+            cache_key,
+            CachedImage {
+                data: optimized.clone(),
+                mime_type: "image/webp".to_string(),
+            },
+        )
+        .await;
+
+    Ok(optimized)
+}
+
+// 缩略图在图片缓存中使用的键
+fn thumbnail_cache_key(image_id: &str, max_edge: u32) -> String {
+    format!("{}#thumb{}", image_id, max_edge)
+}
+
+// 解码图片，按比例缩放到最长边不超过 max_edge，再编码为 JPEG 缩略图
+fn encode_thumbnail_jpeg(data: &[u8], max_edge: u32) -> Result<Vec<u8>, AppError> {
+    let img = decode_fitted(data, max_edge)?;
+
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, THUMBNAIL_JPEG_QUALITY)
+        .encode_image(&img.to_rgb8())
+        .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?;
+    Ok(out)
+}
+
+// 获取最长边不超过 max_edge 的 JPEG 缩略图，供网格显示，避免前端加载整张原图
+#[tauri::command]
+async fn get_thumbnail(
+    image_id: String,
+    max_edge: u32,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    let cache_key = thumbnail_cache_key(&image_id, max_edge);
+    if let Some(cached) = state.cache_get(&cache_key).await {
+        return Ok(cached.data);
+    }
+
+    let image = load_image(&image_id, &state).await?;
+
+    let thumbnail =
+        tokio::task::spawn_blocking(move || encode_thumbnail_jpeg(&image.data, max_edge))
+            .await
+            .map_err(|err| format!("缩略图生成任务执行失败: {}", err))?
+            .map_err(|e| String::from(e))?;
+
+    state
+        .cache_insert(
+            cache_key,
+            CachedImage {
+                data: thumbnail.clone(),
+                mime_type: "image/jpeg".to_string(),
+            },
+        )
+        .await;
+
+    Ok(thumbnail)
+}
+
+// 把一张图片写入相册目录：images/ 下为缩小后的大图，thumbs/ 下为缩略图，均为 WebP
+fn write_gallery_item(
+    data: &[u8],
+    source: &str,
+    stem: &str,
+    dest_dir: &Path,
+    limiter: Option<&RateLimiter>,
+) -> Result<GalleryItem, AppError> {
+    // 只解码一次，缩略图由缩小后的大图继续缩小
+    let full_img = decode_fitted(data, GALLERY_FULL_MAX_DIM)?;
+    let (width, height) = (full_img.width(), full_img.height());
+    let full = encode_webp(&full_img, GALLERY_FULL_QUALITY);
+    let thumbnail = encode_webp(
+        &fit_within(full_img, GALLERY_THUMB_MAX_DIM),
+        GALLERY_THUMB_QUALITY,
+    );
+
+    let full_rel = format!("images/{}.webp", stem);
+    let thumbnail_rel = format!("thumbs/{}.webp", stem);
+    for (rel, bytes) in [(&full_rel, &full), (&thumbnail_rel, &thumbnail)] {
+        let path = dest_dir.join(rel);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+        }
+        throttle::write_file(&path, bytes, limiter)
+            .map_err(|e| AppError::FileWriteError(e.to_string()))?;
+    }
+
+    Ok(GalleryItem {
+        source: source.to_string(),
+        width,
+        height,
+        full: full_rel,
+        thumbnail: thumbnail_rel,
+    })
+}
+
+// 写入相册清单 gallery.json
+fn write_gallery_manifest(manifest: &GalleryManifest, dest_dir: &Path) -> Result<(), AppError> {
+    let content = serde_json::to_string_pretty(manifest)
+        .map_err(|e| AppError::ConfigSerializeError(e.to_string()))?;
+    fs::write(dest_dir.join(GALLERY_MANIFEST), content)
+        .map_err(|e| AppError::FileWriteError(e.to_string()))
+}
+
+// 导出时同时编码写入的图片数，未指定时使用 CPU 核心数
+fn export_concurrency_or_default(concurrency: Option<usize>) -> usize {
+    concurrency.filter(|n| *n > 0).unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    })
+}
+
+// 导出文件夹的选项
+#[derive(Clone, Debug)]
+struct ExportOptions {
+    hide_thumbnails: bool,
+    // 最多同时解密写入的图片数
+    concurrency: usize,
+    // 所有写入的总速度上限，None 表示不限速
+    max_write_bytes_per_sec: Option<u64>,
+    // 全部图片处理完后在 output_dir 写入 manifest.json
+    write_manifest: bool,
+    // 导出文件名模板，见 render_export_name
+    filename_template: String,
+    // 为 false 时不覆盖 output_dir 中已有的文件，文件名和大小都相同的视为已导出并跳过，
+    // 用于继续中断的导出
+    overwrite: bool,
+}
+
+// 生成导出文件名时可用的字段
+struct ExportNameFields<'a> {
+    hash: &'a str,
+    // 原始文件的修改时间，用于 {date}
+    modified: u64,
+    // 按修改时间排序后的序号，从 1 开始
+    index: usize,
+    ext: &'a str,
+    // 导出的文件夹名称
+    folder: &'a str,
+}
+
+// 按模板生成导出文件名
+//
+// 支持 {hash}、{date}（YYYY-MM-DD，UTC）、{index}（至少 4 位，不足补 0）、{ext} 和 {folder}，
+// 其他内容原样保留。结果中不能用于文件名的字符替换为 _，生成的文件名为空时退回默认模板
+fn render_export_name(template: &str, fields: &ExportNameFields) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let token = &rest[start..];
+        let Some(end) = token.find('}') else {
+            rest = token;
+            break;
+        };
+        match &token[1..end] {
+            "hash" => rendered.push_str(fields.hash),
+            "date" => {
+                let (year, month, day) = unix_to_date(fields.modified);
+                rendered.push_str(&format!("{:04}-{:02}-{:02}", year, month, day));
+            }
+            "index" => rendered.push_str(&format!("{:04}", fields.index)),
+            "ext" => rendered.push_str(fields.ext),
+            "folder" => rendered.push_str(fields.folder),
+            _ => rendered.push_str(&token[..=end]),
+        }
+        rest = &token[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let name = sanitize_file_name(&rendered);
+    if name.is_empty() && template != DEFAULT_EXPORT_TEMPLATE {
+        return render_export_name(DEFAULT_EXPORT_TEMPLATE, fields);
+    }
+    name
+}
+
+// 把不能用于文件名的字符替换为 _，并去掉首尾空白和结尾的点（Windows 不允许，也避免生成 ..）
+fn sanitize_file_name(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"<>:"/\|?*"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    replaced.trim().trim_end_matches('.').trim_end().to_string()
+}
+
+// 占用导出文件名；已被本次导出使用时在扩展名前追加 _1、_2 ...，不区分大小写比较
+fn claim_export_name(name: String, taken: &mut HashSet<String>) -> String {
+    if taken.insert(name.to_lowercase()) {
+        return name;
+    }
+
+    let path = Path::new(&name);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string());
+    (1..)
+        .map(|n| match &ext {
+            Some(ext) => format!("{}_{}.{}", stem, n, ext),
+            None => format!("{}_{}", stem, n),
+        })
+        .find(|candidate| taken.insert(candidate.to_lowercase()))
+        .unwrap()
+}
+
+// 导出文件夹的进度事件（export-progress），每导出完成或失败一张图片发送一次
+#[derive(Serialize, Clone)]
+struct ExportProgressEvent {
+    processed: usize,
+    total: usize,
+    path: String,
+    // 导出失败时的原因
+    error: Option<String>,
+}
+
+// 导出失败的图片
+#[derive(Serialize, Debug, PartialEq)]
+struct ExportFailure {
+    // 相对根目录的路径
+    path: String,
+    reason: String,
+}
+
+// 批量导出文件夹的结果
+#[derive(Serialize, Debug, Default)]
+struct ExportSummary {
+    succeeded: usize,
+    failed: usize,
+    // 同一 hash 的其他版本，只导出优先级最高的一个
+    skipped_duplicates: usize,
+    errors: Vec<ExportFailure>,
+    // 写入的总字节数
+    bytes_written: u64,
+    // 不覆盖导出时，output_dir 中已有且文件名和大小都相同而跳过的图片
+    skipped_existing: usize,
+    // 是否被 cancel_export 提前结束，未开始的图片不计入成功或失败
+    cancelled: bool,
+    // 写入的清单文件路径，未要求写清单时为 None
+    manifest_path: Option<String>,
+}
+
+impl ExportSummary {
+    // 已处理的图片数，包括成功、失败和跳过已有文件的
+    fn processed(&self) -> usize {
+        self.succeeded + self.failed + self.skipped_existing
+    }
+}
+
+// 导出清单 manifest.json 中的一项，导出失败的图片也会列出
+#[derive(Serialize, Deserialize, Debug)]
+struct ExportManifestEntry {
+    // 相对根目录的原始路径
+    source: String,
+    // 原始文件名中的 hash
+    hash: String,
+    // 导出后的文件名，导出失败时为 None
+    output: Option<String>,
+    // DAT 版本，导出失败时为 None
+    version: Option<String>,
+    mime_type: Option<String>,
+    // 导出文件的大小（字节）
+    size: u64,
+    // 原始文件的修改时间
+    modified: u64,
+    width: Option<u32>,
+    height: Option<u32>,
+    error: Option<String>,
+}
+
+// 导出成功的单个文件
+struct ExportedFile {
+    output: PathBuf,
+    bytes: u64,
+    // output_dir 中已有相同的文件，本次没有写入
+    skipped: bool,
+    // 写清单时才读取 DAT 版本和导出文件的开头，否则为 None 和空
+    version: Option<&'static str>,
+    head: Vec<u8>,
+}
+
+// 读取导出文件的大小；describe 为 true 时同时读取清单需要的版本和文件开头
+fn exported_file(source: &Path, output: PathBuf, describe: bool) -> Result<ExportedFile, AppError> {
+    use std::io::Read;
+
+    let read_error =
+        |e: std::io::Error| AppError::FileReadError(format!("{}: {}", output.display(), e));
+    let bytes = fs::metadata(&output).map_err(read_error)?.len();
+    let mut head = Vec::new();
+    if describe {
+        fs::File::open(&output)
+            .and_then(|file| file.take(GRID_HEAD_BYTES as u64).read_to_end(&mut head))
+            .map_err(read_error)?;
+    }
+
+    Ok(ExportedFile {
+        version: describe.then(|| file_version_label(source)),
+        output,
+        bytes,
+        skipped: false,
+        head,
+    })
+}
+
+// 单张图片的导出任务
+struct ExportJob {
+    source: PathBuf,
+    xor_key: XorKey,
+    aes_key: Option<Vec<u8>>,
+    hash: String,
+    modified: u64,
+    index: usize,
+    folder_name: String,
+    output_dir: PathBuf,
+    options: Arc<ExportOptions>,
+    // 本次导出已使用的文件名（小写），各任务共享
+    taken_names: Arc<Mutex<HashSet<String>>>,
+    limiter: Option<Arc<RateLimiter>>,
+    // 上次导出清单中这张图片的条目
+    prior: Option<ExportManifestEntry>,
+}
+
+// 解密一张图片并按模板命名写入 output_dir
+//
+// 不覆盖时：上次清单记录的文件仍在且大小一致则直接跳过；生成的文件名已存在时先写到临时文件，
+// 大小相同视为已导出并删除临时文件，否则改用带计数的新文件名，不会覆盖已有文件
+fn export_image(job: &ExportJob) -> Result<ExportedFile, AppError> {
+    let describe = job.options.write_manifest;
+    let taken = |name: String| claim_export_name(name, &mut job.taken_names.lock().unwrap());
+
+    if let Some(prior) = &job.prior {
+        if let Some(output) = &prior.output {
+            let path = job.output_dir.join(output);
+            if fs::metadata(&path).is_ok_and(|metadata| metadata.len() == prior.size) {
+                taken(output.clone());
+                let mut file = exported_file(&job.source, path, describe)?;
+                file.skipped = true;
+                return Ok(file);
+            }
+        }
+    }
+
+    // 扩展名在解密出文件开头后才能确定，此时再生成文件名
+    let mut existing = None;
+    let output_for = |ext: &str| {
+        let fields = ExportNameFields {
+            hash: &job.hash,
+            modified: job.modified,
+            index: job.index,
+            ext,
+            folder: &job.folder_name,
+        };
+        let output = job.output_dir.join(taken(render_export_name(
+            &job.options.filename_template,
+            &fields,
+        )));
+        if job.options.overwrite || !output.exists() {
+            return output;
+        }
+        let temp = output.with_file_name(format!(
+            ".{}.resume",
+            output.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        existing = Some(output);
+        temp
+    };
+    let written = api::decrypt_to_file_limited(
+        &job.source,
+        job.xor_key,
+        job.aes_key.as_deref(),
+        output_for,
+        job.limiter.as_deref(),
+    )?;
+
+    let Some(existing) = existing else {
+        return exported_file(&job.source, written, describe);
+    };
+    let write_error = |e: std::io::Error| AppError::FileWriteError(e.to_string());
+    let same_size = fs::metadata(&written).map_err(write_error)?.len()
+        == fs::metadata(&existing).map_err(write_error)?.len();
+    if same_size {
+        fs::remove_file(&written).map_err(write_error)?;
+        let mut file = exported_file(&job.source, existing, describe)?;
+        file.skipped = true;
+        return Ok(file);
+    }
+
+    let name = existing
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let output = std::iter::repeat_with(|| job.output_dir.join(taken(name.clone())))
+        .find(|output| !output.exists())
+        .unwrap();
+    fs::rename(&written, &output).map_err(write_error)?;
+    exported_file(&job.source, output, describe)
+}
+
+// 读取 output_dir 中上次导出的清单，返回导出成功的条目，键为原始路径；清单不存在或损坏时为空
+//
+// 只接受单纯的文件名，避免清单中的路径指向 output_dir 之外
+fn read_export_manifest(output_dir: &Path) -> HashMap<String, ExportManifestEntry> {
+    fs::read_to_string(output_dir.join(EXPORT_MANIFEST))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Vec<ExportManifestEntry>>(&content).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.error.is_none())
+        .filter(|entry| {
+            entry
+                .output
+                .as_deref()
+                .is_some_and(|output| Path::new(output).file_name() == Some(output.as_ref()))
+        })
+        .map(|entry| (entry.source.clone(), entry))
+        .collect()
+}
+
+type ExportJoinResult =
+    Result<(tokio::task::Id, Result<ExportedFile, AppError>), tokio::task::JoinError>;
+
+// 记录一张图片的导出结果并发送进度事件；任务 panic 时只记为这张图片失败
+//
+// 提供 manifest 时同时记录清单条目
+fn record_export_result(
+    summary: &mut ExportSummary,
+    pending: &mut HashMap<tokio::task::Id, ImageInfo>,
+    total: usize,
+    joined: ExportJoinResult,
+    manifest: Option<&mut Vec<ExportManifestEntry>>,
+    progress: &mut (dyn FnMut(ExportProgressEvent) + Send),
+) {
+    let (id, result) = match joined {
+        Ok((id, result)) => (id, result.map_err(|e| String::from(e))),
+        Err(err) => (err.id(), Err(format!("导出任务执行失败: {}", err))),
+    };
+    let Some(info) = pending.remove(&id) else {
+        return;
+    };
+
+    let mut entry = ExportManifestEntry {
+        source: info.path.clone(),
+        hash: extract_hash_from_filename(&info.name),
+        output: None,
+        version: None,
+        mime_type: None,
+        size: 0,
+        modified: info.modified,
+        width: None,
+        height: None,
+        error: None,
+    };
+
+    match result {
+        Ok(file) => {
+            if file.skipped {
+                summary.skipped_existing += 1;
+            } else {
+                summary.succeeded += 1;
+                summary.bytes_written += file.bytes;
+            }
+            let dims = image_dimensions(&file.head);
+            entry.output = file
+                .output
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string());
+            entry.version = file.version.map(str::to_string);
+            entry.mime_type = head_mime_type(&file.head);
+            entry.size = file.bytes;
+            entry.width = dims.map(|(width, _)| width);
+            entry.height = dims.map(|(_, height)| height);
+        }
+        Err(reason) => {
+            log::warn!("导出失败 {}: {}", info.path, reason);
+            summary.failed += 1;
+            summary.errors.push(ExportFailure {
+                path: info.path.clone(),
+                reason: reason.clone(),
+            });
+            entry.error = Some(reason);
+        }
+    }
+
+    progress(ExportProgressEvent {
+        processed: summary.processed(),
+        total,
+        path: info.path,
+        error: entry.error.clone(),
+    });
+    if let Some(manifest) = manifest {
+        manifest.push(entry);
+    }
+}
+
+// 按原始路径排序后写入导出清单
+fn write_export_manifest(
+    mut entries: Vec<ExportManifestEntry>,
+    output_dir: &Path,
+) -> Result<PathBuf, AppError> {
+    entries.sort_by(|a, b| a.source.cmp(&b.source));
+    let content = serde_json::to_string_pretty(&entries)
+        .map_err(|e| AppError::ConfigSerializeError(e.to_string()))?;
+    let path = output_dir.join(EXPORT_MANIFEST);
+    fs::write(&path, content).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+    Ok(path)
+}
+
+// 解密文件夹中的所有图片，按 options.filename_template 命名写入 output_dir
+//
+// folder_path 为前端传入的绝对路径，必须位于根目录下。单张图片失败不影响其他图片，
+// cancel 被置位时不再开始新的图片，等待已开始的图片写完后返回。
+// 要求写清单时在所有图片处理完后写入 manifest.json，只列出已处理的图片（包括失败和跳过的）
+async fn export_folder_files(
+    state: &AppState,
+    root_path: &Path,
+    folder_path: &str,
+    output_dir: &Path,
+    options: &ExportOptions,
+    cancel: &AtomicBool,
+    progress: &mut (dyn FnMut(ExportProgressEvent) + Send),
+) -> Result<ExportSummary, AppError> {
+    let folder = resolve_folder_in_root(root_path, folder_path)?;
+    let images = collect_images(&folder, root_path, options.hide_thumbnails, false);
+    let found = images.len();
+    let mut images = deduplicate_images_by_hash(images);
+    sort_images(&mut images, "time", "asc");
+    let total = images.len();
+    let folder_name = folder
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    fs::create_dir_all(output_dir).map_err(|e| AppError::FileWriteError(e.to_string()))?;
+
+    // 不覆盖时参考上次导出的清单，跳过已经完整导出的图片
+    let mut prior = if options.overwrite {
+        HashMap::new()
+    } else {
+        let output_dir = output_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || read_export_manifest(&output_dir))
+            .await
+            .map_err(|err| AppError::Internal(format!("读取导出清单任务执行失败: {}", err)))?
+    };
+
+    let mut summary = ExportSummary {
+        skipped_duplicates: found - total,
+        ..Default::default()
+    };
+
+    let limiter = options
+        .max_write_bytes_per_sec
+        .map(|rate| Arc::new(RateLimiter::new(rate)));
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut pending = HashMap::new();
+    let mut manifest = Vec::new();
+    let describe = options.write_manifest;
+    let job_options = Arc::new(options.clone());
+    let taken_names = Arc::new(Mutex::new(HashSet::new()));
+
+    for (i, info) in images.into_iter().enumerate() {
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|err| AppError::InvalidArgument(format!("获取导出许可失败: {}", err)))?;
+
+        // 等待许可期间完成的图片先记录结果，及时发送进度
+        while let Some(joined) = tasks.try_join_next_with_id() {
+            let manifest = describe.then_some(&mut manifest);
+            record_export_result(
+                &mut summary,
+                &mut pending,
+                total,
+                joined,
+                manifest,
+                progress,
+            );
+        }
+
+        if cancel.load(Ordering::Relaxed) {
+            log::info!(
+                "导出已取消，已开始 {}/{}",
+                summary.processed() + pending.len(),
+                total
+            );
+            summary.cancelled = true;
+            break;
+        }
+
+        let source = root_path.join(&info.path);
+        let (xor_key, aes_key) = resolve_keys(state, &source);
+        let job = ExportJob {
+            source,
+            xor_key,
+            aes_key,
+            hash: extract_hash_from_filename(&info.name),
+            modified: info.modified,
+            index: i + 1,
+            folder_name: folder_name.clone(),
+            output_dir: output_dir.to_path_buf(),
+            options: job_options.clone(),
+            taken_names: taken_names.clone(),
+            limiter: limiter.clone(),
+            prior: prior.remove(&info.path),
+        };
+        let task = tasks.spawn_blocking(move || {
+            let result = export_image(&job);
+            drop(permit);
+            result
+        });
+        pending.insert(task.id(), info);
+    }
+
+    while let Some(joined) = tasks.join_next_with_id().await {
+        let manifest = describe.then_some(&mut manifest);
+        record_export_result(
+            &mut summary,
+            &mut pending,
+            total,
+            joined,
+            manifest,
+            progress,
+        );
+    }
+
+    if describe {
+        let path = write_export_manifest(manifest, output_dir)?;
+        summary.manifest_path = Some(path.display().to_string());
+    }
+
+    Ok(summary)
+}
+
+// 把文件夹中的图片全部解密导出到 output_dir，文件名按 filename_template 生成，
+// 未指定时以 hash 命名并按格式补全扩展名，可用的字段见 render_export_name
+//
+// 每张图片完成后发送 export-progress 事件，可通过 cancel_export 取消。
+// write_manifest 为 true 时在 output_dir 写入 manifest.json，记录每个文件的来源、版本、格式和尺寸。
+// overwrite 默认为 false，output_dir 中文件名和大小都相同的文件视为已导出并跳过，
+// 有上次的清单时直接按清单跳过，不再解密，中断后重新调用即可继续
+// 最多 export_concurrency 张同时解密写入，max_write_bytes_per_sec 限制所有写入的总速度，
+// 未指定时使用默认并发且不限速
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn export_folder(
+    folder_path: String,
+    output_dir: String,
+    hide_thumbnails: bool,
+    export_concurrency: Option<usize>,
+    max_write_bytes_per_sec: Option<u64>,
+    write_manifest: Option<bool>,
+    filename_template: Option<String>,
+    overwrite: Option<bool>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExportSummary, String> {
+    use tauri::Emitter;
+
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let options = ExportOptions {
+        hide_thumbnails,
+        concurrency: export_concurrency_or_default(export_concurrency),
+        max_write_bytes_per_sec,
+        write_manifest: write_manifest.unwrap_or(false),
+        filename_template: filename_template.unwrap_or_else(|| DEFAULT_EXPORT_TEMPLATE.to_string()),
+        overwrite: overwrite.unwrap_or(false),
+    };
+    state.export_cancel.store(false, Ordering::Relaxed);
+
+    export_folder_files(
+        &state,
+        &root_path,
+        &folder_path,
+        Path::new(&output_dir),
+        &options,
+        &state.export_cancel,
+        &mut |event| {
+            let _ = app.emit("export-progress", event);
+        },
+    )
+    .await
+    .map_err(|e| String::from(e))
+}
+
+// 取消正在进行的 export_folder，已开始写入的图片会写完
+#[tauri::command]
+fn cancel_export(state: State<AppState>) -> Result<(), String> {
+    state.export_cancel.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+// 解密文件夹中的所有图片，生成可直接用浏览器打开的静态相册（WebP 大图、缩略图和清单）
+//
+// 解密仍受全局解密并发限制；编码和写入最多 export_concurrency 张同时进行，
+// max_write_bytes_per_sec 限制所有写入的总速度，未指定时不限速
+#[tauri::command]
+async fn build_gallery(
+    folder_path: String,
+    dest_dir: String,
+    export_concurrency: Option<usize>,
+    max_write_bytes_per_sec: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<GalleryManifest, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let mut images = collect_images(&folder, &root_path, true, false);
+    images = deduplicate_images_by_hash(images);
+    sort_images(&mut images, "time", "asc");
+
+    let dest = PathBuf::from(&dest_dir);
+    fs::create_dir_all(&dest)
+        .map_err(|e| AppError::FileWriteError(e.to_string()))
+        .map_err(|e| String::from(e))?;
+
+    let mut manifest = GalleryManifest {
+        items: Vec::new(),
+        skipped: 0,
+    };
+
+    let limiter = max_write_bytes_per_sec.map(|rate| Arc::new(RateLimiter::new(rate)));
+    let permits = Arc::new(Semaphore::new(export_concurrency_or_default(
+        export_concurrency,
+    )));
+    let mut tasks = Vec::new();
+
+    for info in images {
+        let image = match load_image(&info.path, &state).await {
+            Ok(image) => image,
+            Err(err) => {
+                log::warn!("相册解密失败 {}: {}", info.path, err);
+                manifest.skipped += 1;
+                continue;
+            }
+        };
+
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|err| format!("获取导出许可失败: {}", err))?;
+
+        let stem = Path::new(&info.name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image")
+            .to_string();
+        let source = info.path.clone();
+        let item_dest = dest.clone();
+        let limiter = limiter.clone();
+        tasks.push((
+            info.path,
+            tokio::task::spawn_blocking(move || {
+                let item =
+                    write_gallery_item(&image.data, &source, &stem, &item_dest, limiter.as_deref());
+                drop(permit);
+                item
+            }),
+        ));
+    }
+
+    for (path, task) in tasks {
+        let item = task
+            .await
+            .map_err(|err| format!("相册生成任务执行失败: {}", err))?;
+        match item {
+            Ok(item) => manifest.items.push(item),
+            Err(err) => {
+                log::warn!("相册跳过 {}: {}", path, err);
+                manifest.skipped += 1;
+            }
+        }
+    }
+
+    write_gallery_manifest(&manifest, &dest).map_err(|e| String::from(e))?;
+    Ok(manifest)
+}
+
+// 解析导出格式名称，返回对应的图片格式和文件扩展名
+fn parse_export_format(name: &str) -> Option<(image::ImageFormat, &'static str)> {
+    match name.to_lowercase().as_str() {
+        "png" => Some((image::ImageFormat::Png, "png")),
+        "jpg" | "jpeg" => Some((image::ImageFormat::Jpeg, "jpg")),
+        "gif" => Some((image::ImageFormat::Gif, "gif")),
+        "webp" => Some((image::ImageFormat::WebP, "webp")),
+        _ => None,
+    }
+}
+
+// 读取 EXIF 中的拍摄时间 (年, 月)
+fn exif_capture_month(data: &[u8]) -> Option<(u16, u8)> {
+    let exif = read_exif(data)?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(values) => {
+            let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+            (1..=12)
+                .contains(&date.month)
+                .then_some((date.year, date.month))
+        }
+        _ => None,
+    }
+}
+
+// Unix 时间戳 (UTC) 转换为 (年, 月)
+fn unix_to_year_month(secs: u64) -> (u16, u8) {
+    let (year, month, _) = unix_to_date(secs);
+    (year, month)
+}
+
+// Unix 时间戳 (UTC) 转换为 (年, 月, 日)
+fn unix_to_date(secs: u64) -> (u16, u8, u8) {
+    // 按公历 400 年周期从 0000-03-01 起计算
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year as u16, month as u8, day as u8)
+}
+
+// 按日期整理导出时使用的子目录：优先使用 EXIF 拍摄时间，其次使用文件修改时间，
+// 都没有时放到 unknown 目录
+fn date_subdir(data: &[u8], modified: Option<u64>) -> PathBuf {
+    let month = exif_capture_month(data)
+        .or_else(|| modified.filter(|secs| *secs > 0).map(unix_to_year_month));
+    match month {
+        Some((year, month)) => PathBuf::from(format!("{:04}", year)).join(format!("{:02}", month)),
+        None => PathBuf::from("unknown"),
+    }
+}
+
+// 解码一次图片，依次转码为各个格式写入 dest_dir，返回写出的文件路径
+//
+// 非图片数据（如视频）没有可用的转码格式，直接报错；个别格式编码失败时跳过该格式
+fn export_formats(
+    data: &[u8],
+    stem: &str,
+    dest_dir: &Path,
+    formats: &[String],
+    limiter: Option<&RateLimiter>,
+) -> Result<Vec<PathBuf>, AppError> {
+    if formats.is_empty() {
+        return Err(AppError::InvalidArgument("导出格式列表为空".to_string()));
+    }
+
+    let mut targets = Vec::new();
+    for name in formats {
+        let target = parse_export_format(name)
+            .ok_or_else(|| AppError::InvalidArgument(format!("不支持的导出格式: {}", name)))?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+
+    if sniff_image_mime(data).is_none() {
+        return Err(AppError::UnsupportedImageFormat(
+            "只有图片可以转码导出".to_string(),
+        ));
+    }
+    let img = image::load_from_memory(data)
+        .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?;
+
+    fs::create_dir_all(dest_dir).map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let mut written = Vec::new();
+    for (format, extension) in targets {
+        // JPEG 不支持透明通道
+        let encoded = if format == image::ImageFormat::Jpeg {
+            image::DynamicImage::ImageRgb8(img.to_rgb8())
+        } else {
+            img.clone()
+        };
+
+        let mut out = std::io::Cursor::new(Vec::new());
+        if let Err(err) = encoded.write_to(&mut out, format) {
+            log::warn!("导出为 {} 失败，已跳过: {}", extension, err);
+            continue;
+        }
+
+        let path = dest_dir.join(format!("{}.{}", stem, extension));
+        throttle::write_file(&path, &out.into_inner(), limiter)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+// 解密一次图片并同时导出为多种格式，返回写出的文件路径
+//
+// organize_by_date 为 true 时按拍摄日期写入 dest_dir 下的 YYYY/MM 子目录；
+// max_write_bytes_per_sec 限制写入速度，未指定时不限速
+#[tauri::command]
+async fn export_multi_format(
+    image_id: String,
+    dest_dir: String,
+    formats: Vec<String>,
+    organize_by_date: bool,
+    max_write_bytes_per_sec: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let image = load_image(&image_id, &state).await?;
+
+    let modified = if organize_by_date {
+        state
+            .root_dir
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|root| resolve_in_root(root, &image_id).ok())
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+    } else {
+        None
+    };
+
+    let stem = Path::new(&image_id)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image")
+        .to_string();
+
+    let paths = tokio::task::spawn_blocking(move || {
+        let mut dest = PathBuf::from(&dest_dir);
+        if organize_by_date {
+            dest.push(date_subdir(&image.data, modified));
+        }
+        let limiter = max_write_bytes_per_sec.map(RateLimiter::new);
+        export_formats(&image.data, &stem, &dest, &formats, limiter.as_ref())
+    })
+    .await
+    .map_err(|err| format!("导出任务执行失败: {}", err))?
+    .map_err(|e| String::from(e))?;
+
+    Ok(paths
+        .into_iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+// 计算以 JPEG SOI 开头的数据流长度（到 EOI 为止），数据不完整时返回 None
+//
+// 按段长度跳过 APPn 等段，内嵌的 EXIF 缩略图不会被误判为结束
+fn jpeg_stream_len(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut i = 2;
+    loop {
+        // 跳过标记前的填充字节
+        while i + 1 < data.len() && data[i] == 0xFF && data[i + 1] == 0xFF {
+            i += 1;
+        }
+        if i + 1 >= data.len() || data[i] != 0xFF {
+            return None;
+        }
+
+        let marker = data[i + 1];
+        match marker {
+            0xD9 => return Some(i + 2),
+            0x01 | 0xD0..=0xD7 => {
+                i += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        if i + 3 >= data.len() {
+            return None;
+        }
+        let segment_len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        i += 2 + segment_len;
+
+        // SOS 之后是熵编码数据，扫描到下一个真正的标记为止
+        if marker == 0xDA {
+            loop {
+                if i + 1 >= data.len() {
+                    return None;
+                }
+                if data[i] == 0xFF {
+                    match data[i + 1] {
+                        0x00 | 0xD0..=0xD7 => {
+                            i += 2;
+                            continue;
+                        }
+                        0xFF => {
+                            i += 1;
+                            continue;
+                        }
+                        _ => break,
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+// 计算以 PNG 签名开头的数据流长度（到 IEND 块为止），数据不完整时返回 None
+fn png_stream_len(data: &[u8]) -> Option<usize> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut i = PNG_SIGNATURE.len();
+    loop {
+        if i + 8 > data.len() {
+            return None;
+        }
+        let chunk_len =
+            u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+        let chunk_type = &data[i + 4..i + 8];
+
+        // 长度 + 类型 + 数据 + CRC
+        i = i.checked_add(12)?.checked_add(chunk_len)?;
+        if i > data.len() {
+            return None;
+        }
+        if chunk_type == b"IEND" {
+            return Some(i);
+        }
+    }
+}
+
+// 查找下一个 JPEG / PNG 数据流的起始位置
+fn find_next_image_start(data: &[u8]) -> Option<usize> {
+    data.windows(4)
+        .position(|w| w[..3] == [0xFF, 0xD8, 0xFF] || w == [0x89, b'P', b'N', b'G'])
+}
+
+// 按 JPEG SOI/EOI、PNG 签名/IEND 边界拆分首尾相接的多张图片
+//
+// 无法确定结束位置的数据流（其他格式或数据截断）连同剩余数据作为最后一张图片
+fn split_concatenated_images(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut images = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let rest = &data[pos..];
+        let len = jpeg_stream_len(rest)
+            .or_else(|| png_stream_len(rest))
+            .unwrap_or(rest.len());
+        images.push(rest[..len].to_vec());
+        pos += len;
+
+        // 跳过图片之间的填充数据
+        match find_next_image_start(&data[pos..]) {
+            Some(offset) => pos += offset,
+            None => break,
+        }
+    }
+
+    if images.is_empty() {
+        images.push(data.to_vec());
+    }
+
+    images
+}
+
+// 在任意数据中查找完整的 JPEG / PNG 数据流，返回 (偏移, 数据)
+//
+// 起始标记之后的数据不完整时视为误匹配，从下一个字节继续查找
+fn carve_images(data: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut images = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = find_next_image_start(&data[pos..]) {
+        let start = pos + offset;
+        let rest = &data[start..];
+        match jpeg_stream_len(rest).or_else(|| png_stream_len(rest)) {
+            Some(len) => {
+                images.push((start, &rest[..len]));
+                pos = start + len;
+            }
+            None => pos = start + 1,
+        }
+    }
+
+    images
+}
+
+// 把切出的图片写入 output_dir，文件名为 "<源文件名>_<偏移>.<扩展名>"
+fn write_carved_images(
+    data: &[u8],
+    stem: &str,
+    output_dir: &Path,
+) -> Result<Vec<CarvedImage>, AppError> {
+    carve_images(data)
+        .into_iter()
+        .map(|(offset, bytes)| {
+            let mime_type = detect_mime_type(bytes);
+            let output = output_dir.join(format!(
+                "{}_{}.{}",
+                stem,
+                offset,
+                extension_for_mime(bytes, &mime_type)
+            ));
+            fs::write(&output, bytes)
+                .map_err(|e| AppError::FileWriteError(format!("{}: {}", output.display(), e)))?;
+            Ok(CarvedImage {
+                offset,
+                mime_type,
+                path: output.to_string_lossy().to_string(),
+                size: bytes.len(),
+            })
+        })
+        .collect()
+}
+
+// 尽力恢复单个文件中的图片：先正常解密，失败时对原始数据整体 XOR 后再切割
+//
+// 切出的图片写入 output_dir，结果中只返回路径和元数据
+fn carve_file(
+    path: &Path,
+    image_id: String,
+    xor_key: XorKey,
+    aes_key: Option<&[u8]>,
+    output_dir: &Path,
+) -> RecoveredImage {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let recovered =
+        |method: &str, images: Vec<CarvedImage>, error: Option<String>| RecoveredImage {
+            image_id: image_id.clone(),
+            method: method.to_string(),
+            images,
+            error,
+        };
+
+    // 截断的文件按尽力模式解密；解密成功但切不出图片（如截断的 AES 段解出了无效数据）时
+    // 同样回退到原始 XOR
+    let error = match DatDecryptor::decrypt_best_effort(path, xor_key, aes_key) {
+        Ok(data) => match write_carved_images(&data, &stem, output_dir) {
+            Ok(images) if !images.is_empty() => return recovered("decrypt", images, None),
+            Ok(_) => None,
+            Err(err) => return recovered("decrypt", Vec::new(), Some(String::from(err))),
+        },
+        Err(err) => Some(String::from(AppError::from(err))),
+    };
+
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(_) => return recovered("xor", Vec::new(), error),
+    };
+    match write_carved_images(
+        &V3Decryptor::xor_decrypt(&raw, xor_key.key),
+        &stem,
+        output_dir,
+    ) {
+        Ok(images) => recovered("xor", images, error),
+        Err(err) => recovered("xor", Vec::new(), Some(String::from(err))),
+    }
+}
+
+// 从损坏的缓存文件夹中尽力恢复图片，切出的图片写入 output_dir，逐个文件报告恢复结果
+#[tauri::command]
+async fn carve_folder(
+    folder_path: String,
+    output_dir: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<RecoveredImage>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let jobs: Vec<_> = collect_images(&folder, &root_path, false, false)
+        .into_iter()
+        .map(|image| {
+            let path = root_path.join(&image.path);
+            let (xor_key, aes_key) = resolve_keys(&state, &path);
+            (path, image.path, xor_key, aes_key)
+        })
+        .collect();
+
+    let output_dir = PathBuf::from(output_dir);
+    fs::create_dir_all(&output_dir)
+        .map_err(|e| String::from(AppError::FileWriteError(e.to_string())))?;
+
+    tokio::task::spawn_blocking(move || {
+        jobs.into_iter()
+            .map(|(path, id, xor_key, aes_key)| {
+                carve_file(&path, id, xor_key, aes_key.as_deref(), &output_dir)
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| format!("恢复任务执行失败: {}", err))
+}
+
+// 解密并拆分包含多张图片的 DAT 文件，单张图片时返回一个元素
+#[tauri::command]
+async fn split_multi_image(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<Vec<u8>>, String> {
+    let image = load_image(&image_id, &state).await?;
+    Ok(split_concatenated_images(&image.data))
+}
+
+// 从列表中均匀选取最多 count 个元素（保持原有顺序）
+fn pick_evenly_spaced<T: Clone>(items: &[T], count: usize) -> Vec<T> {
+    let count = count.min(items.len());
+    (0..count)
+        .map(|i| items[i * items.len() / count].clone())
+        .collect()
+}
+
+// 将各帧解码并裁剪缩放为 thumb_height 见方的格子后水平拼接，编码为 JPEG
+//
+// frames 为编码后的图片数据，逐帧解码，同一时间只保留一帧完整尺寸的像素。
+// 缺失或无法解码的帧以灰色格子占位，保证胶片条宽度固定为 帧数 * thumb_height
+fn compose_filmstrip(frames: &[Option<Vec<u8>>], thumb_height: u32) -> Result<Vec<u8>, AppError> {
+    if frames.is_empty() || frames.len() > FILMSTRIP_MAX_COUNT {
+        return Err(AppError::InvalidArgument(format!(
+            "胶片条需要 1 到 {} 张图片",
+            FILMSTRIP_MAX_COUNT
+        )));
+    }
+    if thumb_height == 0 || thumb_height > FILMSTRIP_MAX_THUMB_HEIGHT {
+        return Err(AppError::InvalidArgument(format!(
+            "thumb_height 必须在 1 到 {} 之间",
+            FILMSTRIP_MAX_THUMB_HEIGHT
+        )));
+    }
+
+    let cell = thumb_height;
+    let width = u32::try_from(frames.len())
+        .ok()
+        .and_then(|count| count.checked_mul(cell))
+        .ok_or_else(|| AppError::InvalidArgument("胶片条尺寸过大".to_string()))?;
+    let mut strip = image::RgbImage::from_pixel(width, cell, image::Rgb([48, 48, 48]));
+
+    for (index, frame) in frames.iter().enumerate() {
+        let Some(frame) = frame.as_deref() else {
+            continue;
+        };
+        if let Ok(frame) = image::load_from_memory(frame) {
+            let thumb = frame
+                .resize_to_fill(cell, cell, image::imageops::FilterType::Triangle)
+                .to_rgb8();
+            image::imageops::replace(&mut strip, &thumb, index as i64 * cell as i64, 0);
+        }
+    }
+
+    let mut out = std::io::Cursor::new(Vec::new());
+    strip
+        .write_to(&mut out, image::ImageFormat::Jpeg)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(out.into_inner())
+}
+
+// 把一帧编码为 PNG data URL
+fn frame_data_url(frame: &image::RgbaImage) -> Result<String, AppError> {
+    let mut out = std::io::Cursor::new(Vec::new());
+    frame
+        .write_to(&mut out, image::ImageFormat::Png)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(out.into_inner())
+    ))
+}
+
+// 按动画解码器逐帧解码，返回帧和循环次数
+fn decode_animation_frames<'a, D>(decoder: D) -> Result<AnimationData, AppError>
+where
+    D: image::AnimationDecoder<'a>,
+{
+    let loop_count = match decoder.loop_count() {
+        image::metadata::LoopCount::Infinite => 0,
+        image::metadata::LoopCount::Finite(n) => n.get(),
+    };
+
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            Ok(AnimationFrame {
+                data_url: frame_data_url(frame.buffer())?,
+                delay_ms: numer / denom.max(1),
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    Ok(AnimationData { frames, loop_count })
+}
+
+// 将 GIF / 动态 WebP 拆分为逐帧数据，静态图片返回延迟为 0 的单帧
+fn decode_animation(data: &[u8]) -> Result<AnimationData, AppError> {
+    let unsupported = |e: image::ImageError| AppError::UnsupportedImageFormat(e.to_string());
+    let cursor = std::io::Cursor::new(data);
+
+    match sniff_image_mime(data) {
+        Some("image/gif") => decode_animation_frames(
+            image::codecs::gif::GifDecoder::new(cursor).map_err(unsupported)?,
+        ),
+        Some("image/webp") => {
+            let decoder = image::codecs::webp::WebPDecoder::new(cursor).map_err(unsupported)?;
+            if decoder.has_animation() {
+                decode_animation_frames(decoder)
+            } else {
+                let frame = image::DynamicImage::from_decoder(decoder).map_err(unsupported)?;
+                Ok(AnimationData {
+                    frames: vec![AnimationFrame {
+                        data_url: frame_data_url(&frame.to_rgba8())?,
+                        delay_ms: 0,
+                    }],
+                    loop_count: 0,
+                })
+            }
+        }
+        _ => {
+            let frame = image::load_from_memory(data).map_err(unsupported)?;
+            Ok(AnimationData {
+                frames: vec![AnimationFrame {
+                    data_url: frame_data_url(&frame.to_rgba8())?,
+                    delay_ms: 0,
+                }],
+                loop_count: 0,
+            })
+        }
+    }
+}
+
+// 解密动图并返回逐帧数据和每帧时长，供前端精确控制播放
+//
+// WXGF 动图经 DLL 转换为 GIF 后按 GIF 处理
+#[tauri::command]
+async fn get_animation(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<AnimationData, String> {
+    let image = load_image(&image_id, &state).await?;
+
+    tokio::task::spawn_blocking(move || decode_animation(&image.data))
+        .await
+        .map_err(|err| format!("动图解码任务执行失败: {}", err))?
+        .map_err(|e| String::from(e))
+}
+
+// 生成文件夹的胶片条：按时间排序后均匀选取 count 张图片，缩放为 thumb_height 高的
+// 正方形缩略图并水平拼接为一张 JPEG
+//
+// count 和 thumb_height 超出上限时按上限处理
+#[tauri::command]
+async fn get_filmstrip(
+    folder_path: String,
+    count: usize,
+    thumb_height: u32,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    if count == 0 || thumb_height == 0 {
+        return Err(String::from(AppError::InvalidArgument(
+            "count 和 thumb_height 必须大于 0".to_string(),
+        )));
+    }
+    let count = count.min(FILMSTRIP_MAX_COUNT);
+    let thumb_height = thumb_height.min(FILMSTRIP_MAX_THUMB_HEIGHT);
+
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    // 缓存键包含文件夹签名，文件夹内容变化后自动失效
+    let signature_folder = folder.clone();
+    let signature =
+        tokio::task::spawn_blocking(move || compute_folder_signature(&signature_folder))
+            .await
+            .map_err(|err| format!("文件夹签名任务执行失败: {}", err))?
+            .map_err(|e| String::from(e))?;
+    let cache_key = format!(
+        "{}#filmstrip-{}-{}-{}",
+        folder_path, count, thumb_height, signature
+    );
+    if let Some(cached) = state.cache_get(&cache_key).await {
+        return Ok(cached.data);
+    }
+
+    let walk_root = root_path.clone();
+    let images = tokio::task::spawn_blocking(move || {
+        list_sorted_images(&folder, &walk_root, true, false, None, "time", "asc").0
+    })
+    .await
+    .map_err(|err| format!("遍历文件夹任务执行失败: {}", err))?;
+
+    // 只在这里取出编码后的数据，解码放到生成胶片条的阻塞线程中
+    let mut frames = Vec::new();
+    for info in pick_evenly_spaced(&images, count) {
+        let frame = match load_image(&info.path, &state).await {
+            Ok(image) => Some(image.data),
+            Err(err) => {
+                log::warn!("胶片条解密失败 {}: {}", info.path, err);
+                None
+            }
+        };
+        frames.push(frame);
+    }
+
+    let strip = tokio::task::spawn_blocking(move || compose_filmstrip(&frames, thumb_height))
+        .await
+        .map_err(|err| format!("胶片条生成任务执行失败: {}", err))?
+        .map_err(|e| String::from(e))?;
+
+    state
+        .cache_insert(
+            cache_key,
+            CachedImage {
+                data: strip.clone(),
+                mime_type: "image/jpeg".to_string(),
+            },
+        )
+        .await;
+
+    Ok(strip)
+}
+
+// 抽样文件夹中的 v3 文件逐个猜测 XOR 密钥，取多数一致的结果
+fn detect_folder_xor(folder: &Path, root_path: &Path) -> FolderXorGuess {
+    let mut votes: HashMap<u8, usize> = HashMap::new();
+    let mut sampled = 0;
+
+    for image in collect_images(folder, root_path, false, false) {
+        if sampled >= XOR_DETECT_SAMPLE_LIMIT {
+            break;
+        }
+
+        let path = root_path.join(&image.path);
+        if !matches!(DatDecryptor::detect_version(&path), Ok(DatVersion::V3)) {
+            continue;
+        }
+
+        let mut head = Vec::with_capacity(12);
+        let read = fs::File::open(&path).and_then(|file| {
+            use std::io::Read;
+            file.take(12).read_to_end(&mut head)
+        });
+        if read.is_err() {
+            continue;
+        }
+
+        sampled += 1;
+        if let Some(key) = guess_xor_key(&head) {
+            *votes.entry(key).or_default() += 1;
+        }
+    }
+
+    // 票数相同时取较小的密钥，保证结果稳定
+    let best = votes
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+
+    match best {
+        Some((key, count)) => FolderXorGuess {
+            xor_key: Some(key),
+            confidence: count as f64 / sampled as f64,
+            sampled,
+        },
+        None => FolderXorGuess {
+            xor_key: None,
+            confidence: 0.0,
+            sampled,
+        },
+    }
+}
+
+// 自动检测文件夹使用的 XOR 密钥，store 为 true 时将其保存为该文件夹的覆盖密钥
+//
+// 保存时沿用该文件夹当前生效的 AES 密钥
+#[tauri::command]
+fn autodetect_folder_xor(
+    folder_path: String,
+    store: bool,
+    state: State<AppState>,
+) -> Result<FolderXorGuess, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let guess = detect_folder_xor(&folder, &root_path);
+
+    if let (true, Some(key)) = (store, guess.xor_key) {
+        let aes = find_folder_override(&state.folder_keys.lock().unwrap(), &folder)
+            .map(|(_, aes)| aes)
+            .unwrap_or_else(|| state.aes_key.lock().unwrap().clone());
+        store_folder_keys(&state, folder, key, aes).map_err(|e| String::from(e))?;
+    }
+
+    Ok(guess)
+}
+
+// 从图片头或 WXGF 码流中读取尺寸，不解码像素
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match dll::parse_wxgf_header(data) {
+        Some(info) => Some((info.width, info.height)),
+        None => image::ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok()),
+    }
+}
+
+// 解密文件并生成索引条目
+fn build_index_entry(job: &IndexJob) -> Result<IndexEntry, AppError> {
+    let (data, hash) =
+        DatDecryptor::decrypt_and_hash(&job.path, job.xor_key, job.aes_key.as_deref(), "sha256")?;
+
+    let dims = image_dimensions(&data);
+
+    let mime = match sniff_image_mime(&data) {
+        Some(mime) => Some(mime.to_string()),
+        None if dims.is_some() => Some("image/wxgf".to_string()),
+        None => None,
+    };
+
+    Ok(IndexEntry {
+        hash,
+        mime,
+        dims,
+        mtime: job.mtime,
+    })
+}
+
+// 增量更新索引文件：修改时间未变的文件直接跳过，每处理 checkpoint_interval 个文件保存一次
+//
+// cancel 被置位时保存已处理的部分并提前返回
+fn update_content_index(
+    index_path: &Path,
+    root_path: &Path,
+    jobs: &[IndexJob],
+    checkpoint_interval: usize,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(IndexProgressEvent),
+) -> Result<IndexSummary, AppError> {
+    let mut index = ContentIndex::load(index_path, root_path);
+    let mut summary = IndexSummary {
+        total: jobs.len(),
+        index_path: index_path.display().to_string(),
+        ..Default::default()
+    };
+
+    let checkpoint_interval = checkpoint_interval.max(1);
+
+    for (i, job) in jobs.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            log::info!("索引已取消，已处理 {}/{}", i, jobs.len());
+            summary.cancelled = true;
+            break;
+        }
+
+        if index.fresh_entry(&job.rel_path, job.mtime).is_some() {
+            summary.skipped += 1;
+        } else {
+            match build_index_entry(job) {
+                Ok(entry) => {
+                    index.entries.insert(job.rel_path.clone(), entry);
+                    summary.indexed += 1;
+                }
+                Err(err) => {
+                    log::warn!("建立索引失败 {}: {}", job.rel_path, err);
+                    summary.failed += 1;
+                }
+            }
+        }
+
+        if summary.indexed > 0 && (i + 1) % checkpoint_interval == 0 {
+            index.save(index_path)?;
+        }
+
+        progress(IndexProgressEvent {
+            processed: i + 1,
+            total: jobs.len(),
+            path: job.rel_path.clone(),
+        });
+    }
+
+    index.save(index_path)?;
+    Ok(summary)
+}
+
+// 解密文件夹中的所有图片，记录内容哈希、格式和尺寸并持久化为索引
+//
+// 已建立索引且修改时间未变的文件会被跳过，中断后重新调用即可继续。
+// checkpoint_interval 为保存间隔（文件数），未指定时使用 INDEX_SAVE_INTERVAL
+#[tauri::command]
+async fn build_index(
+    folder_path: String,
+    recursive: bool,
+    checkpoint_interval: Option<usize>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<IndexSummary, String> {
+    use tauri::Emitter;
+
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let folder = resolve_folder_in_root(&root_path, &folder_path).map_err(|e| String::from(e))?;
+
+    let jobs: Vec<IndexJob> = collect_images(&folder, &root_path, false, recursive)
+        .into_iter()
+        .map(|image| {
+            let path = root_path.join(&image.path);
+            let (xor_key, aes_key) = resolve_keys(&state, &path);
+            IndexJob {
+                path,
+                rel_path: image.path,
+                mtime: image.modified,
+                xor_key,
+                aes_key,
+            }
+        })
+        .collect();
+
+    let index_path = index::index_path_for_root(&root_path);
+    let interval = checkpoint_interval.unwrap_or(INDEX_SAVE_INTERVAL);
+    let cancel = state.index_cancel.clone();
+    cancel.store(false, Ordering::Relaxed);
+
+    tokio::task::spawn_blocking(move || {
+        update_content_index(
+            &index_path,
+            &root_path,
+            &jobs,
+            interval,
+            &cancel,
+            &mut |event| {
+                let _ = app.emit("index-progress", event);
+            },
+        )
+    })
+    .await
+    .map_err(|err| format!("索引任务执行失败: {}", err))?
+    .map_err(|e| String::from(e))
+}
+
+// 取消正在进行的 build_index，已处理的部分会保存
+#[tauri::command]
+fn cancel_index(state: State<AppState>) -> Result<(), String> {
+    state.index_cancel.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+// 计算图片解密后内容的哈希（WXGF 转换之前的数据），algorithm 支持 md5 / sha256
+#[tauri::command]
+async fn get_image_hash(
+    image_id: String,
+    algorithm: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &image_id).map_err(|e| String::from(e))?;
+
+    let (xor_key, aes_key) = resolve_keys(&state, &full_path);
+
+    let (_, digest) = tokio::task::spawn_blocking(move || {
+        DatDecryptor::decrypt_and_hash(&full_path, xor_key, aes_key.as_deref(), &algorithm)
+    })
+    .await
+    .map_err(|err| format!("哈希任务执行失败: {}", err))?
+    .map_err(|e| String::from(AppError::from(e)))?;
+
+    Ok(digest)
+}
+
+// 临时文件名中的序号，同一进程内同时写入同一目标时互不冲突
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// 先写入 output 同目录下的临时文件，write 成功后再重命名为 output
+//
+// 失败时只删除临时文件：密钥错误等解密失败不会截断或删除 output 处已有的文件
+fn write_file_atomically<T>(
+    output: &Path,
+    write: impl FnOnce(&mut fs::File) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let write_error =
+        |e: std::io::Error| AppError::FileWriteError(format!("{}: {}", output.display(), e));
+    let file_name = output
+        .file_name()
+        .ok_or_else(|| AppError::InvalidPath(output.display().to_string()))?;
+    let temp = output.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .map_err(write_error)?;
+
+    let result = write(&mut file).and_then(|value| {
+        file.sync_all().map_err(write_error)?;
+        Ok(value)
+    });
+    drop(file);
+
+    let renamed = result.and_then(|value| {
+        fs::rename(&temp, output).map_err(write_error)?;
+        Ok(value)
+    });
+    if renamed.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    renamed
+}
+
+// 根据查找路径、文件是否存在和已缓存的加载结果组装 DLL 状态
+fn build_dll_status(
+    path: Option<&Path>,
+    found: bool,
+    load_result: Option<Result<&str, AppError>>,
+) -> DllStatus {
+    let path_str = path.map(|p| p.display().to_string());
+    let loaded = load_result.as_ref().map(|r| r.is_ok());
+    let symbol = match &load_result {
+        Some(Ok(symbol)) => Some(symbol.to_string()),
+        _ => None,
+    };
+    let error = match &load_result {
+        Some(Err(err)) => Some(err.to_string()),
+        _ => None,
+    };
+
+    let message = match (&path_str, found, &load_result) {
+        (None, _, _) => "当前平台不支持 WXGF 转换，仅 Windows 可用".to_string(),
+        (Some(_), _, Some(Ok(_))) => "DLL 已加载，可以转换 WXGF 图片".to_string(),
+        (Some(path), false, _) => format!(
+            "未在 {} 找到 VoipEngine.dll，请将其放到该位置后重启程序",
+            path
+        ),
+        (Some(_), true, Some(Err(err))) => format!("DLL 加载失败: {}", err),
+        (Some(_), true, None) => "已找到 DLL，将在首次转换 WXGF 图片时加载".to_string(),
+    };
+
+    DllStatus {
+        supported: path.is_some(),
+        path: path_str,
+        found,
+        loaded,
+        symbol,
+        error,
+        message,
+    }
+}
+
+// 查询 WXGF 转换 DLL 的状态，只读取已缓存的加载结果，不会触发加载
+#[tauri::command]
+fn dll_status() -> DllStatus {
+    #[cfg(windows)]
+    {
+        use crate::dll::WxAMDecoder;
+
+        let path = WxAMDecoder::dll_path();
+        let load_result = WxAMDecoder::load_result().map(|r| r.map(|_| WxAMDecoder::SYMBOL_NAME));
+        build_dll_status(Some(&path), path.exists(), load_result)
+    }
+    #[cfg(not(windows))]
+    {
+        build_dll_status(None, false, None)
+    }
+}
+
+// 使用一组密钥解密文件并记录结果
+fn trial_decrypt(path: &Path, keys: &KeySet) -> KeyTrial {
+    let aes = aes_key_from_str(&keys.aes);
+    let aes = AesHandler::is_valid_key_len(aes.len()).then_some(aes);
+
+    match DatDecryptor::decrypt_exact(path, keys.xor, aes.as_deref()) {
+        Ok(data) => {
+            let entropy = (data.len() >= ENTROPY_SAMPLE_BYTES)
+                .then(|| shannon_entropy(&data[..ENTROPY_SAMPLE_BYTES]));
+            KeyTrial {
+                valid: has_media_magic(&data),
+                head_hex: data
+                    .iter()
+                    .take(16)
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                likely_wrong_key: entropy.is_some_and(|e| e >= WRONG_KEY_ENTROPY)
+                    && !starts_with_compressed_stream(&data),
+                entropy,
+                error: None,
+            }
+        }
+        Err(err) => KeyTrial {
+            valid: false,
+            head_hex: String::new(),
+            entropy: None,
+            likely_wrong_key: false,
+            error: Some(String::from(AppError::from(err))),
+        },
+    }
+}
+
+// 用给定密钥解密样本文件开头，判断密钥是否正确
+//
+// 与 trial_decrypt 一样不做 XOR 密钥纠正，只解密文件开头
+fn validate_keys_with(path: &Path, xor: u8, aes: &str) -> KeyValidation {
+    let detected_version = match api::detect_version(path) {
+        Ok(version) => version_label(version).to_string(),
+        Err(err) => {
+            return KeyValidation {
+                valid: false,
+                detected_version: version_label(DatVersion::Unknown).to_string(),
+                detected_mime: None,
+                error: Some(String::from(err)),
+            }
+        }
+    };
+
+    let aes_key = api::parse_aes_key(aes);
+    match DatDecryptor::decrypt_head(path, xor, aes_key.as_deref()) {
+        Ok(head) => {
+            let detected_mime = head_mime_type(&head);
+            KeyValidation {
+                valid: detected_mime.is_some(),
+                detected_version,
+                detected_mime,
+                error: None,
+            }
+        }
+        Err(err) => KeyValidation {
+            valid: false,
+            detected_version,
+            detected_mime: None,
+            error: Some(String::from(AppError::from(err))),
+        },
+    }
+}
+
+// 用样本文件校验一组密钥，不修改当前密钥，也不写入配置
+//
+// sample_path 为相对根目录的路径，供设置界面在保存前确认密钥正确
+#[tauri::command]
+fn validate_keys(
+    sample_path: String,
+    xor: u8,
+    aes: String,
+    state: State<AppState>,
+) -> Result<KeyValidation, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &sample_path).map_err(|e| String::from(e))?;
+
+    Ok(validate_keys_with(&full_path, xor, &aes))
+}
+
+// 计算数据的香农熵（比特/字节），均匀随机数据接近 8
+fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// PNG / GIF / WebP 的文件头之后紧接着压缩数据，开头部分的熵本来就很高
+//
+// JPEG 开头是量化表、哈夫曼表等结构化数据，熵较低，因此不在此列
+fn starts_with_compressed_stream(data: &[u8]) -> bool {
+    matches!(
+        sniff_image_mime(data),
+        Some("image/png") | Some("image/gif") | Some("image/webp")
+    )
+}
+
+// 用两组密钥分别解密同一个文件，对比哪组密钥正确，两组密钥都不会被保存
+//
+// 两次完整解密在阻塞线程中进行，不持有根目录的锁
+#[tauri::command]
+async fn compare_keys(
+    file_path: String,
+    keys_a: KeySet,
+    keys_b: KeySet,
+    state: State<'_, AppState>,
+) -> Result<KeyCompare, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &file_path).map_err(|e| String::from(e))?;
+
+    tokio::task::spawn_blocking(move || KeyCompare {
+        a: trial_decrypt(&full_path, &keys_a),
+        b: trial_decrypt(&full_path, &keys_b),
+    })
+    .await
+    .map_err(|err| format!("密钥对比任务执行失败: {}", err))
+}
+
+// 切换根目录下某张图片的收藏状态，返回切换后是否已收藏
+fn toggle_favorite_in(config: &mut Config, root: &str, image_id: &str) -> bool {
+    let favorites = config.favorites.entry(root.to_string()).or_default();
+
+    if let Some(index) = favorites.iter().position(|id| id == image_id) {
+        favorites.remove(index);
+        if favorites.is_empty() {
+            config.favorites.remove(root);
+        }
+        false
+    } else {
+        favorites.push(image_id.to_string());
+        true
+    }
+}
+
+// 当前根目录的配置键
+fn current_root_key(state: &AppState) -> Result<String, AppError> {
+    state
+        .root_dir
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|root| root.to_string_lossy().to_string())
+        .ok_or(AppError::RootDirNotSet)
+}
+
+// 切换图片的收藏状态并保存到配置文件，返回切换后是否已收藏
+//
+// 收藏按根目录分开保存，避免不同账号的收藏混在一起
+#[tauri::command]
+fn toggle_favorite(image_id: String, state: State<AppState>) -> Result<bool, String> {
+    let root = current_root_key(&state).map_err(|e| String::from(e))?;
+
+    update_config(|config| toggle_favorite_in(config, &root, &image_id))
+        .map_err(|e| String::from(e))
+}
+
+// 列出当前根目录下收藏的图片（按收藏顺序）
+#[tauri::command]
+fn list_favorites(state: State<AppState>) -> Result<Vec<String>, String> {
+    let root = current_root_key(&state).map_err(|e| String::from(e))?;
+
+    Ok(read_config()
+        .map_err(|e| String::from(e))?
+        .and_then(|mut config| config.favorites.remove(&root))
+        .unwrap_or_default())
+}
+
+// 视频帧在图片缓存中使用的键，时间戳按 0.1 秒取整
+fn video_frame_cache_key(image_id: &str, timestamp_secs: f64) -> String {
+    format!(
+        "{}#frame-{}",
+        image_id,
+        (timestamp_secs.max(0.0) * 10.0).round() as u64
+    )
+}
+
+// 截取视频在指定时间点的一帧，返回 JPEG 数据
+//
+// 需要启用 ffmpeg feature，并在程序目录或 PATH 中提供 ffmpeg
+#[tauri::command]
+async fn get_video_frame(
+    image_id: String,
+    timestamp_secs: f64,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    let cache_key = video_frame_cache_key(&image_id, timestamp_secs);
+    if let Some(cached) = state.cache_get(&cache_key).await {
+        return Ok(cached.data);
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    {
+        let video = load_image(&image_id, &state).await?;
+        let timestamp = (timestamp_secs.max(0.0) * 10.0).round() / 10.0;
+
+        let frame =
+            tokio::task::spawn_blocking(move || video::extract_frame(&video.data, timestamp))
+                .await
+                .map_err(|err| format!("视频帧提取任务执行失败: {}", err))?
+                .map_err(|e| String::from(e))?;
+
+        state
+            .cache_insert(
+                cache_key,
+                CachedImage {
+                    data: frame.clone(),
+                    mime_type: "image/jpeg".to_string(),
+                },
+            )
+            .await;
+
+        Ok(frame)
+    }
+
+    #[cfg(not(feature = "ffmpeg"))]
+    {
+        Err(String::from(AppError::Internal(
+            "当前构建未启用 ffmpeg 功能，无法提取视频帧".to_string(),
+        )))
+    }
+}
+
+// 编译时启用的可选功能列表
+fn enabled_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "ffmpeg") {
+        features.push("ffmpeg".to_string());
+    }
+    if cfg!(feature = "faces") {
+        features.push("faces".to_string());
+    }
+    features
+}
+
+// 获取版本、平台和编译时功能信息，不依赖运行时状态
+#[tauri::command]
+fn app_info() -> AppInfo {
+    #[cfg(windows)]
+    let dll_bundled = crate::dll::WxAMDecoder::dll_path().exists();
+    #[cfg(not(windows))]
+    let dll_bundled = false;
+
+    AppInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+        features: enabled_features(),
+        dll_bundled,
+    }
+}
+
+// 读取 WXGF 图片的尺寸、帧数和是否为动图，不调用 DLL
+//
+// 非 WXGF 文件返回 None，前端可据此在转换前选择 GIF 或静态图
+#[tauri::command]
+async fn get_wxgf_info(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<dll::WxgfInfo>, String> {
+    let root_path = state
+        .root_dir
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(AppError::RootDirNotSet)
+        .map_err(|e| String::from(e))?;
+
+    let full_path = resolve_in_root(&root_path, &image_id).map_err(|e| String::from(e))?;
+
+    let (xor_key, aes_key) = resolve_keys(&state, &full_path);
+
+    // 图片缓存中保存的是转换后的数据，这里需要解密后的原始 WXGF
+    let data = tokio::task::spawn_blocking(move || {
+        DatDecryptor::decrypt(&full_path, xor_key, aes_key.as_deref())
+    })
+    .await
+    .map_err(|err| format!("解密任务执行失败: {}", err))?
+    .map_err(|e| String::from(AppError::from(e)))?;
+
+    Ok(dll::parse_wxgf_header(&data))
+}
+
+// 解码图片并计算清晰度（拉普拉斯方差）和亮度
+fn compute_quality(data: &[u8]) -> Result<QualityScore, AppError> {
+    let gray = image::load_from_memory(data)
+        .map_err(|e| AppError::UnsupportedImageFormat(e.to_string()))?
+        .to_luma8();
+    let (width, height) = gray.dimensions();
+
+    let brightness =
+        gray.pixels().map(|p| p.0[0] as f64).sum::<f64>() / (width as f64 * height as f64) / 255.0;
+
+    // 3x3 拉普拉斯核，只计算内部像素；方差用 Welford 算法逐个累计，不保存每个像素的响应
+    let mut count = 0u64;
+    let mut mean = 0.0;
+    let mut m2 = 0.0;
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let at = |dx: i32, dy: i32| {
+                gray.get_pixel((x as i32 + dx) as u32, (y as i32 + dy) as u32)
+                    .0[0] as f64
+            };
+            let response = at(-1, 0) + at(1, 0) + at(0, -1) + at(0, 1) - 4.0 * at(0, 0);
+            count += 1;
+            let delta = response - mean;
+            mean += delta / count as f64;
+            m2 += delta * (response - mean);
+        }
+    }
+
+    let sharpness = if count == 0 { 0.0 } else { m2 / count as f64 };
+
+    Ok(QualityScore {
+        sharpness,
+        brightness,
+    })
+}
+
+// 读取图片中的 EXIF 信息，没有 EXIF 时返回 None
+fn read_exif(data: &[u8]) -> Option<exif::Exif> {
+    exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()
+}
+
+// 相邻像素（向右、向下）颜色几乎相同的比例，截图中大面积纯色区域会使该值很高
+fn flat_color_ratio(img: &image::RgbaImage) -> f64 {
+    let (width, height) = img.dimensions();
+    let same = |a: &image::Rgba<u8>, b: &image::Rgba<u8>| {
+        a.0.iter().zip(b.0.iter()).all(|(x, y)| x.abs_diff(*y) <= 2)
+    };
+
+    let mut flat = 0u64;
+    let mut total = 0u64;
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = img.get_pixel(x, y);
+            if x + 1 < width {
+                total += 1;
+                flat += same(pixel, img.get_pixel(x + 1, y)) as u64;
+            }
+            if y + 1 < height {
+                total += 1;
+                flat += same(pixel, img.get_pixel(x, y + 1)) as u64;
+            }
+        }
+    }
+
+    if total == 0 {
+        0.0
+    } else {
+        flat as f64 / total as f64
+    }
+}
+
+// 按启发式规则判断图片类别：photo / screenshot / sticker / other
+//
+// 依次检查：带相机 EXIF 的是照片；尺寸小且有透明像素的是贴纸；
+// 大面积纯色（竖长图的阈值更低）的是截图；其余较大的 JPEG 视为照片
+fn classify_image(data: &[u8]) -> &'static str {
+    if read_exif(data).is_some_and(|exif| {
+        exif.get_field(exif::Tag::Make, exif::In::PRIMARY).is_some()
+            || exif
+                .get_field(exif::Tag::Model, exif::In::PRIMARY)
+                .is_some()
+    }) {
+        return "photo";
+    }
+
+    let img = match image::load_from_memory(data) {
+        Ok(img) => img.to_rgba8(),
+        Err(_) => return "other",
+    };
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return "other";
+    }
+
+    let has_transparency = img.pixels().any(|p| p.0[3] < 255);
+    if has_transparency && width.max(height) <= STICKER_MAX_DIM {
+        return "sticker";
+    }
+
+    // 手机截图通常是竖长的，适当放宽纯色比例要求
+    let aspect = width.max(height) as f64 / width.min(height) as f64;
+    let flat_threshold = if aspect >= 1.7 {
+        SCREENSHOT_FLAT_RATIO - 0.1
+    } else {
+        SCREENSHOT_FLAT_RATIO
+    };
+    if flat_color_ratio(&img) >= flat_threshold {
+        return "screenshot";
+    }
+
+    if sniff_image_mime(data) == Some("image/jpeg") && width.min(height) >= PHOTO_MIN_DIM {
+        return "photo";
+    }
+
+    "other"
+}
+
+// 图片分析结果（分类、质量评估、人脸检测）的缓存键
+//
+// 结果以 JSON 保存为图片的派生条目，与缩略图共用数量上限，随图片缓存一起清除
+fn analysis_cache_key(image_id: &str, kind: &str) -> String {
+    format!("{}#{}", image_id, kind)
+}
+
+// 读取缓存的图片分析结果
+async fn cached_analysis<T: serde::de::DeserializeOwned>(state: &AppState, key: &str) -> Option<T> {
+    let cached = state.cache_get(key).await?;
+    serde_json::from_slice(&cached.data).ok()
+}
+
+// 缓存图片分析结果
+async fn store_analysis<T: Serialize>(state: &AppState, key: String, value: &T) {
+    match serde_json::to_vec(value) {
+        Ok(data) => {
+            let image = CachedImage {
+                data,
+                mime_type: "application/json".to_string(),
+            };
+            state.cache_insert(key, image).await;
+        }
+        Err(err) => log::warn!("序列化分析结果失败 {}: {}", key, err),
+    }
+}
+
+// 判断图片内容类别（照片、截图、贴纸或其他），用于自动打标签
+#[tauri::command]
+async fn classify_content(image_id: String, state: State<'_, AppState>) -> Result<String, String> {
+    let cache_key = analysis_cache_key(&image_id, "class");
+    if let Some(class) = cached_analysis::<String>(&state, &cache_key).await {
+        return Ok(class);
+    }
+
+    let image = load_image(&image_id, &state).await?;
+    let class = tokio::task::spawn_blocking(move || classify_image(&image.data))
+        .await
+        .map_err(|err| format!("图片分类任务执行失败: {}", err))?;
+
+    store_analysis(&state, cache_key, &class).await;
+    Ok(class.to_string())
+}
+
+// 评估图片的清晰度和亮度，供前端标记模糊或曝光异常的照片
+#[tauri::command]
+async fn assess_quality(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<QualityScore, String> {
+    let cache_key = analysis_cache_key(&image_id, "quality");
+    if let Some(score) = cached_analysis(&state, &cache_key).await {
+        return Ok(score);
+    }
+
+    let image = load_image(&image_id, &state).await?;
+    let score = tokio::task::spawn_blocking(move || compute_quality(&image.data))
+        .await
+        .map_err(|err| format!("质量评估任务执行失败: {}", err))?
+        .map_err(|e| String::from(e))?;
+
+    store_analysis(&state, cache_key, &score).await;
+    Ok(score)
+}
+
+// 检测图片中的人脸并返回人脸框，非图片返回空列表
+//
+// 需要启用 faces feature，并在程序目录提供人脸检测模型
+#[tauri::command]
+async fn detect_faces(
+    image_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<FaceBox>, String> {
+    #[cfg(feature = "faces")]
+    {
+        let cache_key = analysis_cache_key(&image_id, "faces");
+        if let Some(faces) = cached_analysis(&state, &cache_key).await {
+            return Ok(faces);
+        }
+
+        let image = load_image(&image_id, &state).await?;
+        let faces = tokio::task::spawn_blocking(move || faces::detect(&image.data))
+            .await
+            .map_err(|err| format!("人脸检测任务执行失败: {}", err))?
+            .map_err(|e| String::from(e))?;
+
+        store_analysis(&state, cache_key, &faces).await;
+        Ok(faces)
+    }
+
+    #[cfg(not(feature = "faces"))]
+    {
+        let _ = (image_id, state);
+        Err(String::from(AppError::Internal(
+            "当前构建未启用 faces 功能，无法检测人脸".to_string(),
+        )))
+    }
+}
+
+// 判断进程名列表中是否包含微信主进程（3.x 为 WeChat.exe，4.x 为 Weixin.exe）
+#[cfg_attr(not(windows), allow(dead_code))]
+fn contains_wechat_process<I, S>(process_names: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    process_names.into_iter().any(|name| {
+        let name = name.as_ref();
+        name.eq_ignore_ascii_case("WeChat.exe") || name.eq_ignore_ascii_case("Weixin.exe")
+    })
+}
+
+// 检查微信是否正在运行，微信运行时可能锁定数据文件（尽力而为，非 Windows 平台始终返回 false）
+#[tauri::command]
+fn check_wechat_running() -> bool {
+    #[cfg(windows)]
+    {
+        use sysinfo::{ProcessesToUpdate, System};
+
+        let mut system = System::new();
+        system.refresh_processes(ProcessesToUpdate::All, true);
+        contains_wechat_process(
+            system
+                .processes()
+                .values()
+                .map(|process| process.name().to_string_lossy().to_string()),
+        )
+    }
+
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
+// 切换无界面模式，开启时清空已有缓存
+#[tauri::command]
+fn set_headless(headless: bool, state: State<AppState>) -> Result<(), String> {
+    state.headless.store(headless, Ordering::Relaxed);
+    if headless {
+        state.image_cache.lock().unwrap().clear();
+    }
+    Ok(())
+}
+
+// 获取当前密钥
+#[tauri::command]
+fn get_keys(state: State<AppState>) -> Result<(u8, String), String> {
+    let xor = *state.xor_key.lock().unwrap();
+    let aes = state.aes_key.lock().unwrap();
+    Ok((xor, aes_key_to_str(&aes)))
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let state = AppState::default();
+    let config = read_config()
+        .unwrap_or_else(|err| {
+            log::warn!("读取配置文件失败，使用默认配置（不会覆盖原文件）: {}", err);
+            None
+        })
+        .unwrap_or_default();
+    if let Some(backend) = CacheBackend::parse(&config.cache_backend) {
+        state.image_cache.lock().unwrap().set_backend(backend);
+    }
+    if let Some(mode) = EtagMode::parse(&config.etag_mode) {
+        *state.etag_mode.lock().unwrap() = mode;
+    }
+    match parse_mime_overrides(&config.mime_overrides) {
+        Ok(overrides) => *MIME_OVERRIDES.write().unwrap() = overrides,
+        Err(err) => log::warn!("忽略无效的 MIME 覆盖规则: {}", err),
+    }
+    match parse_dll_dir(&config.dll_dir) {
+        #[cfg(windows)]
+        Ok(dir) => crate::dll::WxAMDecoder::set_dll_dir(dir),
+        #[cfg(not(windows))]
+        Ok(_) => {}
+        Err(err) => log::warn!("忽略无效的 DLL 目录: {}", err),
+    }
+    if std::env::args().any(|arg| arg == HEADLESS_ARG) {
+        state.headless.store(true, Ordering::Relaxed);
+    }
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
+        .manage(state)
+        .register_asynchronous_uri_scheme_protocol(IMAGE_SCHEME, |ctx, request, responder| {
+            use tauri::Manager;
+
+            let app = ctx.app_handle().clone();
+            let image_id = image_id_from_uri_path(request.uri().path());
+            tauri::async_runtime::spawn(async move {
+                let state = app.state::<AppState>();
+                let result = match image_id {
+                    Some(image_id) => load_image(&image_id, &state).await,
+                    None => Err(String::from(AppError::InvalidArgument(
+                        "缺少图片 ID".to_string(),
+                    ))),
+                };
+                responder.respond(image_protocol_response(result));
+            });
+        })
+        .invoke_handler(tauri::generate_handler![
+            open_folder_dialog,
+            get_folder_tree,
+            get_images_in_folder,
+            get_images_batch,
+            decrypt_dat_file,
+            update_keys,
+            get_keys,
+            get_image_data,
+            clear_image_cache,
+            reset_state,
+            force_decrypt,
+            folder_signature,
+            set_folder_keys,
+            get_image_with_etag,
+            images_in_range,
+            check_wechat_running,
+            open_decrypt_session,
+            read_decrypted_chunk,
+            close_decrypt_session,
+            version_breakdown,
+            aes_key_requirement,
+            stream_decrypt,
+            set_session_keys,
+            get_optimized,
+            decrypt_export_bundle,
+            can_decrypt,
+            set_wxgf_flags,
+            split_multi_image,
+            get_filmstrip,
+            autodetect_folder_xor,
+            get_image_hash,
+            dll_status,
+            compare_keys,
+            find_image_roots,
+            validate_keys,
+            toggle_favorite,
+            list_favorites,
+            get_video_frame,
+            app_info,
+            get_wxgf_info,
+            smart_prefetch,
+            assess_quality,
+            carve_folder,
+            export_multi_format,
+            recognized_patterns,
+            build_index,
+            get_animation,
+            detect_faces,
+            save_keys_to_credential_store,
+            load_keys_from_credential_store,
+            preview_dedup,
+            reclaimable_space,
+            set_headless,
+            classify_content,
+            set_plaintext_fallback,
+            set_etag_mode,
+            build_gallery,
+            cancel_index,
+            decrypt_explicit,
+            set_mime_overrides,
+            get_grid_metadata,
+            decrypt_dat_to_file,
+            export_folder,
+            cancel_export,
+            get_thumbnail,
+            get_image_variants,
+            set_cache_backend,
+            set_dll_dir
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG_HEADER: [u8; 8] = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
+    const PNG_HEADER: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+    fn write_file(dir: &Path, name: &str, data: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_folder_override_beats_global_keys() {
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x11;
+        *state.aes_key.lock().unwrap() = b"globalglobalglob".to_vec();
+        state.folder_keys.lock().unwrap().insert(
+            PathBuf::from("/root/account_b"),
+            (0x22, b"accountbaccountb".to_vec()),
+        );
+
+        let (xor, aes) = resolve_keys(&state, Path::new("/root/account_b/img/a.dat"));
+        assert_eq!(xor.key, 0x22);
+        assert_eq!(aes.as_deref(), Some(&b"accountbaccountb"[..]));
+
+        let (xor, aes) = resolve_keys(&state, Path::new("/root/account_a/img/a.dat"));
+        assert_eq!(xor.key, 0x11);
+        assert_eq!(aes.as_deref(), Some(&b"globalglobalglob"[..]));
+    }
+
+    #[test]
+    fn test_folder_override_walks_up_to_nearest_ancestor() {
+        let mut folder_keys = HashMap::new();
+        folder_keys.insert(PathBuf::from("/root/a"), (0x01, vec![]));
+        folder_keys.insert(PathBuf::from("/root/a/b"), (0x02, vec![]));
+
+        let nearest = find_folder_override(&folder_keys, Path::new("/root/a/b/c/d/x.dat"));
+        assert_eq!(nearest.map(|(xor, _)| xor), Some(0x02));
+
+        let parent = find_folder_override(&folder_keys, Path::new("/root/a/c/x.dat"));
+        assert_eq!(parent.map(|(xor, _)| xor), Some(0x01));
+
+        assert!(find_folder_override(&folder_keys, Path::new("/root/z/x.dat")).is_none());
+    }
+
+    fn set_mtime(path: &Path, secs: u64) {
+        let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_images_in_range_filters_before_paging() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("2024-03");
+        fs::create_dir(&nested).unwrap();
+
+        set_mtime(&write_file(dir.path(), "early.dat", b"x"), 1_000);
+        set_mtime(&write_file(dir.path(), "in_a.dat", b"x"), 2_000);
+        set_mtime(&write_file(&nested, "in_b.dat", b"x"), 2_500);
+        set_mtime(&write_file(&nested, "late.dat", b"x"), 9_000);
+
+        let mut images = collect_images(dir.path(), dir.path(), false, true);
+        assert_eq!(images.len(), 4);
+
+        filter_images_by_time(&mut images, 2_000, 3_000);
+        sort_images(&mut images, "time", "asc");
+        let names: Vec<&str> = images.iter().map(|img| img.name.as_str()).collect();
+        assert_eq!(names, vec!["in_a.dat", "in_b.dat"]);
+
+        let (page_images, total, has_more) = paginate_images(images, 0, 1);
+        assert_eq!(page_images.len(), 1);
+        assert_eq!(total, 2);
+        assert!(has_more);
+    }
+
+    #[test]
+    fn test_resolve_in_root_rejects_traversal() {
+        let parent = tempfile::tempdir().unwrap();
+        let root = parent.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        write_file(&root.join("sub"), "a.dat", b"x");
+        write_file(parent.path(), "secret.dat", b"x");
+
+        assert_eq!(
+            resolve_in_root(&root, "sub/a.dat").unwrap(),
+            root.join("sub/a.dat")
+        );
+
+        for escape in ["../secret.dat", "sub/../../secret.dat", "sub/../a.dat"] {
+            assert!(matches!(
+                resolve_in_root(&root, escape),
+                Err(AppError::InvalidPath(_))
+            ));
+        }
+
+        let absolute = parent.path().join("secret.dat");
+        assert!(matches!(
+            resolve_in_root(&root, absolute.to_str().unwrap()),
+            Err(AppError::InvalidPath(_))
+        ));
+
+        assert!(matches!(
+            resolve_in_root(&root, "missing.dat"),
+            Err(AppError::FileNotFound(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_in_root_rejects_symlink_escape() {
+        let parent = tempfile::tempdir().unwrap();
+        let root = parent.path().join("root");
+        fs::create_dir(&root).unwrap();
+        write_file(parent.path(), "secret.dat", b"x");
+        std::os::unix::fs::symlink(parent.path().join("secret.dat"), root.join("link.dat"))
+            .unwrap();
+
+        assert!(matches!(
+            resolve_in_root(&root, "link.dat"),
+            Err(AppError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_collect_images_non_recursive_skips_subfolders() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("sub");
+        fs::create_dir(&nested).unwrap();
+        write_file(dir.path(), "a.dat", b"x");
+        write_file(&nested, "b.dat", b"x");
+
+        let images = collect_images(dir.path(), dir.path(), false, false);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].path, "a.dat");
+    }
+
+    #[test]
+    fn test_collect_images_recursive_includes_date_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let month = dir.path().join("2024-01");
+        let day = month.join("15");
+        fs::create_dir_all(&day).unwrap();
+        write_file(dir.path(), "a.dat", b"x");
+        write_file(&month, "b.dat", b"x");
+        write_file(&day, "c.dat", b"x");
+        write_file(&day, "c_t.dat", b"x");
+
+        let (images, truncated) =
+            walk_images(&month, dir.path(), true, true, WalkOptions::default());
+        let mut paths: Vec<_> = images
+            .iter()
+            .map(|image| image.path.replace('\\', "/"))
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["2024-01/15/c.dat", "2024-01/b.dat"]);
+        assert!(!truncated);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_stops_on_symlink_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a");
+        fs::create_dir(&nested).unwrap();
+        write_file(&nested, "x.dat", b"x");
+        // a/loop -> 根目录，形成循环
+        std::os::unix::fs::symlink(dir.path(), nested.join("loop")).unwrap();
+
+        let follow = WalkOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let (images, truncated) = walk_images(dir.path(), dir.path(), false, true, follow);
+        assert_eq!(images.len(), 1);
+        assert!(!truncated);
+
+        // 默认不跟随符号链接
+        assert_eq!(collect_images(dir.path(), dir.path(), false, true).len(), 1);
+
+        let mut walk = WalkState::new(follow);
+        walk.enter_dir(dir.path(), 0);
+        let tree = build_tree(dir.path(), 0, &mut walk);
+        assert_eq!(tree.children.len(), 1);
+        assert!(tree.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_scan_image_roots_ranks_by_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("attach").join("a").join("Img");
+        let large = dir.path().join("attach").join("b").join("Img");
+        let sparse = dir.path().join("sparse");
+        let mixed = dir.path().join("mixed");
+        for folder in [&small, &large, &sparse, &mixed] {
+            fs::create_dir_all(folder).unwrap();
+        }
+
+        for i in 0..6 {
+            write_file(&small, &format!("{}.dat", i), b"x");
+        }
+        for i in 0..9 {
+            write_file(&large, &format!("{}.dat", i), b"x");
+        }
+        write_file(&large, &"a".repeat(30), b"x");
+        for i in 0..2 {
+            write_file(&sparse, &format!("{}.dat", i), b"x");
+        }
+        // 图片文件占比过低的目录不算
+        for i in 0..5 {
+            write_file(&mixed, &format!("{}.dat", i), b"x");
+        }
+        for i in 0..10 {
+            write_file(&mixed, &format!("{}.txt", i), b"x");
+        }
+
+        let roots = scan_image_roots(dir.path(), WalkOptions::default());
+        assert_eq!(roots, vec![(large.clone(), 10), (small.clone(), 6)]);
+
+        let shallow = WalkOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        assert!(scan_image_roots(dir.path(), shallow).is_empty());
+    }
+
+    #[test]
+    fn test_walk_limits_mark_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("a").join("b");
+        fs::create_dir_all(&deep).unwrap();
+        write_file(dir.path(), "top.dat", b"x");
+        write_file(&deep, "deep.dat", b"x");
+
+        let shallow = WalkOptions {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let (images, truncated) = walk_images(dir.path(), dir.path(), false, true, shallow);
+        assert_eq!(images.len(), 1);
+        assert!(truncated);
+
+        let few = WalkOptions {
+            max_entries: 1,
+            ..Default::default()
+        };
+        assert!(walk_images(dir.path(), dir.path(), false, true, few).1);
+
+        let mut walk = WalkState::new(shallow);
+        walk.enter_dir(dir.path(), 0);
+        let tree = build_tree(dir.path(), 0, &mut walk);
+        assert!(!tree.truncated);
+        assert!(tree.children[0].truncated);
+
+        let (_, truncated) =
+            walk_images(dir.path(), dir.path(), false, true, WalkOptions::default());
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_credential_store_round_trip() {
+        let store = credentials::tests::MemoryStore::default();
+
+        let state = AppState::default();
+        assert!(save_aes_to_store(&store, "account", &state).is_err());
+
+        *state.aes_key.lock().unwrap() = b"0123456789abcdef".to_vec();
+        save_aes_to_store(&store, "account", &state).unwrap();
+
+        let restored = AppState::default();
+        restored.image_cache.lock().unwrap().insert(
+            "stale".to_string(),
+            CachedImage {
+                data: vec![1],
+                mime_type: "image/jpeg".to_string(),
+            },
+        );
+        load_aes_from_store(&store, "account", &restored).unwrap();
+        assert_eq!(
+            *restored.aes_key.lock().unwrap(),
+            b"0123456789abcdef".to_vec()
+        );
+        assert!(restored.image_cache.lock().unwrap().is_empty());
+
+        assert!(load_aes_from_store(&store, "other", &restored).is_err());
+    }
+
+    #[test]
+    fn test_credential_store_keeps_hex_like_text_key() {
+        let store = credentials::tests::MemoryStore::default();
+
+        // 32 个十六进制字符的文本密钥，按 "text" 保存时是 32 字节的 AES-256 密钥
+        let text = "0123456789abcdef0123456789abcdef";
+        let key = parse_aes_key(text, "text").unwrap();
+        assert_eq!(key, text.as_bytes());
+
+        let state = AppState::default();
+        *state.aes_key.lock().unwrap() = key.clone();
+        save_aes_to_store(&store, "account", &state).unwrap();
+
+        // 保存后配置中只剩目标名称，aes_encoding 仍是用户原来选的 "text"
+        let config = Config {
+            xor: 0x56,
+            aes_encoding: "text".to_string(),
+            credential_target: Some("account".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            keys_from_config(config, &store).unwrap(),
+            (0x56, key.clone())
+        );
+
+        let restored = AppState::default();
+        load_aes_from_store(&store, "account", &restored).unwrap();
+        assert_eq!(*restored.aes_key.lock().unwrap(), key);
+    }
+
+    #[test]
+    fn test_extract_hash_strips_single_variant_suffix() {
+        assert_eq!(extract_hash_from_filename("abc_h.dat"), "abc");
+        assert_eq!(extract_hash_from_filename("abc_t.dat"), "abc");
+        assert_eq!(extract_hash_from_filename("abc.dat"), "abc");
+        assert_eq!(extract_hash_from_filename("ABC_T.DAT"), "ABC");
+
+        // hash 本身以 t 结尾，或中间含有 _t / _h 时保持不变
+        assert_eq!(extract_hash_from_filename("abct.dat"), "abct");
+        assert_eq!(extract_hash_from_filename("a_tb_hc.dat"), "a_tb_hc");
+
+        // 只去掉一个版本后缀
+        assert_eq!(extract_hash_from_filename("abc_t_t.dat"), "abc_t");
+        assert_eq!(extract_hash_from_filename("abc_h_t"), "abc_h");
+    }
+
+    #[test]
+    fn test_image_variants_lists_every_resolution() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "abc_h.dat",
+            "abc.dat",
+            "abc_t.dat",
+            "abcd.dat",
+            "other_t.dat",
+        ] {
+            write_file(dir.path(), name, b"x");
+        }
+
+        let images = collect_images(dir.path(), dir.path(), false, false);
+        let variants = find_image_variants(images, "abc");
+        let summary: Vec<_> = variants
+            .iter()
+            .map(|v| (v.image_id.as_str(), v.variant))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("abc_t.dat", "thumbnail"),
+                ("abc.dat", "normal"),
+                ("abc_h.dat", "original"),
+            ]
+        );
+
+        // 图库去重仍只保留一个版本
+        let deduped =
+            deduplicate_images_by_hash(collect_images(dir.path(), dir.path(), false, false));
+        assert_eq!(deduped.len(), 3);
+    }
+
+    #[test]
+    fn test_preview_dedup_reports_all_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["abc.dat", "abc_t.dat", "abc_h.dat", "solo_h.dat"] {
+            write_file(dir.path(), name, b"x");
+        }
+
+        let groups = build_dedup_groups(collect_images(dir.path(), dir.path(), false, false));
+        assert_eq!(groups.len(), 2);
+
+        let abc = &groups[0];
+        assert_eq!(abc.hash, "abc");
+        let names: Vec<&str> = abc.variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["abc_t.dat", "abc.dat", "abc_h.dat"]);
+
+        let best = abc
+            .variants
+            .iter()
+            .min_by_key(|v| get_image_priority(&v.name))
+            .unwrap();
+        assert_eq!(abc.chosen, best.path);
+        assert_eq!(abc.chosen, "abc_t.dat");
+
+        assert_eq!(groups[1].chosen, "solo_h.dat");
+    }
+
+    #[test]
+    fn test_list_folder_images_dedup_is_optional() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["abc.dat", "abc_t.dat", "abc_h.dat", "solo.dat"] {
+            write_file(dir.path(), name, b"x");
+        }
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        write_file(&sub, "nested.dat", b"x");
+
+        let raw = list_folder_images(dir.path(), dir.path(), false);
+        assert_eq!(raw.len(), 4);
+
+        let mut names: Vec<String> = list_folder_images(dir.path(), dir.path(), true)
+            .into_iter()
+            .map(|img| img.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["abc_t.dat", "solo.dat"]);
+    }
+
+    #[test]
+    fn test_reclaimable_space_counts_content_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3, 4];
+        let xored: Vec<u8> = jpeg.iter().map(|b| b ^ 0x37).collect();
+        let mut padded = xored.clone();
+        padded.extend_from_slice(&[0x37; 4]);
+
+        // 两组重复文件和一个没有重复的文件
+        let jobs: Vec<HashJob> = [
+            ("a.dat", xored.clone()),
+            ("b.dat", xored.clone()),
+            ("c.dat", padded.clone()),
+            ("d.dat", padded.clone()),
+            ("e.dat", vec![0x11; 20]),
+        ]
+        .into_iter()
+        .map(|(name, data)| HashJob {
+            size: data.len() as u64,
+            path: write_file(dir.path(), name, &data),
+            xor_key: XorKey::exact(0x37),
+            aes_key: None,
+        })
+        .collect();
+
+        let expected = (xored.len() + padded.len()) as u64;
+        assert_eq!(compute_reclaimable_space(&jobs, 4), expected);
+        assert_eq!(compute_reclaimable_space(&jobs, 1), expected);
+        assert_eq!(compute_reclaimable_space(&jobs[4..], 4), 0);
+    }
+
+    #[test]
+    fn test_batch_reports_dimensions() {
+        use crate::decrypt::tests::build_v4_fixture;
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 9, 5);
+        write_file(dir.path(), "a.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(dir.path(), "b.dat", &[0x01, 0x02, 0x03, 0x04]);
+        write_file(
+            dir.path(),
+            "c.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &png, 32, 8, 0x56),
+        );
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        state.headless.store(true, Ordering::Relaxed);
+
+        let images = collect_images(dir.path(), dir.path(), false, false);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let batch = runtime
+            .block_on(build_image_batch(&state, dir.path(), images, 0, 10))
+            .unwrap();
+        let dims: HashMap<_, _> = batch
+            .images
+            .iter()
+            .map(|image| (image.name.as_str(), (image.width, image.height)))
+            .collect();
+        assert_eq!(dims["a.dat"], (9, 5));
+        assert_eq!(dims["b.dat"], (0, 0));
+        // 未设置 AES 密钥时 v4 文件读不出尺寸，但版本只看文件头
+        assert_eq!(dims["c.dat"], (0, 0));
+
+        let versions: HashMap<_, _> = batch
+            .images
+            .iter()
+            .map(|image| (image.name.as_str(), image.version.as_str()))
+            .collect();
+        assert_eq!(versions["a.dat"], "v3");
+        assert_eq!(versions["c.dat"], "v4v2");
+    }
+
+    #[test]
+    fn test_headless_batch_leaves_cache_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg: Vec<u8> = [0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3, 4]
+            .iter()
+            .map(|b| b ^ 0x37)
+            .collect();
+        for name in ["a.dat", "b.dat", "c.dat"] {
+            write_file(dir.path(), name, &jpeg);
+        }
+
+        let state = AppState::default();
+        *state.root_dir.lock().unwrap() = Some(dir.path().to_path_buf());
+        *state.xor_key.lock().unwrap() = 0x37;
+        state.headless.store(true, Ordering::Relaxed);
+
+        let images = collect_images(dir.path(), dir.path(), false, false);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let batch = runtime
+            .block_on(build_image_batch(&state, dir.path(), images, 0, 10))
+            .unwrap();
+        assert_eq!(batch.images.len(), 3);
+
+        for image in &batch.images {
+            let loaded = runtime
+                .block_on(load_image(&image.image_id, &state))
+                .unwrap();
+            assert_eq!(loaded.mime_type, "image/jpeg");
+        }
+        assert!(state.image_cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_analysis_results_are_derived_cache_entries() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let state = AppState::default();
+        let quality_key = analysis_cache_key("a.dat", "quality");
+        let class_key = analysis_cache_key("a.dat", "class");
+
+        let score = QualityScore {
+            sharpness: 12.5,
+            brightness: 0.25,
+        };
+        runtime.block_on(store_analysis(&state, quality_key.clone(), &score));
+        runtime.block_on(store_analysis(&state, class_key.clone(), &"photo"));
+        let cached: QualityScore = runtime
+            .block_on(cached_analysis(&state, &quality_key))
+            .unwrap();
+        assert_eq!((cached.sharpness, cached.brightness), (12.5, 0.25));
+        let class: Option<String> = runtime.block_on(cached_analysis(&state, &class_key));
+        assert_eq!(class.as_deref(), Some("photo"));
+
+        // 随原图一起删除
+        cache::remove_files(state.image_cache.lock().unwrap().remove("a.dat"));
+        let class: Option<String> = runtime.block_on(cached_analysis(&state, &class_key));
+        assert!(class.is_none());
+
+        // 重置应用状态时一并清除
+        runtime.block_on(store_analysis(&state, quality_key.clone(), &score));
+        reset_app_state(&state, false).unwrap();
+        let cached: Option<QualityScore> = runtime.block_on(cached_analysis(&state, &quality_key));
+        assert!(cached.is_none());
+    }
+
+    #[test]
+    fn test_reset_app_state_clears_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::default();
+        *state.root_dir.lock().unwrap() = Some(dir.path().to_path_buf());
+        *state.xor_key.lock().unwrap() = 0x37;
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(state.cache_insert(
+                "a.dat".to_string(),
+                CachedImage {
+                    data: JPEG_HEADER.to_vec(),
+                    mime_type: "image/jpeg".to_string(),
+                },
+            ));
+        state.decrypt_sessions.lock().unwrap().dir = dir.path().join("sessions");
+        let session_id = session_with_data(&state, &[1, 2, 3]);
+        let session_file = state.decrypt_sessions.lock().unwrap().files[&session_id].clone();
+
+        let summary = reset_app_state(&state, false).unwrap();
+        assert_eq!(
+            summary.root_dir.as_deref(),
+            Some(dir.path().to_string_lossy().as_ref())
+        );
+        assert_eq!(summary.cached_images, 1);
+        assert_eq!(summary.decrypt_sessions, 1);
+        assert!(!summary.keys_reset);
+
+        assert!(state.root_dir.lock().unwrap().is_none());
+        assert!(state.image_cache.lock().unwrap().is_empty());
+        assert!(state.decrypt_sessions.lock().unwrap().is_empty());
+        assert!(!session_file.exists());
+        assert_eq!(*state.xor_key.lock().unwrap(), 0x37);
+
+        let again = reset_app_state(&state, false).unwrap();
+        assert!(again.root_dir.is_none());
+        assert_eq!(again.cached_images, 0);
+    }
+
+    // 在 JPEG 的 SOI 之后插入只包含一个 ASCII 字段的 EXIF 段
+    fn jpeg_with_exif(jpeg: &[u8], tag: exif::Tag, value: &str) -> Vec<u8> {
+        let field = exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&field);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(&tiff);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn test_classify_content_categories() {
+        // 伪随机噪声，没有纯色区域
+        let mut seed = 12345u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+        let noisy =
+            image::RgbImage::from_fn(64, 64, |_, _| image::Rgb([noise(), noise(), noise()]));
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        noisy.write_to(&mut jpeg, image::ImageFormat::Jpeg).unwrap();
+        let jpeg = jpeg.into_inner();
+
+        assert_eq!(
+            classify_image(&jpeg_with_exif(&jpeg, exif::Tag::Make, "Canon")),
+            "photo"
+        );
+        assert_eq!(classify_image(&jpeg), "other");
+
+        // 白色背景上的几条色块，类似聊天记录截图
+        let screenshot = image::RgbImage::from_fn(300, 600, |_, y| {
+            if y % 100 < 30 {
+                image::Rgb([120, 200, 90])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        screenshot
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        assert_eq!(classify_image(&png.into_inner()), "screenshot");
+
+        // 透明背景上的小图标
+        let sticker = image::RgbaImage::from_fn(128, 128, |x, y| {
+            if (32..96).contains(&x) && (32..96).contains(&y) {
+                image::Rgba([250, 200, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        sticker.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        assert_eq!(classify_image(&png.into_inner()), "sticker");
+
+        assert_eq!(classify_image(b"not an image"), "other");
+    }
+
+    #[test]
+    fn test_export_organized_by_date() {
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 8, 8);
+        let with_date = jpeg_with_exif(&jpeg, exif::Tag::DateTimeOriginal, "2021:07:15 10:30:00");
+
+        // EXIF 拍摄时间优先于修改时间
+        assert_eq!(
+            date_subdir(&with_date, Some(1_700_000_000)),
+            PathBuf::from("2021").join("07")
+        );
+        // 2023-11-14 22:13:20 UTC
+        assert_eq!(
+            date_subdir(&jpeg, Some(1_700_000_000)),
+            PathBuf::from("2023").join("11")
+        );
+        assert_eq!(date_subdir(&jpeg, None), PathBuf::from("unknown"));
+        assert_eq!(unix_to_year_month(0), (1970, 1));
+        assert_eq!(unix_to_year_month(951_782_400), (2000, 2));
+
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join(date_subdir(&with_date, None));
+        let paths = export_formats(&with_date, "a", &dest, &["png".to_string()], None).unwrap();
+        assert_eq!(paths, vec![dir.path().join("2021/07/a.png")]);
+        assert!(paths[0].exists());
+    }
+
+    #[test]
+    fn test_plaintext_v3_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 8, 8);
+        let plain = write_file(dir.path(), "plain.dat", &jpeg);
+        let encrypted = write_file(
+            dir.path(),
+            "encrypted.dat",
+            &V3Decryptor::xor_decrypt(&jpeg, 0x37),
+        );
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x37;
+
+        // 默认不开启，未加密文件仍使用配置的密钥
+        let (xor, aes) = resolve_keys(&state, &plain);
+        assert_eq!(xor, XorKey::exact(0x37));
+        assert_ne!(
+            DatDecryptor::decrypt(&plain, xor, aes.as_deref()).unwrap(),
+            jpeg
+        );
+
+        // 开启后解密时推算出密钥 0，哈希与流式导出使用同样的结果
+        state.plaintext_fallback.store(true, Ordering::Relaxed);
+        let (xor, aes) = resolve_keys(&state, &plain);
+        assert_eq!(xor, XorKey::recovering(0x37));
+        assert_eq!(
+            DatDecryptor::decrypt(&plain, xor, aes.as_deref()).unwrap(),
+            jpeg
+        );
+        let (_, digest) =
+            DatDecryptor::decrypt_and_hash(&plain, xor, aes.as_deref(), "sha256").unwrap();
+        let (_, expected) =
+            DatDecryptor::decrypt_and_hash(&encrypted, 0x37, None, "sha256").unwrap();
+        assert_eq!(digest, expected);
+        let written = api::decrypt_to_file(&plain, xor, None, &dir.path().join("out")).unwrap();
+        assert_eq!(fs::read(written).unwrap(), jpeg);
+
+        // 加密文件不受影响
+        let (xor, aes) = resolve_keys(&state, &encrypted);
+        assert_eq!(
+            DatDecryptor::decrypt(&encrypted, xor, aes.as_deref()).unwrap(),
+            jpeg
+        );
+    }
+
+    #[test]
+    fn test_version_breakdown_mixed_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write_file(root, "a.dat", &[0u8; 100]);
+        write_file(root, "b.dat", &[0u8; 200]);
+        let mut v1 = b"\x07\x08V1\x08\x07".to_vec();
+        v1.resize(64, 0);
+        write_file(root, "c.dat", &v1);
+        let mut v2 = b"\x07\x08V2\x08\x07".to_vec();
+        v2.resize(30, 0);
+        write_file(root, "d.dat", &v2);
+        v2.resize(50, 0);
+        write_file(root, "e.dat", &v2);
+
+        let images = collect_images(root, root, false, false);
+        let breakdown = compute_version_breakdown(root, &images);
+
+        assert_eq!(breakdown.len(), 3);
+        assert_eq!(
+            breakdown["v3"],
+            VersionStats {
+                count: 2,
+                total_bytes: 300,
+                average_size: 150
+            }
+        );
+        assert_eq!(
+            breakdown["v4v1"],
+            VersionStats {
+                count: 1,
+                total_bytes: 64,
+                average_size: 64
+            }
+        );
+        assert_eq!(
+            breakdown["v4v2"],
+            VersionStats {
+                count: 2,
+                total_bytes: 80,
+                average_size: 40
+            }
+        );
+    }
+
+    #[test]
+    fn test_aes_key_from_str_lengths() {
+        assert_eq!(aes_key_from_str("0123456789abcdef").len(), 16);
+        assert_eq!(aes_key_from_str("0123456789abcdef0123").len(), 16);
+        assert_eq!(
+            aes_key_from_str("0123456789abcdef0123456789abcdef!!"),
+            b"0123456789abcdef0123456789abcdef"
+        );
+        assert_eq!(aes_key_from_str("short"), b"short");
+    }
+
+    #[test]
+    fn test_hex_aes_key_parsing() {
+        let hex = "0f1e2d3c4b5a69788796a5b4c3d2e1f0";
+        let raw = parse_hex_bytes(hex).unwrap();
+        assert_eq!(aes_key_from_str(hex), raw);
+        assert_eq!(parse_aes_key(hex, "hex").unwrap(), raw);
+        assert_eq!(parse_aes_key(hex, "text").unwrap(), hex.as_bytes());
+
+        // 16 位文本密钥保持兼容
+        assert_eq!(
+            parse_aes_key("0123456789abcdef", "").unwrap(),
+            b"0123456789abcdef"
+        );
+
+        assert!(matches!(
+            parse_aes_key("0f1e2d3c4b5a6978zz96a5b4c3d2e1f0", "hex"),
+            Err(AppError::ConfigParseError(_))
+        ));
+        assert!(matches!(
+            parse_aes_key("0f1e", "hex"),
+            Err(AppError::ConfigParseError(_))
+        ));
+
+        // 写回配置的字符串能还原为相同的字节
+        for key in [raw, b"0123456789abcdef".to_vec(), hex.as_bytes().to_vec()] {
+            assert_eq!(aes_key_from_str(&aes_key_to_str(&key)), key);
+        }
+    }
+
+    #[test]
+    fn test_aes_key_requirement_per_subtype() {
+        let write_v4 = |dir: &Path, name: &str, signature: &[u8]| {
+            let mut data = signature.to_vec();
+            data.resize(32, 0);
+            write_file(dir, name, &data);
+        };
+
+        let fixed = tempfile::tempdir().unwrap();
+        write_v4(fixed.path(), "a.dat", b"\x07\x08V1\x08\x07");
+        write_v4(fixed.path(), "b.dat", b"\x07\x08V1\x08\x07");
+        write_file(fixed.path(), "c.dat", &[0u8; 32]);
+        assert_eq!(sample_aes_requirement(fixed.path(), fixed.path()), "fixed");
+
+        let dynamic = tempfile::tempdir().unwrap();
+        write_v4(dynamic.path(), "a.dat", b"\x07\x08V2\x08\x07");
+        assert_eq!(
+            sample_aes_requirement(dynamic.path(), dynamic.path()),
+            "dynamic"
+        );
+
+        let mixed = tempfile::tempdir().unwrap();
+        write_v4(mixed.path(), "a.dat", b"\x07\x08V1\x08\x07");
+        write_v4(mixed.path(), "b.dat", b"\x07\x08V2\x08\x07");
+        assert_eq!(sample_aes_requirement(mixed.path(), mixed.path()), "mixed");
+
+        let plain = tempfile::tempdir().unwrap();
+        write_file(plain.path(), "a.dat", &[0u8; 32]);
+        assert_eq!(sample_aes_requirement(plain.path(), plain.path()), "none");
+    }
+
+    // 收集 stream_decrypt_events 发送的数据块
+    fn collect_chunk_events(
+        state: &AppState,
+        file_path: &str,
+        chunk_size: usize,
+    ) -> (Vec<DecryptChunkEvent>, DecryptEndEvent) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let emit = move |chunk: DecryptChunkEvent| {
+            sink.lock().unwrap().push(chunk);
+            Ok(())
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let end = runtime
+            .block_on(stream_decrypt_events(state, file_path, chunk_size, emit))
+            .unwrap();
+        let events = std::mem::take(&mut *events.lock().unwrap());
+        (events, end)
+    }
+
+    fn reassemble_chunks(chunks: &[DecryptChunkEvent]) -> Vec<u8> {
+        let mut reassembled = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.seq, i);
+            reassembled.extend(
+                base64::engine::general_purpose::STANDARD
+                    .decode(&chunk.data)
+                    .unwrap(),
+            );
+        }
+        reassembled
+    }
+
+    #[test]
+    fn test_stream_chunks_reassemble() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = Vec::new();
+        let mut writer = ChunkEventWriter::new("a.dat", 64, |chunk| {
+            chunks.push(chunk);
+            Ok(())
+        });
+        // 写入的分段大小与块大小无关
+        for part in data.chunks(100) {
+            std::io::Write::write_all(&mut writer, part).unwrap();
+        }
+        let end = writer.finish("image/png".to_string()).unwrap();
+        assert_eq!((end.chunk_count, end.total_len), (16, 1000));
+        assert_eq!(chunks.len(), 16);
+        assert_eq!(reassemble_chunks(&chunks), data);
+
+        // 块大小为 0 时使用默认值
+        let mut count = 0;
+        let mut writer = ChunkEventWriter::new("a.dat", 0, |_| {
+            count += 1;
+            Ok(())
+        });
+        std::io::Write::write_all(&mut writer, &data).unwrap();
+        assert_eq!(writer.finish(String::new()).unwrap().chunk_count, 1);
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_stream_decrypt_emits_while_decrypting() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 64, 64);
+        write_file(dir.path(), "a.dat", &V3Decryptor::xor_decrypt(&png, 0x37));
+
+        let state = AppState::default();
+        *state.root_dir.lock().unwrap() = Some(dir.path().to_path_buf());
+        *state.xor_key.lock().unwrap() = 0x37;
+
+        let (chunks, end) = collect_chunk_events(&state, "a.dat", 256);
+        assert_eq!(reassemble_chunks(&chunks), png);
+        assert_eq!(end.chunk_count, chunks.len());
+        assert_eq!(end.total_len, png.len());
+        assert_eq!(end.mime_type, "image/png");
+        // 流式解密不写入图片缓存
+        assert!(state.image_cache.lock().unwrap().is_empty());
+
+        // 已缓存的图片从缓存数据切分
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(state.cache_insert(
+                "b.dat".to_string(),
+                CachedImage {
+                    data: JPEG_HEADER.to_vec(),
+                    mime_type: "image/jpeg".to_string(),
+                },
+            ));
+        let (chunks, end) = collect_chunk_events(&state, "b.dat", 3);
+        assert_eq!(reassemble_chunks(&chunks), JPEG_HEADER);
+        assert_eq!((end.chunk_count, end.mime_type.as_str()), (3, "image/jpeg"));
+    }
+
+    #[test]
+    fn test_session_keys_do_not_touch_config() {
+        let before = fs::read(CONFIG_FILE).ok();
+
+        let state = AppState::default();
+        state.session_only_keys.store(true, Ordering::Relaxed);
+        store_keys(&state, 0x42, "0123456789abcdef", "").unwrap();
+        store_keys(&state, 0x43, "fedcba9876543210", "").unwrap();
+
+        assert_eq!(*state.xor_key.lock().unwrap(), 0x43);
+        assert_eq!(*state.aes_key.lock().unwrap(), b"fedcba9876543210");
+        assert_eq!(fs::read(CONFIG_FILE).ok(), before);
+    }
+
+    #[test]
+    fn test_update_config_keeps_unparseable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        assert!(read_config_file(&path).unwrap().is_none());
+
+        // 手动编辑出错的配置文件不能被默认配置覆盖
+        let broken = br#"{"xor": 86, "aes": "0123456789abcdef","#;
+        fs::write(&path, broken).unwrap();
+        assert!(matches!(
+            read_config_file(&path),
+            Err(AppError::ConfigParseError(_))
+        ));
+        assert!(matches!(
+            update_config_file(&path, |config| config.etag_mode = "weak".to_string()),
+            Err(AppError::ConfigParseError(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), broken);
+    }
+
+    #[test]
+    fn test_update_config_serializes_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        std::thread::scope(|scope| {
+            for i in 0..8u8 {
+                let path = &path;
+                scope.spawn(move || {
+                    update_config_file(path, |config| {
+                        config.folder_keys.insert(
+                            format!("folder{}", i),
+                            FolderKeyConfig {
+                                xor: i,
+                                aes: String::new(),
+                            },
+                        );
+                    })
+                    .unwrap();
+                });
+            }
+        });
+
+        let config = read_config_file(&path).unwrap().unwrap();
+        assert_eq!(config.folder_keys.len(), 8);
+    }
+
+    #[test]
+    fn test_optimized_webp_within_bound() {
+        let source = image::RgbImage::from_fn(200, 100, |x, y| {
+            image::Rgb([x as u8, y as u8, (x + y) as u8])
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        source.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        let webp = encode_optimized_webp(png.get_ref(), 64, 75).unwrap();
+        assert_eq!(&webp[0..4], b"RIFF");
+        assert_eq!(&webp[8..12], b"WEBP");
+        assert_eq!(sniff_image_mime(&webp), Some("image/webp"));
+
+        let decoded = image::load_from_memory(&webp).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 32));
+
+        assert!(encode_optimized_webp(b"not an image", 64, 75).is_err());
+    }
+
+    #[test]
+    fn test_thumbnail_jpeg_keeps_aspect_ratio() {
+        let png = encode_test_image(image::ImageFormat::Png, 300, 120);
+
+        let thumb = encode_thumbnail_jpeg(&png, 100).unwrap();
+        assert_eq!(sniff_image_mime(&thumb), Some("image/jpeg"));
+        let decoded = image::load_from_memory(&thumb).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 40));
+
+        // 小于 max_edge 的图片不放大
+        let small = image::load_from_memory(&encode_thumbnail_jpeg(&png, 1000).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (300, 120));
+
+        assert_eq!(thumbnail_cache_key("a/b.dat", 256), "a/b.dat#thumb256");
+        assert!(encode_thumbnail_jpeg(b"not an image", 100).is_err());
+    }
+
+    #[test]
+    fn test_resolve_export_bundle_in_document_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let export = root.join("export");
+        fs::create_dir_all(export.join("images")).unwrap();
+        write_file(&export.join("images"), "b.dat", &JPEG_HEADER);
+        write_file(&export.join("images"), "a.dat", &JPEG_HEADER);
+        write_file(
+            &export,
+            "chat.html",
+            br#"<html><body>
+                <img src="images/b.dat">
+                <img src="https://example.com/x.jpg">
+                <img src="images/missing.dat">
+                <img src="images/a.dat">
+                <img src="images/b.dat">
+            </body></html>"#,
+        );
+
+        let expected = vec![export.join("images/b.dat"), export.join("images/a.dat")];
+        assert_eq!(
+            resolve_bundle_images(&export.join("chat.html"), root).unwrap(),
+            expected
+        );
+        assert_eq!(resolve_bundle_images(&export, root).unwrap(), expected);
+
+        // 引用根目录之外的文件会被忽略
+        let other = tempfile::tempdir().unwrap();
+        assert!(resolve_bundle_images(&export, other.path())
+            .unwrap()
+            .is_empty());
+
+        // 通过 "../" 越出根目录的引用同样被忽略
+        let nested_root = export.join("images");
+        write_file(
+            &nested_root,
+            "escape.html",
+            br#"<img src="../chat.html"><img src="../../export/chat.html"><img src="a.dat">"#,
+        );
+        assert_eq!(
+            resolve_bundle_images(&nested_root.join("escape.html"), &nested_root).unwrap(),
+            vec![nested_root.join("a.dat")]
+        );
+    }
+
+    #[test]
+    fn test_check_decryptable_preconditions() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        // v3: 正确的 XOR 密钥才能得到有效魔数
+        let v3 = write_file(root, "a.dat", &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x33));
+        assert!(check_decryptable(&v3, XorKey::exact(0x33), None, false));
+        assert!(!check_decryptable(&v3, XorKey::exact(0x34), None, false));
+
+        // v4: 缺少 AES 密钥或密钥错误
+        let mut plain = JPEG_HEADER.to_vec();
+        plain.resize(64, 0);
+        let v4 = write_file(
+            root,
+            "b.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &plain, 32, 8, 0x33),
+        );
+        assert!(check_decryptable(
+            &v4,
+            XorKey::exact(0x33),
+            Some(TEST_AES_KEY),
+            false
+        ));
+        assert!(!check_decryptable(&v4, XorKey::exact(0x33), None, false));
+        assert!(!check_decryptable(
+            &v4,
+            XorKey::exact(0x33),
+            Some(b"fedcba9876543210"),
+            false
+        ));
+
+        // WXGF 需要 DLL
+        let wxgf = write_file(
+            root,
+            "c.dat",
+            &V3Decryptor::xor_decrypt(b"wxgf\x00\x01", 0x33),
+        );
+        assert!(check_decryptable(&wxgf, XorKey::exact(0x33), None, true));
+        assert!(!check_decryptable(&wxgf, XorKey::exact(0x33), None, false));
+
+        // 文件不存在
+        assert!(!check_decryptable(
+            &root.join("missing.dat"),
+            XorKey::exact(0x33),
+            None,
+            true
+        ));
+    }
+
+    fn encode_test_image(format: image::ImageFormat, width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 7) as u8, (y * 5) as u8, (x ^ y) as u8])
+        });
+        let mut out = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_carve_jpeg_from_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 16, 8);
+
+        // v4 签名但文件头声明的 AES 段远超文件长度，正常解密得不到图片；
+        // 后面跟着一张完整的 JPEG 和一张被截断的 JPEG
+        let mut plain = vec![0u8; 40];
+        plain.extend_from_slice(&jpeg);
+        plain.extend_from_slice(&jpeg[..jpeg.len() / 2]);
+        let mut content = decrypt::VersionDetector::V4_V1_SIGNATURE.to_vec();
+        content.extend_from_slice(&u32::MAX.to_le_bytes());
+        content.extend_from_slice(&V3Decryptor::xor_decrypt(&plain, 0x56));
+        let path = write_file(dir.path(), "broken.dat", &content);
+
+        let output_dir = dir.path().join("carved");
+        fs::create_dir(&output_dir).unwrap();
+        let result = carve_file(
+            &path,
+            "broken.dat".to_string(),
+            XorKey::exact(0x56),
+            Some(b"0123456789abcdef"),
+            &output_dir,
+        );
+        assert_eq!(result.method, "xor");
+        assert_eq!(result.images.len(), 1);
+        assert_eq!(result.images[0].mime_type, "image/jpeg");
+        assert_eq!(result.images[0].size, jpeg.len());
+
+        let offset = result.images[0].offset;
+        assert_eq!(
+            PathBuf::from(&result.images[0].path),
+            output_dir.join(format!("broken_{}.jpg", offset))
+        );
+        let carved = fs::read(&result.images[0].path).unwrap();
+        assert_eq!(carved, jpeg);
+        assert!(image::load_from_memory(&carved).is_ok());
+    }
+
+    #[test]
+    fn test_export_png_and_jpeg_from_one_decode() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = encode_test_image(image::ImageFormat::Png, 12, 10);
+
+        let formats = vec!["png".to_string(), "JPEG".to_string(), "jpg".to_string()];
+        let paths = export_formats(&source, "a", dir.path(), &formats, None).unwrap();
+        assert_eq!(
+            paths,
+            vec![dir.path().join("a.png"), dir.path().join("a.jpg")]
+        );
+
+        let png = image::open(&paths[0]).unwrap();
+        let jpeg = image::open(&paths[1]).unwrap();
+        assert_eq!((png.width(), png.height()), (12, 10));
+        assert_eq!((jpeg.width(), jpeg.height()), (12, 10));
+        assert_eq!(
+            image::guess_format(&fs::read(&paths[1]).unwrap()).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+
+        let bad = vec!["tiff".to_string()];
+        assert!(export_formats(&source, "a", dir.path(), &bad, None).is_err());
+        assert!(export_formats(&source, "a", dir.path(), &[], None).is_err());
+        assert!(
+            export_formats(b"\x00\x00\x00\x18ftypmp42", "a", dir.path(), &formats, None).is_err()
+        );
+    }
+
+    #[test]
+    fn test_recognized_patterns_match_classifier() {
+        let patterns = build_recognized_patterns();
+        assert_eq!(patterns.extensions, vec![".dat"]);
+        assert_eq!(patterns.thumbnail_suffixes, vec!["_t"]);
+
+        for len in &patterns.sns_filename_lengths {
+            let name = "a".repeat(*len);
+            assert!(is_valid_sns_filename(&name));
+            assert!(is_valid_sns_filename(&format!("{}_t", name)));
+            assert!(!is_valid_sns_filename(&"a".repeat(len + 1)));
+            // 只去掉一个缩略图后缀
+            assert!(!is_valid_sns_filename(&format!("{}_t_t", name)));
+        }
+    }
+
+    #[test]
+    fn test_build_and_reload_content_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let index_path = dir.path().join("index.json");
+
+        let png = encode_test_image(image::ImageFormat::Png, 6, 4);
+        write_file(&root, "a.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(&root, "b.dat", &[]);
+
+        let jobs_for = |images: Vec<ImageInfo>| -> Vec<IndexJob> {
+            images
+                .into_iter()
+                .map(|image| IndexJob {
+                    path: root.join(&image.path),
+                    rel_path: image.path,
+                    mtime: image.modified,
+                    xor_key: XorKey::exact(0x56),
+                    aes_key: None,
+                })
+                .collect()
+        };
+
+        let mut events = Vec::new();
+        let jobs = jobs_for(collect_images(&root, &root, false, false));
+        let summary = update_content_index(
+            &index_path,
+            &root,
+            &jobs,
+            INDEX_SAVE_INTERVAL,
+            &AtomicBool::new(false),
+            &mut |e| events.push(e.processed),
+        )
+        .unwrap();
+        assert_eq!((summary.total, summary.indexed, summary.skipped), (2, 2, 0));
+        assert_eq!(events, vec![1, 2]);
+
+        let index = ContentIndex::load(&index_path, &root);
+        let entry = index.entries.get("a.dat").unwrap();
+        assert_eq!(entry.mime.as_deref(), Some("image/png"));
+        assert_eq!(entry.dims, Some((6, 4)));
+        assert_eq!(entry.hash.len(), 64);
+
+        // 第二次运行跳过未修改的文件，只重新索引修改过的文件
+        set_mtime(&root.join("a.dat"), 1_000);
+        let jobs = jobs_for(collect_images(&root, &root, false, false));
+        let summary = update_content_index(
+            &index_path,
+            &root,
+            &jobs,
+            INDEX_SAVE_INTERVAL,
+            &AtomicBool::new(false),
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!((summary.indexed, summary.skipped), (1, 1));
+        assert_eq!(
+            ContentIndex::load(&index_path, &root).entries["a.dat"].mtime,
+            1_000
+        );
+    }
+
+    #[test]
+    fn test_cancelled_index_resumes_remaining_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let index_path = dir.path().join("index.json");
+
+        let png = encode_test_image(image::ImageFormat::Png, 2, 2);
+        for name in ["a.dat", "b.dat", "c.dat", "d.dat", "e.dat"] {
+            write_file(&root, name, &V3Decryptor::xor_decrypt(&png, 0x56));
+        }
+        let mut images = collect_images(&root, &root, false, false);
+        sort_images(&mut images, "name", "asc");
+        let jobs: Vec<IndexJob> = images
+            .into_iter()
+            .map(|image| IndexJob {
+                path: root.join(&image.path),
+                rel_path: image.path,
+                mtime: image.modified,
+                xor_key: XorKey::exact(0x56),
+                aes_key: None,
+            })
+            .collect();
+
+        // 处理完两个文件后取消
+        let cancel = AtomicBool::new(false);
+        let summary = update_content_index(&index_path, &root, &jobs, 1, &cancel, &mut |e| {
+            if e.processed == 2 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+        assert!(summary.cancelled);
+        assert_eq!(summary.indexed, 2);
+        assert_eq!(ContentIndex::load(&index_path, &root).entries.len(), 2);
+
+        // 继续时跳过已保存的文件，只处理剩余的文件
+        let summary = update_content_index(
+            &index_path,
+            &root,
+            &jobs,
+            1,
+            &AtomicBool::new(false),
+            &mut |_| {},
+        )
+        .unwrap();
+        assert!(!summary.cancelled);
+        assert_eq!((summary.indexed, summary.skipped), (3, 2));
+        assert_eq!(ContentIndex::load(&index_path, &root).entries.len(), 5);
+    }
+
+    #[test]
+    fn test_animation_frames_from_gif() {
+        use image::codecs::gif::{GifEncoder, Repeat};
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            encoder.set_repeat(Repeat::Finite(3)).unwrap();
+            for (shade, delay) in [(0u8, 100u32), (128, 250), (255, 40)] {
+                let buffer = image::RgbaImage::from_pixel(4, 4, image::Rgba([shade, 0, 0, 255]));
+                let frame = image::Frame::from_parts(
+                    buffer,
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(delay, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+
+        let animation = decode_animation(&gif).unwrap();
+        assert_eq!(animation.loop_count, 3);
+        let delays: Vec<u32> = animation.frames.iter().map(|f| f.delay_ms).collect();
+        assert_eq!(delays, vec![100, 250, 40]);
+        assert!(animation.frames[0]
+            .data_url
+            .starts_with("data:image/png;base64,"));
+
+        let still = decode_animation(&encode_test_image(image::ImageFormat::Png, 4, 4)).unwrap();
+        assert_eq!(still.frames.len(), 1);
+        assert_eq!(still.frames[0].delay_ms, 0);
+
+        assert!(decode_animation(b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_split_two_concatenated_jpegs() {
+        let first = encode_test_image(image::ImageFormat::Jpeg, 16, 8);
+        let second = encode_test_image(image::ImageFormat::Jpeg, 8, 16);
+        let mut combined = first.clone();
+        combined.extend_from_slice(&second);
+
+        let parts = split_concatenated_images(&combined);
+        assert_eq!(parts, vec![first.clone(), second]);
+
+        // 单张图片返回一个元素
+        assert_eq!(split_concatenated_images(&first), vec![first]);
+    }
+
+    #[test]
+    fn test_split_mixed_png_and_jpeg_with_padding() {
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 4, 4);
+        let mut combined = png.clone();
+        combined.extend_from_slice(&[0u8; 5]);
+        combined.extend_from_slice(&jpeg);
+
+        assert_eq!(split_concatenated_images(&combined), vec![png, jpeg]);
+    }
+
+    #[test]
+    fn test_pick_evenly_spaced() {
+        let items: Vec<usize> = (0..10).collect();
+        assert_eq!(pick_evenly_spaced(&items, 5), vec![0, 2, 4, 6, 8]);
+        assert_eq!(pick_evenly_spaced(&items, 3), vec![0, 3, 6]);
+        assert_eq!(pick_evenly_spaced(&items, 20), items);
+        assert!(pick_evenly_spaced(&items, 0).is_empty());
+    }
+
+    #[test]
+    fn test_filmstrip_dimensions() {
+        let wide = encode_test_image(image::ImageFormat::Png, 120, 40);
+        let tall = encode_test_image(image::ImageFormat::Jpeg, 30, 90);
+        let frames = vec![Some(wide), None, Some(b"not an image".to_vec()), Some(tall)];
+
+        let strip = compose_filmstrip(&frames, 32).unwrap();
+        let decoded = image::load_from_memory(&strip).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4 * 32, 32));
+
+        assert!(compose_filmstrip(&[], 32).is_err());
+
+        // 超出上限的尺寸直接拒绝，不会分配巨大的画布
+        assert!(compose_filmstrip(&[None], u32::MAX).is_err());
+        assert!(compose_filmstrip(&vec![None; FILMSTRIP_MAX_COUNT + 1], 32).is_err());
+    }
+
+    #[test]
+    fn test_detect_folder_xor_consensus() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for i in 0..3 {
+            write_file(
+                root,
+                &format!("{}.dat", i),
+                &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x5A),
+            );
+        }
+        write_file(
+            root,
+            "png.dat",
+            &V3Decryptor::xor_decrypt(&PNG_HEADER, 0x5A),
+        );
+        write_file(
+            root,
+            "other.dat",
+            &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x11),
+        );
+
+        let guess = detect_folder_xor(root, root);
+        assert_eq!(guess.xor_key, Some(0x5A));
+        assert_eq!(guess.sampled, 5);
+        assert!((guess.confidence - 0.8).abs() < 1e-9);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(
+            detect_folder_xor(empty.path(), empty.path()),
+            FolderXorGuess {
+                xor_key: None,
+                confidence: 0.0,
+                sampled: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_dll_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(parse_dll_dir("").unwrap(), None);
+        assert_eq!(parse_dll_dir("  ").unwrap(), None);
+        assert_eq!(
+            parse_dll_dir(&dir.path().display().to_string()).unwrap(),
+            Some(dir.path().to_path_buf())
+        );
+        assert!(matches!(
+            parse_dll_dir(&dir.path().join("missing").display().to_string()),
+            Err(AppError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_dll_status_not_found() {
+        let path = Path::new("C:/app/VoipEngine.dll");
+        let status = build_dll_status(Some(path), false, None);
+        assert!(status.supported);
+        assert!(!status.found);
+        assert_eq!(status.loaded, None);
+        assert!(status.message.contains(&path.display().to_string()));
+
+        let status = build_dll_status(
+            Some(path),
+            false,
+            Some(Err(AppError::DllNotFound(path.display().to_string()))),
+        );
+        assert_eq!(status.loaded, Some(false));
+        assert!(status.error.is_some());
+        assert!(status.message.contains("找到"));
+
+        let status = build_dll_status(Some(path), true, Some(Ok("wxam_dec_wxam2pic_5")));
+        assert_eq!(status.symbol.as_deref(), Some("wxam_dec_wxam2pic_5"));
+
+        assert!(!build_dll_status(None, false, None).supported);
+    }
+
+    #[test]
+    fn test_trial_decrypt_correct_vs_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "a.dat",
+            &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x21),
+        );
+
+        let correct = trial_decrypt(
+            &path,
+            &KeySet {
+                xor: 0x21,
+                aes: String::new(),
+            },
+        );
+        assert!(correct.valid);
+        assert_eq!(correct.head_hex, "ff d8 ff e0 00 10 4a 46");
+
+        let wrong = trial_decrypt(
+            &path,
+            &KeySet {
+                xor: 0x22,
+                aes: String::new(),
+            },
+        );
+        assert!(!wrong.valid);
+        assert!(wrong.error.is_none());
+    }
+
+    #[test]
+    fn test_validate_keys_with_sample() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let v3 = write_file(
+            dir.path(),
+            "a.dat",
+            &V3Decryptor::xor_decrypt(&PNG_HEADER, 0x21),
+        );
+
+        let result = validate_keys_with(&v3, 0x21, "");
+        assert!(result.valid);
+        assert_eq!(result.detected_version, "v3");
+        assert_eq!(result.detected_mime.as_deref(), Some("image/png"));
+
+        let result = validate_keys_with(&v3, 0x22, "");
+        assert!(!result.valid);
+        assert_eq!(result.detected_mime, None);
+        assert!(result.error.is_none());
+
+        let mut plain = JPEG_HEADER.to_vec();
+        plain.extend(0..64u8);
+        let v4 = write_file(
+            dir.path(),
+            "b.dat",
+            &build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &plain, 32, 8, 0x21),
+        );
+        let aes = std::str::from_utf8(TEST_AES_KEY).unwrap();
+
+        let result = validate_keys_with(&v4, 0x21, aes);
+        assert!(result.valid);
+        assert_eq!(result.detected_version, "v4v1");
+        assert_eq!(result.detected_mime.as_deref(), Some("image/jpeg"));
+
+        let result = validate_keys_with(&v4, 0x21, "");
+        assert!(!result.valid);
+        assert!(result.error.is_some());
+
+        let result = validate_keys_with(&dir.path().join("missing.dat"), 0x21, "");
+        assert!(!result.valid);
+        assert_eq!(result.detected_version, "unknown");
+        assert!(result.error.is_some());
+    }
+
+    #[test]
+    fn test_entropy_separates_correct_and_wrong_aes_key() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 128, 128);
+        let path = write_file(
+            dir.path(),
+            "a.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &jpeg, 1024, 64, 0x21),
+        );
+
+        let correct = trial_decrypt(
+            &path,
+            &KeySet {
+                xor: 0x21,
+                aes: String::from_utf8(TEST_AES_KEY.to_vec()).unwrap(),
+            },
+        );
+        // 错误的密钥通常因为填充无效而直接解密失败，这里找一个填充碰巧有效的错误密钥
+        let wrong = (0..4096)
+            .map(|i| {
+                trial_decrypt(
+                    &path,
+                    &KeySet {
+                        xor: 0x21,
+                        aes: format!("fedcba987654{:04x}", i),
+                    },
+                )
+            })
+            .find(|trial| trial.error.is_none())
+            .unwrap();
+
+        let correct_entropy = correct.entropy.unwrap();
+        let wrong_entropy = wrong.entropy.unwrap();
+        assert!(correct_entropy < WRONG_KEY_ENTROPY, "{}", correct_entropy);
+        assert!(wrong_entropy >= WRONG_KEY_ENTROPY, "{}", wrong_entropy);
+        assert!(correct.valid && !correct.likely_wrong_key);
+        assert!(wrong.likely_wrong_key);
+
+        assert_eq!(shannon_entropy(&[7; 100]), 0.0);
+        let uniform: Vec<u8> = (0..=255).collect();
+        assert!((shannon_entropy(&uniform) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_favorites_toggle_and_round_trip() {
+        let mut config = Config::default();
+        assert!(toggle_favorite_in(&mut config, "/root/a", "x.dat"));
+        assert!(toggle_favorite_in(&mut config, "/root/a", "y.dat"));
+        assert!(toggle_favorite_in(&mut config, "/root/b", "x.dat"));
+        assert!(!toggle_favorite_in(&mut config, "/root/a", "x.dat"));
+
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.favorites["/root/a"], vec!["y.dat".to_string()]);
+        assert_eq!(restored.favorites["/root/b"], vec!["x.dat".to_string()]);
+
+        // 旧版配置文件没有 favorites 字段
+        let legacy: Config = serde_json::from_str(r#"{"xor":1,"aes":""}"#).unwrap();
+        assert!(legacy.favorites.is_empty());
+
+        // 最后一个收藏被移除后不保留空列表
+        let mut config = restored;
+        assert!(!toggle_favorite_in(&mut config, "/root/b", "x.dat"));
+        assert!(!config.favorites.contains_key("/root/b"));
+    }
+
+    #[test]
+    fn test_toggle_favorite_keeps_keys_and_broken_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, r#"{"xor":86,"aes":"0123456789abcdef"}"#).unwrap();
+
+        let toggle = |image_id: &str| {
+            update_config_file(&path, |config| {
+                toggle_favorite_in(config, "/root/a", image_id)
+            })
+        };
+        assert!(toggle("x.dat").unwrap());
+
+        let config = read_config_file(&path).unwrap().unwrap();
+        assert_eq!(config.xor, 86);
+        assert_eq!(config.aes, "0123456789abcdef");
+        assert_eq!(config.favorites["/root/a"], vec!["x.dat".to_string()]);
+
+        // 配置文件格式错误时收藏失败，原文件保持不变
+        let broken = br#"{"xor":86,"aes":"0123456789abcdef","#;
+        fs::write(&path, broken).unwrap();
+        assert!(matches!(
+            toggle("y.dat"),
+            Err(AppError::ConfigParseError(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), broken);
+    }
+
+    #[test]
+    fn test_video_frame_cache_key_rounds_timestamp() {
+        assert_eq!(
+            video_frame_cache_key("v.dat", 1.04),
+            video_frame_cache_key("v.dat", 0.96)
+        );
+        assert_ne!(
+            video_frame_cache_key("v.dat", 1.0),
+            video_frame_cache_key("v.dat", 1.1)
+        );
+        assert_eq!(video_frame_cache_key("v.dat", -3.0), "v.dat#frame-0");
+    }
+
+    #[test]
+    fn test_app_info_version() {
+        let info = app_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.platform.starts_with(std::env::consts::OS));
+        assert_eq!(
+            info.features.contains(&"ffmpeg".to_string()),
+            cfg!(feature = "ffmpeg")
+        );
+    }
+
+    #[test]
+    fn test_smart_prefetch_scroll() {
+        let ordered: Vec<String> = (0..20).map(|i| format!("{}.dat", i)).collect();
+        let ids = |range: std::ops::Range<usize>| -> Vec<String> { ordered[range].to_vec() };
+        let mut cached: std::collections::HashSet<String> = std::collections::HashSet::new();
+
+        // 初始位置：可见 0..4，预取 4..7
+        let (fetch, evict) = plan_smart_prefetch(&ordered, &ids(0..4), 3, |id| cached.contains(id));
+        assert_eq!(fetch, ids(4..7));
+        assert!(evict.is_empty());
+        cached.extend(ids(0..7));
+
+        // 向下滚动到 10..14：预取 14..17，淘汰 7 之前的图片
+        let (fetch, evict) =
+            plan_smart_prefetch(&ordered, &ids(10..14), 3, |id| cached.contains(id));
+        assert_eq!(fetch, ids(14..17));
+        assert_eq!(evict, ids(0..7));
+
+        // 已缓存的图片不会重复预取
+        cached.extend(ids(14..16));
+        let (fetch, _) = plan_smart_prefetch(&ordered, &ids(10..14), 3, |id| cached.contains(id));
+        assert_eq!(fetch, ids(16..17));
+
+        // 可见图片不在列表中
+        let (fetch, evict) = plan_smart_prefetch(&ordered, &["x.dat".to_string()], 3, |id| {
+            cached.contains(id)
+        });
+        assert!(fetch.is_empty() && evict.is_empty());
+    }
+
+    #[test]
+    fn test_quality_sharp_beats_blurred() {
+        let sharp = image::GrayImage::from_fn(64, 64, |x, y| {
+            image::Luma([if (x / 4 + y / 4) % 2 == 0 { 255 } else { 0 }])
+        });
+        let blurred = image::imageops::blur(&sharp, 3.0);
+
+        let encode = |img: &image::GrayImage| {
+            let mut out = std::io::Cursor::new(Vec::new());
+            img.write_to(&mut out, image::ImageFormat::Png).unwrap();
+            out.into_inner()
+        };
+
+        let sharp_score = compute_quality(&encode(&sharp)).unwrap();
+        let blurred_score = compute_quality(&encode(&blurred)).unwrap();
+        assert!(sharp_score.sharpness > blurred_score.sharpness);
+        assert!((sharp_score.brightness - 0.5).abs() < 0.01);
+
+        // 两个内部像素的响应为 -40 和 10，方差为 625
+        let mut dot = image::GrayImage::new(4, 3);
+        dot.put_pixel(1, 1, image::Luma([10]));
+        assert!((compute_quality(&encode(&dot)).unwrap().sharpness - 625.0).abs() < 1e-9);
+
+        assert!(compute_quality(b"not an image").is_err());
+    }
+
+    // 把数据写入会话目录并登记为解密会话
+    fn session_with_data(state: &AppState, data: &[u8]) -> String {
+        let path = state
+            .decrypt_sessions
+            .lock()
+            .unwrap()
+            .new_file_path()
+            .unwrap();
+        fs::write(&path, data).unwrap();
+        insert_decrypt_session(state, path)
+    }
+
+    #[test]
+    fn test_decrypt_session_sequential_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::default();
+        state.decrypt_sessions.lock().unwrap().dir = dir.path().to_path_buf();
+        let data: Vec<u8> = (0..=255u8).collect();
+        let session_id = session_with_data(&state, &data);
+
+        let mut reassembled = Vec::new();
+        let mut offset = 0u64;
+        loop {
+            let chunk = read_session_chunk(&state, &session_id, offset, 100).unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            offset += chunk.len() as u64;
+            reassembled.extend(chunk);
+        }
+        assert_eq!(reassembled, data);
+
+        assert!(read_session_chunk(&state, &session_id, u64::MAX, 10)
+            .unwrap()
+            .is_empty());
+
+        assert!(state.decrypt_sessions.lock().unwrap().remove(&session_id));
+        assert!(matches!(
+            read_session_chunk(&state, &session_id, 0, 10),
+            Err(AppError::SessionNotFound(_))
+        ));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_decrypt_session_reads_from_file_without_caching() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 8, 8);
+        write_file(&root, "a.dat", &V3Decryptor::xor_decrypt(&png, 0x37));
+
+        let state = AppState::default();
+        *state.root_dir.lock().unwrap() = Some(root.clone());
+        *state.xor_key.lock().unwrap() = 0x37;
+        state.decrypt_sessions.lock().unwrap().dir = dir.path().join("sessions");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let path = runtime
+            .block_on(decrypt_to_session_file(&state, "a.dat"))
+            .unwrap();
+        let session_id = insert_decrypt_session(&state, path.clone());
+
+        assert_eq!(
+            read_session_chunk(&state, &session_id, 0, usize::MAX).unwrap(),
+            png
+        );
+        assert!(state.image_cache.lock().unwrap().is_empty());
+
+        // 释放状态时删除会话文件和目录
+        drop(state);
+        assert!(!path.exists());
+        assert!(!dir.path().join("sessions").exists());
+    }
+
+    #[test]
+    fn test_decrypt_session_ids_are_unique() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::default();
+        state.decrypt_sessions.lock().unwrap().dir = dir.path().to_path_buf();
+        let a = session_with_data(&state, &[1]);
+        let b = session_with_data(&state, &[2]);
+        assert_ne!(a, b);
+        assert_eq!(read_session_chunk(&state, &b, 0, 1).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_contains_wechat_process() {
+        assert!(contains_wechat_process(["explorer.exe", "WeChat.exe"]));
+        assert!(contains_wechat_process(["weixin.exe"]));
+        assert!(!contains_wechat_process([
+            "explorer.exe",
+            "WeChatAppEx.exe"
+        ]));
+        assert!(!contains_wechat_process(Vec::<String>::new()));
+    }
+
+    #[test]
+    fn test_image_protocol_request() {
+        assert_eq!(
+            image_id_from_uri_path("/sub%2Fa%20b.dat").as_deref(),
+            Some("sub/a b.dat")
+        );
+        assert_eq!(image_id_from_uri_path("/a.dat").as_deref(), Some("a.dat"));
+        assert_eq!(image_id_from_uri_path("/"), None);
+        assert_eq!(image_id_from_uri_path("/a%2"), None);
+
+        let image = CachedImage {
+            data: vec![1, 2, 3],
+            mime_type: "image/png".to_string(),
+        };
+        let response = image_protocol_response(Ok(image));
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/png");
+        assert_eq!(response.body(), &vec![1, 2, 3]);
+
+        let response = image_protocol_response(Err("missing".to_string()));
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn test_conditional_image_response() {
+        let image = CachedImage {
+            data: JPEG_HEADER.to_vec(),
+            mime_type: "image/jpeg".to_string(),
+        };
+        let etag = content_etag(&image.data);
+
+        match conditional_image_response(image.clone(), etag.clone(), None) {
+            ImageResponse::Ok {
+                data, etag: tag, ..
+            } => {
+                assert_eq!(data, JPEG_HEADER);
+                assert_eq!(tag, etag);
+            }
+            ImageResponse::NotModified { .. } => panic!("无条件请求应返回数据"),
+        }
+
+        assert!(matches!(
+            conditional_image_response(image.clone(), etag.clone(), Some(&etag)),
+            ImageResponse::NotModified { .. }
+        ));
+        assert!(matches!(
+            conditional_image_response(image, etag, Some("\"stale\"")),
+            ImageResponse::Ok { .. }
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_explicit_reports_all_fields() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 12, 7);
+        let aes = std::str::from_utf8(TEST_AES_KEY).unwrap();
+
+        let v3 = write_file(dir.path(), "v3.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        let result = decrypt_explicit_file(&v3, 0x56, "").unwrap();
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(&result.data)
+            .unwrap();
+        assert_eq!(data, png);
+        assert_eq!(result.mime_type, "image/png");
+        assert_eq!((result.width, result.height), (Some(12), Some(7)));
+        assert_eq!(result.version, "v3");
+        assert_eq!(result.kind, "image");
+
+        let v4 = write_file(
+            dir.path(),
+            "v4.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &png, 32, 8, 0x56),
+        );
+        let result = decrypt_explicit_file(&v4, 0x56, aes).unwrap();
+        assert_eq!(result.version, "v4v2");
+        assert_eq!((result.width, result.height), (Some(12), Some(7)));
+        assert_eq!(result.kind, "image");
+
+        // v4 文件缺少 AES 密钥时报错
+        assert!(decrypt_explicit_file(&v4, 0x56, "").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_to_file_fixes_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        let source = write_file(dir.path(), "a.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+
+        let written =
+            api::decrypt_to_file(&source, 0x56, None, &dir.path().join("out.jpg")).unwrap();
+        assert_eq!(written, dir.path().join("out.png"));
+        assert_eq!(fs::read(&written).unwrap(), png);
+
+        let missing_dir = dir.path().join("missing").join("out");
+        assert!(matches!(
+            api::decrypt_to_file(&source, 0x56, None, &missing_dir),
+            Err(AppError::FileWriteError(_))
+        ));
+
+        // v4 文件边解密边写入；密钥错误时已有文件保持不变
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+        let mut plain = JPEG_HEADER.to_vec();
+        plain.resize(4096, 0x42);
+        let v4 = write_file(
+            dir.path(),
+            "b.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &plain, 1024, 512, 0x56),
+        );
+        let written =
+            api::decrypt_to_file(&v4, 0x56, Some(TEST_AES_KEY), &dir.path().join("b")).unwrap();
+        assert_eq!(written, dir.path().join("b.jpg"));
+        assert_eq!(fs::read(&written).unwrap(), plain);
+
+        fs::write(&written, b"existing").unwrap();
+        assert!(
+            api::decrypt_to_file(&v4, 0x56, Some(b"fedcba9876543210"), &dir.path().join("b"))
+                .is_err()
+        );
+        assert_eq!(fs::read(&written).unwrap(), b"existing");
+    }
+
+    #[test]
+    fn test_write_file_atomically_keeps_existing_file_on_failure() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = write_file(dir.path(), "photo.jpg", b"existing");
+        let mut plain = JPEG_HEADER.to_vec();
+        plain.resize(64, 0x42);
+
+        // 密钥错误导致解密失败时，已有文件保持不变，也不留下临时文件
+        let source = write_file(
+            dir.path(),
+            "a.dat",
+            &build_v4_fixture(VersionDetector::V4_V2_SIGNATURE, &plain, 32, 16, 0x56),
+        );
+        let result = write_file_atomically(&output, |file| {
+            DatDecryptor::decrypt_to_writer(&source, 0x56, Some(b"fedcba9876543210"), file, 1024)
+                .map_err(AppError::from)
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&output).unwrap(), b"existing");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let written = write_file_atomically(&output, |file| {
+            DatDecryptor::decrypt_to_writer(&source, 0x56, Some(TEST_AES_KEY), file, 1024)
+                .map_err(AppError::from)
+        })
+        .unwrap();
+        assert_eq!(written, plain.len() as u64);
+        assert_eq!(fs::read(&output).unwrap(), plain);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    // 导出选项：不隐藏缩略图，两张并发，不限速
+    fn export_options() -> ExportOptions {
+        ExportOptions {
+            hide_thumbnails: false,
+            concurrency: 2,
+            max_write_bytes_per_sec: None,
+            write_manifest: false,
+            filename_template: DEFAULT_EXPORT_TEMPLATE.to_string(),
+            overwrite: false,
+        }
+    }
+
+    #[test]
+    fn test_export_folder_reports_mixed_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        let encrypted = V3Decryptor::xor_decrypt(&png, 0x56);
+        write_file(dir.path(), "abc.dat", &encrypted);
+        write_file(dir.path(), "abc_h.dat", &encrypted);
+        write_file(dir.path(), "def.dat", &encrypted);
+
+        // 缺少 AES 密钥的 v4 文件导出失败
+        let mut v4 = crate::decrypt::VersionDetector::V4_V2_SIGNATURE.to_vec();
+        v4.resize(32, 0);
+        write_file(dir.path(), "bad.dat", &v4);
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        let options = ExportOptions {
+            max_write_bytes_per_sec: Some(1 << 30),
+            ..export_options()
+        };
+
+        let mut events = Vec::new();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let summary = runtime
+            .block_on(export_folder_files(
+                &state,
+                dir.path(),
+                &dir.path().to_string_lossy(),
+                out.path(),
+                &options,
+                &AtomicBool::new(false),
+                &mut |event| events.push(event),
+            ))
+            .unwrap();
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.skipped_duplicates, 1);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.bytes_written, 2 * png.len() as u64);
+        assert!(!summary.cancelled);
+        assert_eq!(summary.errors.len(), 1);
+        assert_eq!(summary.errors[0].path, "bad.dat");
+        assert!(summary.errors[0].reason.contains("AES"));
+        assert_eq!(fs::read(out.path().join("abc.png")).unwrap(), png);
+        assert_eq!(fs::read(out.path().join("def.png")).unwrap(), png);
+
+        // 每张图片一个进度事件，失败的事件带原因
+        let processed: Vec<usize> = events.iter().map(|e| e.processed).collect();
+        assert_eq!(processed, vec![1, 2, 3]);
+        assert!(events.iter().all(|e| e.total == 3));
+        let failed: Vec<&str> = events
+            .iter()
+            .filter(|e| e.error.is_some())
+            .map(|e| e.path.as_str())
+            .collect();
+        assert_eq!(failed, vec!["bad.dat"]);
+    }
+
+    #[test]
+    fn test_export_manifest_matches_exported_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 6, 4);
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 8, 8);
+        write_file(dir.path(), "abc.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(
+            dir.path(),
+            "def.dat",
+            &V3Decryptor::xor_decrypt(&jpeg, 0x56),
+        );
+        let mut v4 = crate::decrypt::VersionDetector::V4_V2_SIGNATURE.to_vec();
+        v4.resize(32, 0);
+        write_file(dir.path(), "bad.dat", &v4);
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        let options = ExportOptions {
+            write_manifest: true,
+            ..export_options()
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let summary = runtime
+            .block_on(export_folder_files(
+                &state,
+                dir.path(),
+                &dir.path().to_string_lossy(),
+                out.path(),
+                &options,
+                &AtomicBool::new(false),
+                &mut |_| {},
+            ))
+            .unwrap();
+
+        let manifest_path = out.path().join(EXPORT_MANIFEST);
+        assert_eq!(
+            summary.manifest_path.as_deref(),
+            Some(manifest_path.to_string_lossy().as_ref())
+        );
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        let entries = manifest.as_array().unwrap();
+        let sources: Vec<&str> = entries
+            .iter()
+            .map(|e| e["source"].as_str().unwrap())
+            .collect();
+        assert_eq!(sources, vec!["abc.dat", "bad.dat", "def.dat"]);
+
+        // 成功的条目与导出的文件一致
+        for (entry, mime, dims) in [
+            (&entries[0], "image/png", (6, 4)),
+            (&entries[2], "image/jpeg", (8, 8)),
+        ] {
+            let output = out.path().join(entry["output"].as_str().unwrap());
+            assert_eq!(entry["size"], fs::metadata(&output).unwrap().len());
+            assert_eq!(entry["mime_type"], mime);
+            assert_eq!(entry["version"], "v3");
+            assert_eq!(
+                (entry["width"].as_u64(), entry["height"].as_u64()),
+                (Some(dims.0), Some(dims.1))
+            );
+            assert!(entry["error"].is_null());
+        }
+        assert_eq!(entries[0]["hash"], "abc");
+        assert_eq!(entries[0]["output"], "abc.png");
+
+        // 失败的条目带原因，没有输出文件
+        assert!(entries[1]["output"].is_null());
+        assert_eq!(entries[1]["error"], summary.errors[0].reason.as_str());
+    }
+
+    #[test]
+    fn test_render_export_name_templates() {
+        // 2024-03-01 08:00:00 UTC
+        let fields = ExportNameFields {
+            hash: "abc123",
+            modified: 1_709_280_000,
+            index: 7,
+            ext: "jpg",
+            folder: "2024-03",
+        };
+        let render = |template: &str| render_export_name(template, &fields);
+
+        assert_eq!(render(DEFAULT_EXPORT_TEMPLATE), "abc123.jpg");
+        assert_eq!(render("{date}_{index}.{ext}"), "2024-03-01_0007.jpg");
+        assert_eq!(render("{folder}/{hash}.{ext}"), "2024-03_abc123.jpg");
+        // 未知字段和未闭合的括号原样保留
+        assert_eq!(render("{name}_{hash"), "{name}_{hash");
+        // 不能用于文件名的字符和结尾的点被替换或去掉，结果为空时使用默认模板
+        assert_eq!(render("a:b?<{index}>.."), "a_b__0007_");
+        assert_eq!(render(".."), "abc123.jpg");
+        assert_eq!(render(""), "abc123.jpg");
+        assert_eq!(unix_to_date(951_782_400), (2000, 2, 29));
+
+        let mut taken = HashSet::new();
+        let claim =
+            |name: &str, taken: &mut HashSet<String>| claim_export_name(name.to_string(), taken);
+        assert_eq!(claim("a.jpg", &mut taken), "a.jpg");
+        assert_eq!(claim("A.JPG", &mut taken), "A_1.JPG");
+        assert_eq!(claim("a.jpg", &mut taken), "a_2.jpg");
+        assert_eq!(claim("a_1.jpg", &mut taken), "a_1_1.jpg");
+        assert_eq!(claim("noext", &mut taken), "noext");
+        assert_eq!(claim("noext", &mut taken), "noext_1");
+    }
+
+    #[test]
+    fn test_export_folder_applies_filename_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 4, 4);
+        write_file(dir.path(), "abc.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(dir.path(), "def.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(
+            dir.path(),
+            "ghi.dat",
+            &V3Decryptor::xor_decrypt(&jpeg, 0x56),
+        );
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let export = |template: &str| {
+            let options = ExportOptions {
+                filename_template: template.to_string(),
+                ..export_options()
+            };
+            runtime
+                .block_on(export_folder_files(
+                    &state,
+                    dir.path(),
+                    &dir.path().to_string_lossy(),
+                    out.path(),
+                    &options,
+                    &AtomicBool::new(false),
+                    &mut |_| {},
+                ))
+                .unwrap()
+        };
+        let names = || {
+            let mut names: Vec<String> = fs::read_dir(out.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // 同名的两张 PNG 按完成顺序追加计数，JPEG 扩展名不同不冲突
+        assert_eq!(export("photo.{ext}").succeeded, 3);
+        assert_eq!(names(), vec!["photo.jpg", "photo.png", "photo_1.png"]);
+        let mut exported: Vec<Vec<u8>> = ["photo.png", "photo_1.png"]
+            .iter()
+            .map(|name| fs::read(out.path().join(name)).unwrap())
+            .collect();
+        exported.dedup();
+        assert_eq!(exported, vec![png]);
+
+        for entry in fs::read_dir(out.path()).unwrap() {
+            fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+        export("{index}_{hash}.{ext}");
+        assert_eq!(names().len(), 3);
+        assert!(names().iter().all(|name| name.starts_with("000")));
+    }
+
+    #[test]
+    fn test_export_folder_resumes_without_overwriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        write_file(dir.path(), "abc.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+        write_file(dir.path(), "def.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let export = |options: ExportOptions| {
+            runtime
+                .block_on(export_folder_files(
+                    &state,
+                    dir.path(),
+                    &dir.path().to_string_lossy(),
+                    out.path(),
+                    &options,
+                    &AtomicBool::new(false),
+                    &mut |_| {},
+                ))
+                .unwrap()
+        };
+
+        let first = export(export_options());
+        assert_eq!((first.succeeded, first.skipped_existing), (2, 0));
+
+        // 没有清单时解密后比较：大小相同的跳过，不同的写到新文件名，不覆盖已有文件
+        fs::write(out.path().join("def.png"), b"stale").unwrap();
+        let second = export(export_options());
+        assert_eq!((second.succeeded, second.skipped_existing), (1, 1));
+        assert_eq!(second.bytes_written, png.len() as u64);
+        assert_eq!(fs::read(out.path().join("def.png")).unwrap(), b"stale");
+        assert_eq!(fs::read(out.path().join("def_1.png")).unwrap(), png);
+        assert!(fs::read_dir(out.path()).unwrap().all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".resume")));
+
+        // 覆盖时重新写入所有文件
+        let overwrite = ExportOptions {
+            overwrite: true,
+            write_manifest: true,
+            ..export_options()
+        };
+        let third = export(overwrite);
+        assert_eq!((third.succeeded, third.skipped_existing), (2, 0));
+        assert_eq!(fs::read(out.path().join("def.png")).unwrap(), png);
+
+        // 有清单时按清单跳过，不再解密源文件
+        write_file(dir.path(), "abc.dat", b"no longer decryptable");
+        let resumed = ExportOptions {
+            write_manifest: true,
+            ..export_options()
+        };
+        let fourth = export(resumed);
+        assert_eq!((fourth.succeeded, fourth.failed), (0, 0));
+        assert_eq!((fourth.skipped_existing, fourth.bytes_written), (2, 0));
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.path().join(EXPORT_MANIFEST)).unwrap())
+                .unwrap();
+        let outputs: Vec<&str> = manifest
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["output"].as_str().unwrap())
+            .collect();
+        assert_eq!(outputs, vec!["abc.png", "def.png"]);
+    }
+
+    #[test]
+    fn test_export_folder_stops_when_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        write_file(dir.path(), "abc.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let summary = runtime
+            .block_on(export_folder_files(
+                &state,
+                dir.path(),
+                &dir.path().to_string_lossy(),
+                out.path(),
+                &export_options(),
+                &AtomicBool::new(true),
+                &mut |_| {},
+            ))
+            .unwrap();
+
+        assert!(summary.cancelled);
+        assert_eq!((summary.succeeded, summary.failed), (0, 0));
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 0);
+    }
+
+    // 根目录 root 和同级的 outside 目录，outside 中有一张能用 0x56 解密的图片
+    fn root_with_sibling() -> (tempfile::TempDir, PathBuf, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let png = encode_test_image(image::ImageFormat::Png, 4, 4);
+        write_file(&outside, "abc.dat", &V3Decryptor::xor_decrypt(&png, 0x56));
+
+        let escaped = root
+            .join("..")
+            .join("outside")
+            .to_string_lossy()
+            .to_string();
+        (dir, root, escaped)
+    }
+
+    #[test]
+    fn test_resolve_folder_in_root_rejects_escapes() {
+        let (dir, root, escaped) = root_with_sibling();
+        let path = |p: &Path| p.to_string_lossy().to_string();
+
+        assert_eq!(
+            resolve_folder_in_root(&root, &path(&root.join("sub"))).unwrap(),
+            root.join("sub")
+        );
+        assert!(resolve_folder_in_root(&root, &path(&root)).is_ok());
+
+        // 按路径组件比较时 "<root>/../outside" 以根目录开头，必须额外拒绝
+        assert!(Path::new(&escaped).starts_with(&root));
+        assert!(matches!(
+            resolve_folder_in_root(&root, &escaped),
+            Err(AppError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            resolve_folder_in_root(&root, &path(&root.join("sub").join(".."))),
+            Err(AppError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            resolve_folder_in_root(&root, &path(&dir.path().join("outside"))),
+            Err(AppError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            resolve_folder_in_root(&root, &path(&root.join("missing"))),
+            Err(AppError::FileNotFound(_))
+        ));
+
+        #[cfg(unix)]
+        {
+            let link = root.join("link");
+            std::os::unix::fs::symlink(dir.path().join("outside"), &link).unwrap();
+            assert!(matches!(
+                resolve_folder_in_root(&root, &path(&link)),
+                Err(AppError::InvalidPath(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_get_images_batch_rejects_parent_dir() {
+        let (_dir, root, escaped) = root_with_sibling();
+        write_file(&root, "inside.dat", b"x");
+
+        let state = AppState::default();
+        *state.root_dir.lock().unwrap() = Some(root.clone());
+        state.headless.store(true, Ordering::Relaxed);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let batch = |folder: &str| {
+            runtime.block_on(folder_images_batch(
+                &state, folder, 0, 10, "name", "asc", false, true,
+            ))
+        };
+        assert!(matches!(batch(&escaped), Err(AppError::InvalidPath(_))));
+
+        let inside = batch(&root.to_string_lossy()).unwrap();
+        assert_eq!(inside.images.len(), 1);
+        assert_eq!(inside.images[0].path, "inside.dat");
+    }
+
+    #[test]
+    fn test_export_folder_rejects_parent_dir() {
+        let (_dir, root, escaped) = root_with_sibling();
+        let out = tempfile::tempdir().unwrap();
+
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(export_folder_files(
+            &state,
+            &root,
+            &escaped,
+            out.path(),
+            &export_options(),
+            &AtomicBool::new(false),
+            &mut |_| {},
+        ));
+        assert!(matches!(result, Err(AppError::InvalidPath(_))));
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_grid_metadata_reads_dimensions_from_header() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // 只保留 PNG 的前一半，完整解码会失败，但能从文件头读出尺寸
+        let png = encode_test_image(image::ImageFormat::Png, 320, 200);
+        let truncated = &png[..png.len() / 2];
+        assert!(image::load_from_memory(truncated).is_err());
+        write_file(
+            dir.path(),
+            "a.dat",
+            &V3Decryptor::xor_decrypt(truncated, 0x56),
+        );
+        write_file(dir.path(), "b.dat", &[0x11; 40]);
+
+        let mut images = collect_images(dir.path(), dir.path(), false, false);
+        sort_images(&mut images, "name", "asc");
+        let items: Vec<GridItem> = images
+            .into_iter()
+            .map(|info| {
+                let path = dir.path().join(&info.path);
+                grid_item(info, &path, XorKey::exact(0x56), None)
+            })
+            .collect();
+
+        assert_eq!(items[0].image_id, "a.dat");
+        assert_eq!(items[0].kind, "image");
+        assert_eq!(items[0].mime_type.as_deref(), Some("image/png"));
+        assert_eq!((items[0].width, items[0].height), (Some(320), Some(200)));
+
+        assert_eq!(items[1].kind, "unknown");
+        assert_eq!((items[1].mime_type.as_ref(), items[1].width), (None, None));
+    }
+
+    #[test]
+    fn test_sniff_bmp_tiff_heic() {
+        let bmp = b"BM\x36\x00\x00\x00\x00\x00\x00\x00\x36\x00";
+        assert_eq!(sniff_image_mime(bmp), Some("image/bmp"));
+        assert_eq!(extension_for_mime(bmp, "image/bmp"), "bmp");
+        // 保留字段不为 0 时不按 BMP 识别
+        assert_eq!(
+            sniff_image_mime(b"BM\x36\x00\x00\x00\x01\x02\x03\x04"),
+            None
+        );
+
+        assert_eq!(
+            sniff_image_mime(b"II*\x00\x08\x00\x00\x00"),
+            Some("image/tiff")
+        );
+        assert_eq!(
+            sniff_image_mime(b"MM\x00*\x00\x00\x00\x08"),
+            Some("image/tiff")
+        );
+        assert_eq!(extension_for_mime(b"II*\x00", "image/tiff"), "tif");
+
+        for heic in [
+            &b"\x00\x00\x00\x18ftypheic"[..],
+            b"\x00\x00\x00\x18ftypheix",
+        ] {
+            assert_eq!(sniff_image_mime(heic), Some("image/heic"));
+            assert_eq!(media_kind(heic), "image");
+            assert_eq!(extension_for_mime(heic, "image/heic"), "heic");
+        }
+        // 其他 ftyp 品牌仍按视频处理
+        assert_eq!(sniff_image_mime(b"\x00\x00\x00\x18ftypmp42"), None);
+        assert_eq!(media_kind(b"\x00\x00\x00\x18ftypmp42"), "video");
+    }
+
+    #[test]
+    fn test_unknown_mime_is_not_labelled_jpeg() {
+        assert_eq!(
+            detect_mime_with_overrides(&[], &[0x01, 0x02, 0x03, 0x04, 0x05]),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_mime_override_takes_precedence() {
+        let overrides = parse_mime_overrides(&[
+            MimeOverrideConfig {
+                magic: "FF D8".to_string(),
+                mime: "image/x-custom".to_string(),
+            },
+            MimeOverrideConfig {
+                magic: "ffd8ffe1".to_string(),
+                mime: "image/x-exif".to_string(),
+            },
+            MimeOverrideConfig {
+                magic: "02 23 21 53 49 4C 4B".to_string(),
+                mime: "audio/silk".to_string(),
+            },
+        ])
+        .unwrap();
+
+        assert_eq!(detect_mime_with_overrides(&[], &JPEG_HEADER), "image/jpeg");
+        assert_eq!(
+            detect_mime_with_overrides(&overrides, &JPEG_HEADER),
+            "image/x-custom"
+        );
+        // 最长的前缀优先
+        assert_eq!(
+            detect_mime_with_overrides(&overrides, &[0xFF, 0xD8, 0xFF, 0xE1, 0]),
+            "image/x-exif"
+        );
+        assert_eq!(
+            detect_mime_with_overrides(&overrides, b"\x02#!SILK_V3"),
+            "audio/silk"
+        );
+        // 未匹配时使用内置规则
+        assert_eq!(
+            detect_mime_with_overrides(&overrides, b"GIF89a"),
+            "image/gif"
+        );
+
+        assert!(parse_mime_overrides(&[MimeOverrideConfig {
+            magic: "xyz".to_string(),
+            mime: "a/b".to_string(),
+        }])
+        .is_err());
+    }
+
+    #[test]
+    fn test_gallery_manifest_structure() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("gallery");
+        let sources = [
+            (
+                "a.dat",
+                encode_test_image(image::ImageFormat::Png, 600, 300),
+            ),
+            ("b.dat", encode_test_image(image::ImageFormat::Jpeg, 40, 80)),
+        ];
+
+        let mut manifest = GalleryManifest {
+            items: Vec::new(),
+            skipped: 0,
+        };
+        for (name, data) in &sources {
+            let stem = name.trim_end_matches(DAT_EXTENSION);
+            manifest
+                .items
+                .push(write_gallery_item(data, name, stem, &dest, None).unwrap());
+        }
+        assert!(write_gallery_item(b"not an image", "c.dat", "c", &dest, None).is_err());
+        write_gallery_manifest(&manifest, &dest).unwrap();
+
+        let content = fs::read_to_string(dest.join(GALLERY_MANIFEST)).unwrap();
+        let reloaded: GalleryManifest = serde_json::from_str(&content).unwrap();
+        assert_eq!(reloaded.items.len(), 2);
+
+        let a = &reloaded.items[0];
+        assert_eq!(a.source, "a.dat");
+        assert_eq!((a.width, a.height), (600, 300));
+        assert_eq!(a.full, "images/a.webp");
+        assert_eq!(a.thumbnail, "thumbs/a.webp");
+        let thumb = fs::read(dest.join(&a.thumbnail)).unwrap();
+        assert_eq!(sniff_image_mime(&thumb), Some("image/webp"));
+        assert_eq!(image_dimensions(&thumb), Some((256, 128)));
+
+        for item in &reloaded.items {
+            assert!(dest.join(&item.full).exists());
+            assert!(dest.join(&item.thumbnail).exists());
+        }
+    }
+
+    #[test]
+    fn test_weak_etag_tracks_size_and_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "a.dat", b"abcd");
+        set_mtime(&path, 1_000);
+        let etag = file_weak_etag(&path).unwrap();
+        assert_eq!(etag, "W/\"4-1000\"");
+
+        // 只改内容、大小和修改时间不变时 ETag 不变
+        fs::write(&path, b"wxyz").unwrap();
+        set_mtime(&path, 1_000);
+        assert_eq!(file_weak_etag(&path).unwrap(), etag);
+
+        set_mtime(&path, 2_000);
+        assert_ne!(file_weak_etag(&path).unwrap(), etag);
+
+        fs::write(&path, b"abcde").unwrap();
+        set_mtime(&path, 1_000);
+        assert_ne!(file_weak_etag(&path).unwrap(), etag);
+
+        assert_eq!(EtagMode::parse("Weak"), Some(EtagMode::Weak));
+        assert_eq!(EtagMode::parse("hash"), None);
+    }
+
+    #[test]
+    fn test_normalize_tiny_input_does_not_panic() {
+        let (data, mime) = normalize_decrypted_image(vec![0xFF, 0xD8]);
+        assert_eq!(data, vec![0xFF, 0xD8]);
+        assert_eq!(mime, "application/octet-stream");
+
+        let (data, _) = normalize_decrypted_image(Vec::new());
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_wxgf_fallback_to_gif() {
+        let tried = std::cell::RefCell::new(Vec::new());
+        let result = convert_wxgf_with_fallback(b"wxgf", "jpeg", |_, format| {
+            tried.borrow_mut().push(format.to_string());
+            match format {
+                "gif" => Ok(b"GIF89a".to_vec()),
+                _ => Err(AppError::DllDecodeFailed(-1)),
+            }
+        });
+
+        assert_eq!(result.unwrap(), b"GIF89a".to_vec());
+        assert_eq!(*tried.borrow(), vec!["jpeg", "gif"]);
+    }
+
+    #[test]
+    fn test_wxgf_png_falls_back_to_jpeg() {
+        let tried = std::cell::RefCell::new(Vec::new());
+        let result = convert_wxgf_with_fallback(b"wxgf", "png", |_, format| {
+            tried.borrow_mut().push(format.to_string());
+            match format {
+                "jpeg" => Ok(b"JPEG".to_vec()),
+                _ => Err(AppError::DllDecodeFailed(-1)),
+            }
+        });
+
+        assert_eq!(result.unwrap(), b"JPEG".to_vec());
+        assert_eq!(*tried.borrow(), vec!["png", "jpeg"]);
+    }
+
+    #[test]
+    fn test_wxgf_fallback_all_fail() {
+        let result = convert_wxgf_with_fallback(b"wxgf", "gif", |_, format| match format {
+            "gif" => Err(AppError::DllDecodeFailed(-3)),
+            _ => Err(AppError::DllDecodeFailed(-1)),
+        });
+        assert!(matches!(result, Err(AppError::DllDecodeFailed(-3))));
+    }
+
+    #[test]
+    fn test_wxgf_preferred_format_keeps_animation() {
+        use crate::dll::wxgf_header::tests::{build_sps, build_wxgf};
+
+        let sps = build_sps(240, 160, 0);
+        assert_eq!(wxgf_preferred_format(&build_wxgf(&sps, 1)), "jpeg");
+        assert_eq!(wxgf_preferred_format(&build_wxgf(&sps, 3)), "gif");
+        assert_eq!(wxgf_preferred_format(b"not a wxgf file"), "jpeg");
+
+        let mut with_alpha = build_wxgf(&sps, 1);
+        with_alpha.extend_from_slice(&build_wxgf(&build_sps(240, 160, 2), 1)[4..]);
+        assert_eq!(wxgf_preferred_format(&with_alpha), "png");
+    }
+
+    #[test]
+    fn test_convert_wxgf_on_pool_without_dll() {
+        let png = encode_test_image(image::ImageFormat::Png, 4, 3);
+        let mut wrapped = b"wxgf\x00\x00".to_vec();
+        wrapped.extend_from_slice(&png);
+
+        let converted = convert_wxgf_on_pool(wrapped, &dll::backend::PayloadBackend).unwrap();
+        assert_eq!(detect_mime_type(&converted), "image/jpeg");
+
+        // 纯 HEVC 码流无法转换时交还原始数据
+        let hevc = b"wxgf\x00\x00\x00\x01\x40\x01\x0c".to_vec();
+        let (data, err) =
+            convert_wxgf_on_pool(hevc.clone(), &dll::backend::PayloadBackend).unwrap_err();
+        assert_eq!(data, hevc);
+        assert!(matches!(err, AppError::UnsupportedImageFormat(_)));
+    }
+
+    #[test]
+    fn test_folder_signature_tracks_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "a.dat", b"aaaa");
+
+        let first = compute_folder_signature(dir.path()).unwrap();
+        assert_eq!(first, compute_folder_signature(dir.path()).unwrap());
+
+        let added = write_file(dir.path(), "b.dat", b"bbbb");
+        let after_add = compute_folder_signature(dir.path()).unwrap();
+        assert_ne!(first, after_add);
+
+        let file = fs::OpenOptions::new().write(true).open(&added).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        let after_touch = compute_folder_signature(dir.path()).unwrap();
+        assert_ne!(after_add, after_touch);
+        assert_eq!(after_touch, compute_folder_signature(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_force_decrypt_configured_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "a.dat",
+            &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x56),
+        );
+
+        let forced = try_force_decrypt(&path, 0x56, None, &[]).unwrap();
+        assert_eq!(forced.strategy, ForceStrategy::ConfiguredKeys);
+        assert_eq!(forced.xor_key, Some(0x56));
+        assert_eq!(forced.data, JPEG_HEADER);
+    }
+
+    #[test]
+    fn test_force_decrypt_xor_bruteforce() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "a.dat",
+            &V3Decryptor::xor_decrypt(&JPEG_HEADER, 0x56),
+        );
+
+        let forced = try_force_decrypt(&path, 0x11, None, &[]).unwrap();
+        assert_eq!(forced.strategy, ForceStrategy::XorBruteforce);
+        assert_eq!(forced.xor_key, Some(0x56));
+        assert_eq!(forced.data, JPEG_HEADER);
+    }
+
+    #[test]
+    fn test_force_decrypt_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "a.dat", &PNG_HEADER);
+
+        let forced = try_force_decrypt(&path, 0x56, None, &[]).unwrap();
+        assert_eq!(forced.strategy, ForceStrategy::Plaintext);
+        assert_eq!(forced.xor_key, None);
+        assert_eq!(forced.data, PNG_HEADER);
+    }
+
+    #[test]
+    fn test_force_decrypt_key_table() {
+        use crate::decrypt::tests::{build_v4_fixture, TEST_AES_KEY};
+        use crate::decrypt::VersionDetector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = encode_test_image(image::ImageFormat::Jpeg, 8, 8);
+        let path = write_file(
+            dir.path(),
+            "a.dat",
+            &build_v4_fixture(VersionDetector::V4_V1_SIGNATURE, &jpeg, 64, 16, 0x56),
+        );
+
+        // 全局密钥错误，文件夹覆盖密钥可以解密
+        let state = AppState::default();
+        *state.xor_key.lock().unwrap() = 0x56;
+        *state.aes_key.lock().unwrap() = b"fedcba9876543210".to_vec();
+        state.folder_keys.lock().unwrap().insert(
+            PathBuf::from("/other/account"),
+            (0x56, TEST_AES_KEY.to_vec()),
+        );
+        state
+            .folder_keys
+            .lock()
+            .unwrap()
+            .insert(PathBuf::from("/short"), (0x56, b"short".to_vec()));
+
+        let key_table = known_key_candidates(&state);
+        assert_eq!(key_table.len(), 2);
+
+        let forced = try_force_decrypt(&path, 0x56, Some(b"fedcba9876543210"), &key_table).unwrap();
+        assert_eq!(forced.strategy, ForceStrategy::KeyTable);
+        assert_eq!(forced.xor_key, Some(0x56));
+        assert_eq!(forced.key_source.as_deref(), Some("/other/account"));
+        assert_eq!(forced.data, jpeg);
+
+        // 没有可用的已知密钥时放弃
+        assert!(
+            try_force_decrypt(&path, 0x56, Some(b"fedcba9876543210"), &key_table[..1]).is_none()
+        );
+    }
+
+    #[test]
+    fn test_force_decrypt_gives_up_on_noise() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "a.dat", &[0x01, 0x02, 0x03, 0x04, 0x05]);
+
+        assert!(try_force_decrypt(&path, 0x56, None, &[]).is_none());
+    }
 }
@@ -0,0 +1,132 @@
+//! 写入限速模块
+//!
+//! 令牌桶限速器，限制导出时每秒写入磁盘的字节数，避免后台导出占满磁盘带宽、拖慢界面。
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 每次写入的最大块大小，限速时按块等待，写入速度更平稳
+const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 令牌桶限速器，可在多个线程间共享
+///
+/// 桶容量为一秒的配额，初始为空。申请的字节数超过剩余令牌时记为欠额，
+/// 调用方等待欠额按速率补足后再返回。
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    /// (剩余令牌, 上次补充时间)，令牌可以为负表示欠额
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self::starting_at(bytes_per_sec, Instant::now())
+    }
+
+    /// 以 `now` 作为初始时间创建，测试时可以使用构造的时间点
+    fn starting_at(bytes_per_sec: u64, now: Instant) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            state: Mutex::new((0.0, now)),
+        }
+    }
+
+    /// 申请写入 `bytes` 字节，配额不足时阻塞等待
+    pub fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// 在 `now` 时刻申请 `bytes` 字节，返回需要等待的时间
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let rate = self.bytes_per_sec as f64;
+        let mut state = self.state.lock().unwrap();
+        let refilled = state.0 + now.saturating_duration_since(state.1).as_secs_f64() * rate;
+        state.0 = refilled.min(rate) - bytes as f64;
+        state.1 = state.1.max(now);
+        if state.0 < 0.0 {
+            Duration::from_secs_f64(-state.0 / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// 限速写入的包装：每次最多写入一块，写入前先申请配额
+pub struct ThrottledWriter<'a, W> {
+    inner: W,
+    limiter: &'a RateLimiter,
+}
+
+impl<'a, W: Write> ThrottledWriter<'a, W> {
+    pub fn new(inner: W, limiter: &'a RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(WRITE_CHUNK_SIZE);
+        self.limiter.acquire(len);
+        self.inner.write_all(&buf[..len])?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 写入文件，提供限速器时按块申请配额
+pub fn write_file(path: &Path, data: &[u8], limiter: Option<&RateLimiter>) -> std::io::Result<()> {
+    let Some(limiter) = limiter else {
+        return std::fs::write(path, data);
+    };
+
+    let mut writer = ThrottledWriter::new(File::create(path)?, limiter);
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_wait_matches_rate() {
+        let start = Instant::now();
+        let limiter = RateLimiter::starting_at(800_000, start);
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let close = |wait: Duration, secs: f64| (wait.as_secs_f64() - secs).abs() < 1e-6;
+
+        // 桶初始为空，400 KB 以 800 KB/s 写入需要等待 0.5 秒
+        assert!(close(limiter.reserve(400_000, at(0.0)), 0.5));
+        // 0.5 秒后补足的令牌刚好还清欠额，下一块同样等待 0.5 秒
+        assert!(close(limiter.reserve(400_000, at(0.5)), 0.5));
+        // 长时间空闲后最多积累一秒的配额
+        assert!(limiter.reserve(800_000, at(10.0)).is_zero());
+        assert!(close(limiter.reserve(400_000, at(10.0)), 0.5));
+    }
+
+    #[test]
+    fn test_throttle_limits_write_rate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let data: Vec<u8> = (0..400_000u32).map(|i| i as u8).collect();
+
+        // 400 KB 以 800 KB/s 写入至少需要 0.5 秒；只检查下限，机器繁忙时可能更慢
+        let limiter = RateLimiter::new(800_000);
+        let start = Instant::now();
+        write_file(&path, &data, Some(&limiter)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(450));
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        write_file(&path, &data, None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+    }
+}
@@ -0,0 +1,102 @@
+//! 视频帧提取模块
+//!
+//! 调用 ffmpeg 可执行文件从解密后的视频中截取单帧，需要启用 `ffmpeg` feature。
+
+use crate::error::AppError;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// ffmpeg 可执行文件名
+#[cfg(windows)]
+const FFMPEG_NAME: &str = "ffmpeg.exe";
+#[cfg(not(windows))]
+const FFMPEG_NAME: &str = "ffmpeg";
+
+/// 查找 ffmpeg: 优先使用与程序同目录的 ffmpeg，其次使用 PATH 中的 ffmpeg
+pub fn find_ffmpeg() -> Option<PathBuf> {
+    let bundled = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.join(FFMPEG_NAME)));
+    if let Some(path) = bundled.filter(|p| p.exists()) {
+        return Some(path);
+    }
+
+    let available = Command::new(FFMPEG_NAME)
+        .arg("-version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+    available.then(|| PathBuf::from(FFMPEG_NAME))
+}
+
+/// 从视频数据中截取指定时间点的一帧，返回 JPEG 数据
+///
+/// 视频先写入临时文件再交给 ffmpeg，便于 ffmpeg 按时间定位。
+pub fn extract_frame(video: &[u8], timestamp_secs: f64) -> Result<Vec<u8>, AppError> {
+    let ffmpeg = find_ffmpeg().ok_or_else(|| {
+        AppError::Internal("未找到 ffmpeg，请将 ffmpeg 放到程序目录或加入 PATH".to_string())
+    })?;
+
+    let mut input =
+        tempfile::NamedTempFile::new().map_err(|e| AppError::FileWriteError(e.to_string()))?;
+    input
+        .write_all(video)
+        .and_then(|_| input.flush())
+        .map_err(|e| AppError::FileWriteError(e.to_string()))?;
+
+    let output = Command::new(ffmpeg)
+        .args(["-v", "error", "-ss"])
+        .arg(format!("{:.3}", timestamp_secs.max(0.0)))
+        .arg("-i")
+        .arg(input.path())
+        .args([
+            "-frames:v",
+            "1",
+            "-f",
+            "image2pipe",
+            "-vcodec",
+            "mjpeg",
+            "-",
+        ])
+        .output()
+        .map_err(|e| AppError::Internal(format!("ffmpeg 启动失败: {}", e)))?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(AppError::Internal(format!(
+            "ffmpeg 截取视频帧失败: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "需要程序目录或 PATH 中的 ffmpeg"]
+    fn test_extract_frame_from_synthetic_video() {
+        let ffmpeg = find_ffmpeg().expect("未找到 ffmpeg");
+
+        let dir = tempfile::tempdir().unwrap();
+        let video_path = dir.path().join("test.mp4");
+        let status = Command::new(ffmpeg)
+            .args(["-v", "error", "-f", "lavfi", "-i"])
+            .arg("testsrc=duration=2:size=64x48:rate=10")
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(&video_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let video = std::fs::read(&video_path).unwrap();
+        let frame = extract_frame(&video, 1.0).unwrap();
+        assert_eq!(&frame[..3], &[0xFF, 0xD8, 0xFF]);
+
+        let decoded = image::load_from_memory(&frame).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 48));
+    }
+}
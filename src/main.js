@@ -212,6 +212,24 @@ async function startImageLoading(folderPath) {
     }
 }
 
+// 图片协议地址，Windows 上自定义协议通过 http://<scheme>.localhost 访问
+function imageProtocolUrl(imageId) {
+    const encoded = encodeURIComponent(imageId);
+    return navigator.userAgent.includes('Windows')
+        ? `http://wxdat.localhost/${encoded}`
+        : `wxdat://localhost/${encoded}`;
+}
+
+// 通过 wxdat 协议获取图片，直接传输二进制数据，失败时返回 null
+async function fetchImageViaProtocol(imageId) {
+    try {
+        const response = await fetch(imageProtocolUrl(imageId));
+        return response.ok ? await response.blob() : null;
+    } catch {
+        return null;
+    }
+}
+
 // 加载图片的 Blob URL
 async function loadImageBlob(imageId, mimeType = 'image/jpeg') {
     // 检查缓存
@@ -220,7 +238,15 @@ async function loadImageBlob(imageId, mimeType = 'image/jpeg') {
     }
 
     try {
-        // 从后端获取图片二进制数据
+        // 优先使用图片协议，避免经 IPC 复制整张图片
+        const protocolBlob = await fetchImageViaProtocol(imageId);
+        if (protocolBlob) {
+            const blobUrl = URL.createObjectURL(protocolBlob);
+            imageDataCache.set(imageId, blobUrl);
+            return blobUrl;
+        }
+
+        // 回退：从后端获取图片二进制数据
         const imageData = await invoke('get_image_data', { imageId });
         const bytes = imageData?.data ?? [];
         const resolvedMime = imageData?.mime_type || mimeType || 'image/jpeg';
@@ -358,7 +384,8 @@ async function loadMoreImages(folderPath) {
             pageSize: PAGE_SIZE,
             sortBy,
             sortOrder,
-            hideThumbnails
+            hideThumbnails,
+            recursive: false
         });
 
         totalImages = batch.total;
@@ -416,6 +443,7 @@ function addImageToGallery(imageData) {
     const caption = document.createElement('div');
     caption.className = 'caption';
     caption.textContent = imageData.name;
+    caption.title = `${imageData.name} (${imageData.version})`;
 
     card.appendChild(img);
     card.appendChild(caption);
@@ -443,6 +471,7 @@ function addImageToColumn(column, imageData) {
     const caption = document.createElement('div');
     caption.className = 'caption';
     caption.textContent = imageData.name;
+    caption.title = `${imageData.name} (${imageData.version})`;
 
     card.appendChild(img);
     card.appendChild(caption);